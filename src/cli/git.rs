@@ -0,0 +1,92 @@
+use std::path::Path;
+use std::process::{Command as Process, Stdio};
+
+use anyhow::{bail, Result};
+use log::debug;
+
+/** Whether the store is kept in git, so changes to it should be committed like `pass` does.
+*/
+pub fn is_repo(store_path: &Path) -> bool {
+    store_path.join(".git").exists()
+}
+
+/** Commit whatever changed at `paths`, added, modified or removed. Nothing is committed when
+ * they're unchanged, as when a re-encryption had nothing to do.
+*/
+pub fn commit(store_path: &Path, paths: &[&Path], message: &str) -> Result<()> {
+    // Git rejects paths it has never seen, such as an untracked entry that's been removed.
+    let paths: Vec<&Path> = paths
+        .iter()
+        .copied()
+        .filter(|path| path.exists() || is_tracked(store_path, path))
+        .collect();
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let status = git(store_path)
+        .args(["add", "--all", "--"])
+        .args(&paths)
+        .stdout(Stdio::null())
+        .status()?;
+    if !status.success() {
+        bail!("git add exited with {}.", status);
+    }
+
+    let staged = git(store_path)
+        .args(["diff", "--cached", "--quiet", "--"])
+        .args(&paths)
+        .status()?;
+    if staged.success() {
+        debug!("Nothing to commit for '{}'", message);
+        return Ok(());
+    }
+
+    let status = git(store_path)
+        .args(["commit", "--quiet", "--message", message, "--"])
+        .args(&paths)
+        .status()?;
+    if !status.success() {
+        bail!("git commit exited with {}.", status);
+    }
+    Ok(())
+}
+
+/** Run git on the store with the given arguments, as `pass git` does. A `git init` also commits
+ * what's already in the store.
+*/
+pub fn run(store_path: &Path, args: &[String]) -> Result<()> {
+    let init = args.first().map(String::as_str) == Some("init");
+    if !init && !is_repo(store_path) {
+        bail!("the password store is not a git repository. Try \"passrs git init\".");
+    }
+
+    let status = git(store_path).args(args).status()?;
+    if !status.success() {
+        bail!("git exited with {}.", status);
+    }
+    if init {
+        commit(
+            store_path,
+            &[store_path],
+            "Add current contents of password store.",
+        )?;
+    }
+    Ok(())
+}
+
+fn is_tracked(store_path: &Path, path: &Path) -> bool {
+    git(store_path)
+        .args(["ls-files", "--error-unmatch", "--"])
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn git(store_path: &Path) -> Process {
+    let mut git = Process::new("git");
+    git.arg("-C").arg(store_path);
+    git
+}
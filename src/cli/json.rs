@@ -0,0 +1,238 @@
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+
+use passrs::entry::PassEntry;
+use passrs::errors::Error;
+use passrs::pass_scanner::StoreEntry;
+use passrs::SignatureStatus;
+
+/** Version of the JSON output. It's bumped when a field is removed or changes meaning; fields
+ * may be added without a bump, so consumers should ignore any they don't know.
+*/
+pub const SCHEMA_VERSION: u32 = 1;
+
+/** A complete JSON document: the schema version alongside the command's output.
+*/
+#[derive(Debug, Serialize)]
+pub struct Document<T> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub body: T,
+}
+
+impl<T: Serialize> Document<T> {
+    pub fn new(body: T) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            body,
+        }
+    }
+}
+
+/** An entry of the store, named as `pass` names it.
+*/
+#[derive(Debug, Serialize)]
+pub struct EntryInfo {
+    pub name: String,
+    pub path: PathBuf,
+    /// Last modification of the entry's file, in seconds since the Unix epoch.
+    pub modified: Option<u64>,
+}
+
+impl From<&StoreEntry> for EntryInfo {
+    fn from(entry: &StoreEntry) -> Self {
+        Self {
+            name: entry.name.clone(),
+            path: entry.path.clone(),
+            modified: entry
+                .modified
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|since_epoch| since_epoch.as_secs()),
+        }
+    }
+}
+
+/// Output of `ls` on a folder.
+#[derive(Debug, Serialize)]
+pub struct Listing {
+    /// The folder listed, empty for the whole store.
+    pub folder: String,
+    pub entries: Vec<EntryInfo>,
+}
+
+/// Output of `find`.
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub terms: Vec<String>,
+    pub fuzzy: bool,
+    /// Best match first when fuzzy, otherwise in name order.
+    pub matches: Vec<SearchMatch>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchMatch {
+    #[serde(flatten)]
+    pub entry: EntryInfo,
+    /// Fuzzy match score, higher is better; null for plain substring matches.
+    pub score: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Field {
+    pub name: String,
+    pub value: String,
+}
+
+/// How an entry's signature checked out.
+#[derive(Debug, Serialize)]
+pub struct Signature {
+    /// One of `unsigned`, `good`, `bad` or `unknown_signer`.
+    pub status: &'static str,
+    pub fingerprint: Option<String>,
+    pub signer: Option<String>,
+}
+
+impl From<&SignatureStatus> for Signature {
+    fn from(status: &SignatureStatus) -> Self {
+        let (status, fingerprint, signer) = match status {
+            SignatureStatus::Unsigned => ("unsigned", None, None),
+            SignatureStatus::Good {
+                fingerprint,
+                signer,
+            } => ("good", Some(fingerprint), Some(signer)),
+            SignatureStatus::Bad {
+                fingerprint,
+                signer,
+            } => ("bad", Some(fingerprint), Some(signer)),
+            SignatureStatus::UnknownSigner { fingerprint } => {
+                ("unknown_signer", Some(fingerprint), None)
+            }
+        };
+        Self {
+            status,
+            fingerprint: fingerprint.cloned(),
+            signer: signer.cloned(),
+        }
+    }
+}
+
+/// Output of `show` on an entry.
+#[derive(Debug, Serialize)]
+pub struct Shown {
+    pub entry: EntryInfo,
+    pub password: String,
+    /// `key: value` lines, in the order they appear.
+    pub fields: Vec<Field>,
+    pub notes: String,
+    pub otpauth: Vec<String>,
+    pub signature: Signature,
+    /// Key IDs the entry is encrypted to.
+    pub recipients: Vec<String>,
+}
+
+impl Shown {
+    pub fn new(
+        entry: EntryInfo,
+        pass_entry: &PassEntry,
+        signature: &SignatureStatus,
+        recipients: Vec<String>,
+    ) -> Self {
+        Self {
+            entry,
+            password: pass_entry.password.clone(),
+            fields: pass_entry
+                .fields
+                .iter()
+                .map(|(name, value)| Field {
+                    name: name.clone(),
+                    value: value.clone(),
+                })
+                .collect(),
+            notes: pass_entry.notes.clone(),
+            otpauth: pass_entry.otpauth.clone(),
+            signature: signature.into(),
+            recipients,
+        }
+    }
+}
+
+/// Output of `show --field`.
+#[derive(Debug, Serialize)]
+pub struct ShownField {
+    pub entry: EntryInfo,
+    pub field: Field,
+}
+
+/// Output of `show --clip`, in place of the secret.
+#[derive(Debug, Serialize)]
+pub struct Copied {
+    pub entry: EntryInfo,
+    /// What was copied: `line N` or `field NAME`.
+    pub copied: String,
+    /// Seconds until the clipboard is restored.
+    pub clear_after: u64,
+}
+
+/** Output of a failed command.
+*/
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub error: ErrorInfo,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorInfo {
+    /// Stable identifier of the kind of error, see `Error::code`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(err: &anyhow::Error) -> Self {
+        let code = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Error>())
+            .map(Error::code)
+            .unwrap_or("general_error");
+        Self {
+            error: ErrorInfo {
+                code,
+                message: format!("{:#}", err),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_codes() -> Result<(), serde_json::Error> {
+        let err = anyhow::Error::from(Error::NotInStore {
+            name: "web/github".to_string(),
+        });
+        assert_eq!(
+            serde_json::to_string(&Document::new(Failure::new(&err)))?,
+            concat!(
+                r#"{"schema_version":1,"error":{"code":"not_in_store","#,
+                r#""message":"web/github is not in the password store."}}"#
+            )
+        );
+
+        let err = anyhow::Error::from(Error::BadPassphrase {
+            key_id: "f711232219df6593".to_string(),
+        })
+        .context("Unlocking");
+        assert_eq!(Failure::new(&err).error.code, "bad_passphrase");
+        assert_eq!(
+            Failure::new(&anyhow::anyhow!("Password unchanged."))
+                .error
+                .code,
+            "general_error"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,1644 @@
+mod git;
+mod json;
+mod tree;
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command as Process;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use clap::{Args, Parser, Subcommand};
+use log::{debug, error, info, warn};
+use zeroize::Zeroizing;
+
+use passrs::clipboard::{self, Clipboard, CopiedSecret};
+use passrs::entry::PassEntry;
+use passrs::errors::Error;
+use passrs::generator::{self, folder_policy, CharClass, PasswordPolicy};
+use passrs::otp::{self, OtpAuth};
+use passrs::pass_scanner::{self, EntryId, StoreEntry};
+use passrs::settings::Settings;
+use passrs::{DecryptOutcome, DecryptedMessage, Store, GPG_ID_FILE};
+
+/// Attempts at a key's passphrase before giving up, as gpg-agent allows.
+const PASSPHRASE_ATTEMPTS: usize = 3;
+
+/** Command line arguments. The subcommands and their flags follow `pass`, so scripts written for
+ * it work unchanged. Without a subcommand the GUI opens.
+*/
+#[derive(Debug, Parser)]
+#[command(name = "passrs", version, about = "A password manager for pass stores")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<PassCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PassCommand {
+    /// List the store, or a folder of it, or show an entry
+    #[command(visible_alias = "ls", alias = "list")]
+    Show {
+        /// Copy line N, the password by default, to the clipboard instead of printing
+        #[arg(
+            short,
+            long,
+            value_name = "LINE",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "1"
+        )]
+        clip: Option<usize>,
+        /// Print or copy the value of a `key: value` field instead of the whole entry
+        #[arg(long, value_name = "NAME")]
+        field: Option<String>,
+        /// Print JSON rather than text, errors included
+        #[arg(long)]
+        json: bool,
+        pass_name: Option<String>,
+    },
+    /// List entries whose names contain any of the terms
+    #[command(alias = "search")]
+    Find {
+        /// Rank entries by fuzzy match, as the GUI's search does, rather than matching substrings
+        #[arg(long)]
+        fuzzy: bool,
+        /// Print JSON rather than text, errors included
+        #[arg(long)]
+        json: bool,
+        #[arg(required = true)]
+        terms: Vec<String>,
+    },
+    /// Add a new entry, read from the terminal or stdin
+    #[command(alias = "add")]
+    Insert {
+        /// Show the password as it's typed, and only ask for it once
+        #[arg(short, long, conflicts_with = "multiline")]
+        echo: bool,
+        /// Read lines until end of file, instead of a single password
+        #[arg(short, long)]
+        multiline: bool,
+        /// Overwrite an existing entry without asking
+        #[arg(short, long)]
+        force: bool,
+        pass_name: String,
+    },
+    /// Generate a password, following the folder's .passrs-policy if it has one
+    Generate {
+        /// Letters and digits only
+        #[arg(short, long)]
+        no_symbols: bool,
+        /// Copy the password to the clipboard instead of printing it
+        #[arg(short, long)]
+        clip: bool,
+        /// Replace only the first line of an existing entry
+        #[arg(short, long, conflicts_with = "force")]
+        in_place: bool,
+        /// Overwrite an existing entry without asking
+        #[arg(short, long)]
+        force: bool,
+        pass_name: String,
+        pass_length: Option<usize>,
+    },
+    /// Edit an entry, or create it, in $EDITOR
+    Edit { pass_name: String },
+    /// Remove an entry, or a folder with --recursive
+    #[command(alias = "remove", alias = "delete")]
+    Rm {
+        #[arg(short, long)]
+        recursive: bool,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        force: bool,
+        pass_name: String,
+    },
+    /// Move or rename an entry or folder, re-encrypting it for its new location
+    #[command(alias = "rename")]
+    Mv(CopyMoveArgs),
+    /// Copy an entry or folder, re-encrypting it for its new location
+    #[command(alias = "copy")]
+    Cp(CopyMoveArgs),
+    /// Set the recipients of the store, or of a folder, re-encrypting its entries
+    Init {
+        /// Subfolder to set the recipients of
+        #[arg(short, long, value_name = "SUB-FOLDER")]
+        path: Option<String>,
+        /// Key IDs, fingerprints or emails; a single empty ID removes the folder's .gpg-id
+        #[arg(required = true)]
+        gpg_ids: Vec<String>,
+    },
+    /// Show the one-time code of an entry's first otpauth:// URI, like pass-otp
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Otp {
+        #[command(subcommand)]
+        action: Option<OtpAction>,
+        #[command(flatten)]
+        args: OtpArgs,
+    },
+    /// Run git in the store; `git init` also commits the store's current contents
+    Git {
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            required = true,
+            value_name = "GIT-ARGS"
+        )]
+        args: Vec<String>,
+    },
+    /// Clear the clipboard after a delay, left running in the background by --clip
+    #[command(hide = true)]
+    ClipRestore { timeout: u64 },
+    /// `passrs <pass-name>` is short for `passrs show <pass-name>`
+    #[command(external_subcommand)]
+    Implicit(Vec<String>),
+}
+
+#[derive(Debug, Args)]
+pub struct CopyMoveArgs {
+    /// Overwrite an existing entry without asking
+    #[arg(short, long)]
+    force: bool,
+    old_path: String,
+    new_path: String,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum OtpAction {
+    /// Show the current code, the default
+    Code(OtpArgs),
+    /// Show the otpauth:// URI itself
+    Uri(OtpArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct OtpArgs {
+    /// Copy to the clipboard instead of printing
+    #[arg(short, long)]
+    clip: bool,
+    #[arg(required = true)]
+    pass_name: Option<String>,
+}
+
+impl PassCommand {
+    /// Listing of the whole store, what `pass` does without a command.
+    #[cfg(not(feature = "gui"))]
+    pub fn list() -> Self {
+        PassCommand::Show {
+            clip: None,
+            field: None,
+            json: false,
+            pass_name: None,
+        }
+    }
+
+    /// Whether output, errors included, should be JSON.
+    pub fn json(&self) -> bool {
+        matches!(
+            self,
+            Self::Show { json: true, .. } | Self::Find { json: true, .. }
+        )
+    }
+}
+
+impl Cli {
+    /** Parse the process arguments, reading `passrs <pass-name> [flags]` as `show` like `pass`.
+     */
+    pub fn try_parse_args() -> Result<Self, clap::Error> {
+        Self::try_parse_from(pass_args(std::env::args()))
+    }
+}
+
+/** Whether `--json` was asked for, for reporting failures before the arguments are understood.
+*/
+pub fn json_requested(args: impl IntoIterator<Item = String>) -> bool {
+    args.into_iter()
+        .take_while(|arg| arg != "--")
+        .any(|arg| arg == "--json")
+}
+
+/** Print a failure to stdout as a JSON document when `json` is set, or as `pass` would
+ * otherwise.
+*/
+pub fn report_failure(err: &anyhow::Error, json: bool) {
+    if json {
+        let failure = json::Document::new(json::Failure::new(err));
+        match serde_json::to_string(&failure) {
+            Ok(failure) => println!("{}", failure),
+            Err(json_err) => eprintln!("Error: {:#} ({})", err, json_err),
+        }
+    } else {
+        eprintln!("Error: {:#}", err);
+    }
+}
+
+/** Rewrite arguments clap can't take as they're written for `pass`: `passrs <pass-name>` and
+ * `passrs -c <pass-name>` are `passrs show ...`, and `-c<line>` is `--clip=<line>`.
+*/
+fn pass_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut args: Vec<String> = args.into_iter().collect();
+    if let Ok(Cli {
+        command: Some(PassCommand::Implicit(show_args)),
+    }) = Cli::try_parse_from(&args)
+    {
+        args.truncate(1);
+        args.push("show".to_string());
+        args.extend(show_args);
+    } else if args.get(1).is_some_and(|arg| {
+        arg.starts_with('-') && !["-h", "--help", "-V", "--version"].contains(&arg.as_str())
+    }) {
+        // Options before any command are `show`'s, as `pass -c <pass-name>` copies.
+        args.insert(1, "show".to_string());
+    }
+
+    let mut options_done = false;
+    for arg in args.iter_mut().skip(1) {
+        options_done |= arg == "--";
+        let line = arg.strip_prefix("-c").filter(|line| {
+            !options_done && !line.is_empty() && line.chars().all(|c| c.is_ascii_digit())
+        });
+        if let Some(line) = line {
+            *arg = format!("--clip={}", line);
+        }
+    }
+    args
+}
+
+/** Run a subcommand against the store, printing any failure as `pass` would. Returns the
+ * process exit code.
+*/
+pub fn run(command: PassCommand, settings: &Settings) -> i32 {
+    if let PassCommand::ClipRestore { timeout } = command {
+        return restore_clipboard(Duration::from_secs(timeout));
+    }
+
+    let json = command.json();
+    let result = CommandLine::new(settings, io::stdin().lock(), io::stdout().lock())
+        .and_then(|mut cli| cli.execute(command).and_then(|()| cli.finish()));
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            report_failure(&err, json);
+            1
+        }
+    }
+}
+
+/** Put back what the clipboard held before a secret was copied, once the timeout passes. The
+ * copy is handed over on stdin by the process which made it.
+*/
+fn restore_clipboard(timeout: Duration) -> i32 {
+    let copied = match CopiedSecret::receive(&mut io::stdin().lock()) {
+        Ok(copied) => copied,
+        Err(err) => {
+            error!("{}", err);
+            return 1;
+        }
+    };
+    std::thread::sleep(timeout);
+    let Some(mut clipboard) = clipboard::display_clipboard() else {
+        error!("Failed to restore the clipboard: {}", Error::NoDisplay);
+        return 1;
+    };
+    match copied.restore(clipboard.as_mut()) {
+        Ok(()) => 0,
+        Err(err) => {
+            error!("Failed to restore the clipboard: {}", err);
+            1
+        }
+    }
+}
+
+/** A decrypted entry in a private temporary file for `edit`, overwritten and removed on drop.
+*/
+struct TempEntry {
+    path: PathBuf,
+}
+
+impl TempEntry {
+    /** Create the file readable only by the user, in memory under `/dev/shm` where available,
+     * as `pass edit` does.
+     */
+    fn create(contents: &str) -> Result<Self> {
+        let shm = Path::new("/dev/shm");
+        let dir = if shm.is_dir() {
+            shm.to_path_buf()
+        } else {
+            std::env::temp_dir()
+        };
+        let path = dir.join(format!("passrs.{}.txt", uuid::Uuid::new_v4()));
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        let entry = Self { path };
+        file.write_all(contents.as_bytes())?;
+        Ok(entry)
+    }
+
+    fn read(&self) -> Result<Zeroizing<String>> {
+        Ok(Zeroizing::new(fs::read_to_string(&self.path)?))
+    }
+}
+
+impl Drop for TempEntry {
+    fn drop(&mut self) {
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            let zeros = vec![0; metadata.len() as usize];
+            if let Err(err) = fs::write(&self.path, zeros) {
+                warn!("Failed to overwrite {}: {}", self.path.display(), err);
+            }
+        }
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("Failed to remove {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+/** State for running one subcommand: the store, its keys, and where input comes from and
+ * output goes. Prompts are written to stderr, so stdout only carries what scripts read.
+*/
+struct CommandLine<I, O> {
+    store: Store,
+    /// Opened on first copy, so commands which never copy work without a display.
+    clipboard: Option<Box<dyn Clipboard>>,
+    /// Secret copied by the command, cleared from the clipboard after the command exits.
+    copied: Option<CopiedSecret>,
+    input: I,
+    out: O,
+    /// Whether stdin is a terminal, so confirmations can be asked and typing hidden.
+    interactive: bool,
+    /// Whether a locked key's passphrase can be asked for on the terminal.
+    ask_passphrase: bool,
+    editor: String,
+}
+
+impl<I: BufRead, O: Write> CommandLine<I, O> {
+    fn new(settings: &Settings, input: I, out: O) -> Result<Self> {
+        Ok(Self {
+            store: Store::open(settings.clone())?,
+            clipboard: None,
+            copied: None,
+            input,
+            out,
+            interactive: io::stdin().is_terminal(),
+            ask_passphrase: fs::File::open("/dev/tty").is_ok(),
+            editor: std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string()),
+        })
+    }
+
+    fn execute(&mut self, command: PassCommand) -> Result<()> {
+        match command {
+            PassCommand::Show {
+                clip,
+                field,
+                json,
+                pass_name,
+            } => self.show(pass_name.as_deref().unwrap_or_default(), clip, field, json),
+            PassCommand::Find { fuzzy, json, terms } => self.find(&terms, fuzzy, json),
+            PassCommand::Insert {
+                echo,
+                multiline,
+                force,
+                pass_name,
+            } => self.insert(&pass_name, echo, multiline, force),
+            PassCommand::Generate {
+                no_symbols,
+                clip,
+                in_place,
+                force,
+                pass_name,
+                pass_length,
+            } => self.generate(&pass_name, pass_length, no_symbols, clip, in_place, force),
+            PassCommand::Edit { pass_name } => self.edit(&pass_name),
+            PassCommand::Rm {
+                recursive,
+                force,
+                pass_name,
+            } => self.remove(&pass_name, recursive, force),
+            PassCommand::Mv(args) => self.copy_move(&args, true),
+            PassCommand::Cp(args) => self.copy_move(&args, false),
+            PassCommand::Init { path, gpg_ids } => self.init(path.as_deref(), &gpg_ids),
+            PassCommand::Otp { action, args } => match action {
+                Some(OtpAction::Code(args)) => self.otp(&args, false),
+                Some(OtpAction::Uri(args)) => self.otp(&args, true),
+                None => self.otp(&args, false),
+            },
+            PassCommand::Git { args } => git::run(self.store.path(), &args),
+            PassCommand::ClipRestore { .. } | PassCommand::Implicit(_) => {
+                unreachable!("handled before a command line is set up")
+            }
+        }
+    }
+
+    /** Leave a background process to clear anything copied once the timeout passes.
+     */
+    fn finish(&mut self) -> Result<()> {
+        match self.copied.take() {
+            Some(copied) => Ok(clipboard::restore_in_background(
+                &copied,
+                self.store.settings().clipboard_timeout,
+            )?),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<I: BufRead, O: Write> CommandLine<I, O> {
+    fn write_json<T: serde::Serialize>(&mut self, body: T) -> Result<()> {
+        serde_json::to_writer(&mut self.out, &json::Document::new(body))?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    fn not_in_store(pass_name: &str) -> anyhow::Error {
+        Error::NotInStore {
+            name: pass_name.to_string(),
+        }
+        .into()
+    }
+
+    /** Ask a yes/no question, defaulting to no. Like `pass`, nothing is asked when stdin isn't
+     * a terminal, and the answer is taken to be yes.
+     */
+    fn confirm(&mut self, question: &str) -> Result<bool> {
+        if !self.interactive {
+            return Ok(true);
+        }
+        eprint!("{} [y/N] ", question);
+        let mut answer = String::new();
+        self.input.read_line(&mut answer)?;
+        Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+    }
+
+    /** Read a line, prompting for it on a terminal.
+     */
+    fn read_line(&mut self, prompt: &str) -> Result<Zeroizing<String>> {
+        if self.interactive {
+            eprint!("{}", prompt);
+        }
+        let mut line = Zeroizing::new(String::new());
+        self.input.read_line(&mut line)?;
+        let trimmed = Zeroizing::new(line.trim_end_matches(['\n', '\r']).to_string());
+        Ok(trimmed)
+    }
+
+    /** Read a line without echoing it, when typed on a terminal.
+     */
+    fn read_secret(&mut self, prompt: &str) -> Result<Zeroizing<String>> {
+        if self.interactive {
+            return Ok(Zeroizing::new(rpassword::prompt_password(prompt)?));
+        }
+        self.read_line(prompt)
+    }
+
+    /** Ask for a key's passphrase on the terminal and unlock it for the rest of the command.
+     */
+    fn unlock(&mut self, key_id: &str, key_user: &str) -> Result<()> {
+        if !self.ask_passphrase {
+            return Err(Error::KeyLocked {
+                key_id: key_id.to_string(),
+                key_user: key_user.to_string(),
+            }
+            .into());
+        }
+
+        for attempt in 1..=PASSPHRASE_ATTEMPTS {
+            let passphrase = Zeroizing::new(rpassword::prompt_password(format!(
+                "Passphrase for {} ({}): ",
+                key_user, key_id
+            ))?);
+            match self.store.unlock(key_id, &passphrase) {
+                Ok(()) => return Ok(()),
+                Err(Error::BadPassphrase { .. }) if attempt < PASSPHRASE_ATTEMPTS => {
+                    eprintln!("Incorrect passphrase, try again.");
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        unreachable!("the last attempt returns")
+    }
+
+    /** Unlock a key able to decrypt an entry, if none is yet.
+     */
+    fn unlock_for(&mut self, entry_path: &Path) -> Result<()> {
+        match self.store.locked_key(entry_path)? {
+            Some((key_id, key_user)) => self.unlock(&key_id, &key_user),
+            None => Ok(()),
+        }
+    }
+
+    fn decrypt_message(&mut self, entry_path: &Path) -> Result<DecryptedMessage> {
+        loop {
+            match self.store.decrypt_file(entry_path)? {
+                DecryptOutcome::Decrypted(message) => return Ok(message),
+                DecryptOutcome::Locked { key_id, key_user } => self.unlock(&key_id, &key_user)?,
+            }
+        }
+    }
+
+    fn decrypt(&mut self, entry_path: &Path) -> Result<Zeroizing<String>> {
+        Ok(Zeroizing::new(self.decrypt_message(entry_path)?.plaintext))
+    }
+
+    /** Re-encrypt every entry at or below a path whose recipients don't match the `.gpg-id`
+     * now applying to it, as after moving it to another folder.
+     */
+    fn reencrypt_path(&mut self, path: &Path) -> Result<()> {
+        let entries = if path.is_dir() {
+            self.store.scanner()?.collect_pass_files(path)?
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        for entry in entries {
+            if !self.store.needs_reencrypt(&entry)? {
+                continue;
+            }
+            info!("Re-encrypting {} for its new recipients", entry.display());
+            let plaintext = self.decrypt(&entry)?;
+            self.store.encrypt_file(&entry, &plaintext)?;
+        }
+        Ok(())
+    }
+
+    /** The folder a name refers to, or the store itself for an empty name, as `ls`, `init` and
+     * the destination of `mv`/`cp` take it.
+     */
+    fn folder_or_root(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty() {
+            return Ok(self.store.path().to_path_buf());
+        }
+        Ok(self.store.folder_path(name)?)
+    }
+
+    /** Commit the changes at `paths` when the store is kept in git, as `pass` does after every
+     * change it makes.
+     */
+    fn git_commit(&self, paths: &[&Path], message: &str) -> Result<()> {
+        if !git::is_repo(self.store.path()) {
+            return Ok(());
+        }
+        git::commit(self.store.path(), paths, message)
+    }
+
+    /** Remove now empty folders from `folder` upwards, stopping at the store root.
+     */
+    fn remove_empty_folders(&self, folder: &Path) {
+        for folder in folder.ancestors() {
+            if folder == self.store.path() || !folder.starts_with(self.store.path()) {
+                break;
+            }
+            if fs::remove_dir(folder).is_err() {
+                break;
+            }
+            debug!("Removed empty folder {}", folder.display());
+        }
+    }
+
+    /** Copy a secret, printing the message `pass -c` does.
+     */
+    fn clip(&mut self, secret: &str, description: &str) -> Result<()> {
+        self.copy(secret)?;
+        writeln!(
+            self.out,
+            "Copied {} to clipboard. Will clear in {} seconds.",
+            description,
+            self.store.settings().clipboard_timeout
+        )?;
+        Ok(())
+    }
+
+    fn copy(&mut self, secret: &str) -> Result<()> {
+        if self.clipboard.is_none() {
+            self.clipboard = clipboard::display_clipboard();
+        }
+        let Some(clipboard) = &mut self.clipboard else {
+            return Err(Error::NoDisplay.into());
+        };
+        let copied = CopiedSecret::copy(clipboard.as_mut(), secret, self.copied.take())?;
+        self.copied = Some(copied);
+        Ok(())
+    }
+
+    fn show(
+        &mut self,
+        pass_name: &str,
+        clip: Option<usize>,
+        field: Option<String>,
+        json: bool,
+    ) -> Result<()> {
+        let pass_name = pass_name.trim_end_matches('/');
+        let entry_path = match pass_name {
+            "" => None,
+            name => Some(self.store.entry_path(name)?),
+        };
+
+        if let Some(entry_path) = entry_path.filter(|path| path.is_file()) {
+            let DecryptedMessage {
+                plaintext,
+                signature,
+            } = self.decrypt_message(&entry_path)?;
+            let plaintext = Zeroizing::new(plaintext);
+            let entry = PassEntry::parse(&plaintext);
+            let info = json::EntryInfo::from(&StoreEntry::new(&entry_path, self.store.path())?);
+
+            let (secret, description) = match (&field, clip) {
+                (Some(field), _) => match entry.field(field) {
+                    Some(value) => (
+                        Zeroizing::new(value.to_string()),
+                        format!("field {}", field),
+                    ),
+                    None => {
+                        return Err(Error::NoField {
+                            name: pass_name.to_string(),
+                            field: field.clone(),
+                        }
+                        .into())
+                    }
+                },
+                (None, Some(line)) => {
+                    match line
+                        .checked_sub(1)
+                        .and_then(|index| plaintext.lines().nth(index))
+                    {
+                        Some(value) => {
+                            (Zeroizing::new(value.to_string()), format!("line {}", line))
+                        }
+                        None => return Err(Error::NoLine { line }.into()),
+                    }
+                }
+                (None, None) if json => {
+                    let recipients = self.store.message_recipients(&entry_path)?;
+                    return self.write_json(json::Shown::new(info, &entry, &signature, recipients));
+                }
+                (None, None) => {
+                    self.out.write_all(plaintext.as_bytes())?;
+                    return Ok(());
+                }
+            };
+
+            return match (clip, &field) {
+                (Some(_), _) if json => {
+                    self.copy(&secret)?;
+                    self.write_json(json::Copied {
+                        entry: info,
+                        copied: description,
+                        clear_after: self.store.settings().clipboard_timeout,
+                    })
+                }
+                (Some(_), _) => self.clip(&secret, pass_name),
+                (None, Some(field)) if json => self.write_json(json::ShownField {
+                    entry: info,
+                    field: json::Field {
+                        name: field.clone(),
+                        value: secret.to_string(),
+                    },
+                }),
+                (None, _) => Ok(writeln!(self.out, "{}", secret.as_str())?),
+            };
+        }
+
+        let folder = self.folder_or_root(pass_name)?;
+        if !folder.is_dir() {
+            if pass_name.is_empty() {
+                return Err(Error::EmptyStore.into());
+            }
+            return Err(Self::not_in_store(pass_name));
+        }
+
+        let entries = self.store.entries_in(&folder)?;
+        if json {
+            return self.write_json(json::Listing {
+                folder: pass_name.to_string(),
+                entries: entries.iter().map(json::EntryInfo::from).collect(),
+            });
+        }
+
+        let (heading, prefix) = if pass_name.is_empty() {
+            ("Password Store", String::new())
+        } else {
+            (pass_name, format!("{}/", pass_name))
+        };
+        let names: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                let name = &entry.name;
+                name.strip_prefix(&prefix).unwrap_or(name).to_string()
+            })
+            .collect();
+        writeln!(self.out, "{}", heading)?;
+        for line in tree::render(&names) {
+            writeln!(self.out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /** List entries matching any of the terms, ignoring case.
+     *
+     * By default, like `pass find`, an entry matches if any folder or its own name contains a
+     * term, so a matching folder brings in everything below it. With `fuzzy` entries are ranked
+     * by their best fuzzy score against any term instead.
+     */
+    fn find(&mut self, terms: &[String], fuzzy: bool, json: bool) -> Result<()> {
+        let entries = self.store.entries()?;
+
+        let mut matches: Vec<(&StoreEntry, Option<i64>)> = if fuzzy {
+            let mut scores: HashMap<EntryId, i64> = HashMap::new();
+            for term in terms.iter().filter(|term| !term.is_empty()) {
+                let found = pass_scanner::filter_pass_entries(&entries, term).unwrap_or_default();
+                for found in found {
+                    let best = scores.entry(found.id).or_insert(found.score);
+                    *best = (*best).max(found.score);
+                }
+            }
+            scores
+                .into_iter()
+                .map(|(id, score)| (&entries[id], Some(score)))
+                .collect()
+        } else {
+            let lowercase: Vec<String> = terms.iter().map(|term| term.to_lowercase()).collect();
+            entries
+                .iter()
+                .filter(|entry| {
+                    entry.name.to_lowercase().split('/').any(|component| {
+                        lowercase
+                            .iter()
+                            .any(|term| component.contains(term.as_str()))
+                    })
+                })
+                .map(|entry| (entry, None))
+                .collect()
+        };
+        matches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.name.cmp(&b.0.name)));
+
+        if json {
+            return self.write_json(json::SearchResults {
+                terms: terms.to_vec(),
+                fuzzy,
+                matches: matches
+                    .into_iter()
+                    .map(|(entry, score)| json::SearchMatch {
+                        entry: entry.into(),
+                        score,
+                    })
+                    .collect(),
+            });
+        }
+
+        writeln!(self.out, "Search Terms: {}", terms.join(" "))?;
+        let names: Vec<String> = matches
+            .iter()
+            .map(|(entry, _)| entry.name.clone())
+            .collect();
+        if fuzzy {
+            for name in names {
+                writeln!(self.out, "{}", name)?;
+            }
+        } else {
+            for line in tree::render(&names) {
+                writeln!(self.out, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, pass_name: &str, echo: bool, multiline: bool, force: bool) -> Result<()> {
+        let entry_path = self.store.entry_path(pass_name)?;
+        if entry_path.exists()
+            && !force
+            && !self.confirm(&format!(
+                "An entry already exists for {}. Overwrite it?",
+                pass_name
+            ))?
+        {
+            bail!("{} was not overwritten.", pass_name);
+        }
+
+        let contents = if multiline {
+            if self.interactive {
+                eprintln!(
+                    "Enter contents of {} and press Ctrl+D when finished:\n",
+                    pass_name
+                );
+            }
+            let mut contents = Zeroizing::new(String::new());
+            self.input.read_to_string(&mut contents)?;
+            contents
+        } else if echo {
+            let password = self.read_line(&format!("Enter password for {}: ", pass_name))?;
+            Zeroizing::new(format!("{}\n", password.as_str()))
+        } else {
+            let password = self.read_secret(&format!("Enter password for {}: ", pass_name))?;
+            let retyped = self.read_secret(&format!("Retype password for {}: ", pass_name))?;
+            if password != retyped {
+                bail!("the entered passwords do not match.");
+            }
+            Zeroizing::new(format!("{}\n", password.as_str()))
+        };
+
+        self.store.encrypt_file(&entry_path, &contents)?;
+        self.git_commit(
+            &[&entry_path],
+            &format!("Add given password for {} to store.", pass_name),
+        )
+    }
+
+    fn generate(
+        &mut self,
+        pass_name: &str,
+        length: Option<usize>,
+        no_symbols: bool,
+        clip: bool,
+        in_place: bool,
+        force: bool,
+    ) -> Result<()> {
+        if length == Some(0) {
+            bail!("pass-length \"0\" must be a positive integer.");
+        }
+        let entry_path = self.store.entry_path(pass_name)?;
+        if in_place && !entry_path.is_file() {
+            return Err(Self::not_in_store(pass_name));
+        }
+        if !in_place
+            && entry_path.exists()
+            && !force
+            && !self.confirm(&format!(
+                "An entry already exists for {}. Overwrite it?",
+                pass_name
+            ))?
+        {
+            bail!("{} was not overwritten.", pass_name);
+        }
+
+        let folder = folder_policy::entry_policy(&entry_path, self.store.path())?;
+        let mut policy = match &folder {
+            Some((_, folder_policy)) => {
+                folder_policy.password_policy(self.store.settings().generated_length)
+            }
+            None => PasswordPolicy {
+                length: self.store.settings().generated_length,
+                ..PasswordPolicy::default()
+            },
+        };
+        if let Some(length) = length {
+            policy.length = length;
+        }
+        if no_symbols {
+            policy.set_class(CharClass::Symbols, false);
+        }
+        let password = generator::generate(&policy)?;
+
+        if let Some((policy_path, folder_policy)) = &folder {
+            let violations = folder_policy.violations(&password);
+            if !violations.is_empty() {
+                eprintln!(
+                    "Warning: the generated password breaks {}: {}",
+                    policy_path.display(),
+                    violations.join(", ")
+                );
+            }
+        }
+
+        let contents = if in_place {
+            let entry = PassEntry::parse(&self.decrypt(&entry_path)?);
+            Zeroizing::new(entry.with_password(&password).to_string())
+        } else {
+            Zeroizing::new(format!("{}\n", password.as_str()))
+        };
+        self.store.encrypt_file(&entry_path, &contents)?;
+        let message = if in_place {
+            format!("Replace generated password for {}.", pass_name)
+        } else {
+            format!("Add generated password for {} to store.", pass_name)
+        };
+        self.git_commit(&[&entry_path], &message)?;
+
+        if clip {
+            self.clip(&password, pass_name)
+        } else {
+            writeln!(
+                self.out,
+                "The generated password for {} is:\n{}",
+                pass_name,
+                password.as_str()
+            )?;
+            Ok(())
+        }
+    }
+
+    fn edit(&mut self, pass_name: &str) -> Result<()> {
+        let entry_path = self.store.entry_path(pass_name)?;
+        let original = if entry_path.is_file() {
+            self.decrypt(&entry_path)?
+        } else {
+            Zeroizing::new(String::new())
+        };
+
+        let temp_entry = TempEntry::create(&original)?;
+        let mut editor = self.editor.split_whitespace();
+        let Some(program) = editor.next() else {
+            bail!("EDITOR is empty.");
+        };
+        let status = Process::new(program)
+            .args(editor)
+            .arg(&temp_entry.path)
+            .status()?;
+        if !status.success() {
+            bail!(
+                "{} exited with {}, {} was not saved.",
+                program,
+                status,
+                pass_name
+            );
+        }
+
+        let edited = temp_entry.read()?;
+        if edited == original {
+            bail!("Password unchanged.");
+        }
+        self.store.encrypt_file(&entry_path, &edited)?;
+        self.git_commit(
+            &[&entry_path],
+            &format!("Edit password for {} using {}.", pass_name, self.editor),
+        )
+    }
+
+    fn remove(&mut self, pass_name: &str, recursive: bool, force: bool) -> Result<()> {
+        let pass_name = pass_name.trim_end_matches('/');
+        if pass_name.is_empty() {
+            return Err(Self::not_in_store(pass_name));
+        }
+        let folder = self.store.folder_path(pass_name)?;
+        let target = if recursive && folder.is_dir() {
+            folder
+        } else {
+            self.store.entry_path(pass_name)?
+        };
+        if !target.exists() {
+            return Err(Self::not_in_store(pass_name));
+        }
+
+        if !force
+            && !self.confirm(&format!(
+                "Are you sure you would like to delete {}?",
+                pass_name
+            ))?
+        {
+            bail!("{} was not removed.", pass_name);
+        }
+
+        if target.is_dir() {
+            fs::remove_dir_all(&target)?;
+        } else {
+            fs::remove_file(&target)?;
+        }
+        writeln!(self.out, "removed '{}'", target.display())?;
+        if let Some(parent) = target.parent() {
+            self.remove_empty_folders(parent);
+        }
+        self.git_commit(&[&target], &format!("Remove {} from store.", pass_name))
+    }
+
+    /** Move or copy an entry or folder like `pass mv`/`pass cp`. A trailing `/` picks the folder
+     * when there's also an entry of the same name, and a destination that's an existing folder,
+     * or ends in `/`, receives the source inside it.
+     */
+    fn copy_move(&mut self, args: &CopyMoveArgs, remove_source: bool) -> Result<()> {
+        let old_name = args.old_path.trim_end_matches('/');
+        if old_name.is_empty() {
+            return Err(Self::not_in_store(&args.old_path));
+        }
+        let old_folder = self.store.folder_path(old_name)?;
+        let old_entry = self.store.entry_path(old_name)?;
+        let source =
+            if old_folder.is_dir() && (!old_entry.is_file() || args.old_path.ends_with('/')) {
+                old_folder
+            } else {
+                old_entry
+            };
+        if !source.exists() {
+            return Err(Self::not_in_store(&args.old_path));
+        }
+
+        let new_folder = self.folder_or_root(args.new_path.trim_end_matches('/'))?;
+        let mut target = if new_folder.is_dir() || args.new_path.ends_with('/') {
+            new_folder.join(source.file_name().unwrap_or_default())
+        } else if source.is_dir() {
+            new_folder
+        } else {
+            self.store.entry_path(&args.new_path)?
+        };
+        if target.is_dir() && source.is_dir() {
+            bail!("{} already exists.", target.display());
+        }
+        if target.is_dir() {
+            target = target.join(source.file_name().unwrap_or_default());
+        }
+        if target.starts_with(&source) {
+            bail!("Cannot put {} inside itself.", args.old_path);
+        }
+        if target.is_file()
+            && !args.force
+            && !self.confirm(&format!("Overwrite {}?", target.display()))?
+        {
+            bail!("{} was not overwritten.", target.display());
+        }
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if remove_source {
+            fs::rename(&source, &target)?;
+            if let Some(parent) = source.parent() {
+                self.remove_empty_folders(parent);
+            }
+        } else if source.is_dir() {
+            copy_folder(&source, &target)?;
+        } else {
+            fs::copy(&source, &target)?;
+        }
+        self.reencrypt_path(&target)?;
+
+        if remove_source {
+            let message = format!("Rename {} to {}.", args.old_path, args.new_path);
+            self.git_commit(&[&source, &target], &message)
+        } else {
+            let message = format!("Copy {} to {}.", args.old_path, args.new_path);
+            self.git_commit(&[&target], &message)
+        }
+    }
+
+    fn init(&mut self, subfolder: Option<&str>, gpg_ids: &[String]) -> Result<()> {
+        let subfolder = subfolder.unwrap_or_default().trim_end_matches('/');
+        let folder = self.folder_or_root(subfolder)?;
+        let location = if subfolder.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", subfolder)
+        };
+
+        if let [gpg_id] = gpg_ids {
+            if gpg_id.is_empty() {
+                let gpg_id_path = folder.join(GPG_ID_FILE);
+                if !gpg_id_path.is_file() {
+                    bail!(
+                        "{} does not exist and so cannot be removed.",
+                        gpg_id_path.display()
+                    );
+                }
+                fs::remove_file(&gpg_id_path)?;
+                writeln!(self.out, "removed '{}'", gpg_id_path.display())?;
+                self.reencrypt_path(&folder)?;
+                return self.git_commit(
+                    &[&folder],
+                    &format!("Deinitialize {}{}.", GPG_ID_FILE, location),
+                );
+            }
+        }
+
+        fs::create_dir_all(&folder)?;
+        let mut reencryption = self.store.reencrypt(&folder, gpg_ids)?;
+        // Keys are unlocked up front, since a locked key fails the re-encryption part way.
+        for entry in self.store.scanner()?.collect_pass_files(&folder)? {
+            self.unlock_for(&entry)?;
+        }
+        while let Some(entry) = reencryption.step(self.store.session_mut())? {
+            let (done, total) = reencryption.progress();
+            debug!("Re-encrypted {} of {}: {}", done, total, entry.display());
+        }
+        let report = reencryption.commit()?;
+        info!(
+            "Re-encrypted {} entries, {} already up to date",
+            report.reencrypted.len(),
+            report.skipped.len()
+        );
+
+        writeln!(
+            self.out,
+            "Password store initialized for {}{}",
+            gpg_ids.join(", "),
+            location
+        )?;
+        self.git_commit(
+            &[&folder],
+            &format!("Set GPG id to {}{}.", gpg_ids.join(", "), location),
+        )
+    }
+
+    /** Show or copy the code of an entry's first `otpauth://` URI, or the URI itself. HOTP
+     * counters are advanced and saved first, like pass-otp.
+     */
+    fn otp(&mut self, args: &OtpArgs, show_uri: bool) -> Result<()> {
+        let Some(pass_name) = &args.pass_name else {
+            bail!("Usage: passrs otp [code|uri] [--clip,-c] pass-name");
+        };
+        let entry_path = self.store.entry_path(pass_name)?;
+        if !entry_path.is_file() {
+            return Err(Self::not_in_store(pass_name));
+        }
+
+        let entry = PassEntry::parse(&self.decrypt(&entry_path)?);
+        let Some(uri) = entry.otpauth.first() else {
+            bail!("{} has no OTP secret.", pass_name);
+        };
+        let mut generator = OtpAuth::parse(uri)?;
+
+        let (secret, description) = if show_uri {
+            (uri.clone(), format!("OTP URI for {}", pass_name))
+        } else {
+            if generator.increment() {
+                let updated = entry.replace_otpauth(uri, generator.uri());
+                self.store.encrypt_file(&entry_path, &updated.to_string())?;
+                self.git_commit(
+                    &[&entry_path],
+                    &format!("Increment HOTP counter for {}.", pass_name),
+                )?;
+            }
+            (
+                generator.code(otp::unix_time()),
+                format!("OTP code for {}", pass_name),
+            )
+        };
+
+        if args.clip {
+            self.clip(&secret, &description)
+        } else {
+            writeln!(self.out, "{}", secret)?;
+            Ok(())
+        }
+    }
+}
+
+fn copy_folder(source: &Path, target: &Path) -> Result<()> {
+    fs::create_dir_all(target)?;
+    for child in fs::read_dir(source)? {
+        let child = child?;
+        let child_target = target.join(child.file_name());
+        if child.file_type()?.is_dir() {
+            copy_folder(&child.path(), &child_target)?;
+        } else {
+            fs::copy(child.path(), child_target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{init_logs, TmpTree};
+    use passrs::clipboard::MemoryClipboard;
+
+    const KEYPHRASE: &str = "sample";
+    const SAMPLE_KEY_ID: &str = "f711232219df6593";
+    const SAMPLE_FINGERPRINT: &str = "A2FF365C3F7C667011ADD3BAF711232219DF6593";
+    const ALT_FINGERPRINT: &str = "ACF381228DF90F6AC55327BC0B48299C0FA5121E";
+    const ENTRY: &str = "hunter2\nuser: bob\notpauth://hotp/sam?secret=GEZDGNBV&counter=1\n";
+
+    fn sample_settings(store_path: &Path) -> Settings {
+        Settings {
+            store_path: store_path.to_path_buf(),
+            secret_keys: vec!["./src/pgp/sample_key.asc".into()],
+            public_keys: vec![
+                "./src/pgp/sample_key.pub.asc".into(),
+                "./src/pgp/invalid_key.pub.asc".into(),
+            ],
+            ..Settings::default()
+        }
+    }
+
+    /** A command line reading its input from a script, with the sample key unlocked and the
+     * clipboard in memory.
+     */
+    fn sample_cli(settings: &Settings) -> Result<CommandLine<&'static [u8], Vec<u8>>> {
+        let mut cli = CommandLine::new(settings, "".as_bytes(), vec![])?;
+        cli.store.unlock(SAMPLE_KEY_ID, KEYPHRASE)?;
+        cli.clipboard = Some(Box::<MemoryClipboard>::default());
+        cli.interactive = false;
+        cli.ask_passphrase = false;
+        Ok(cli)
+    }
+
+    /** Run one command with the given input, returning what it printed.
+     */
+    fn run_command(
+        cli: &mut CommandLine<&'static [u8], Vec<u8>>,
+        input: &'static str,
+        args: &[&str],
+    ) -> Result<String> {
+        let args = pass_args(["passrs"].iter().chain(args).map(|arg| arg.to_string()));
+        let Some(command) = Cli::try_parse_from(args)?.command else {
+            bail!("no command given");
+        };
+        cli.input = input.as_bytes();
+        cli.execute(command)?;
+        Ok(String::from_utf8(std::mem::take(&mut cli.out))?)
+    }
+
+    fn sample_store() -> Result<(TmpTree, Settings)> {
+        init_logs();
+        let tmp_tree = TmpTree::new();
+        fs::write(tmp_tree.base_path.join(GPG_ID_FILE), SAMPLE_KEY_ID)?;
+        let settings = sample_settings(&tmp_tree.base_path);
+        Ok((tmp_tree, settings))
+    }
+
+    fn sample_entry(cli: &mut CommandLine<&'static [u8], Vec<u8>>) -> Result<()> {
+        run_command(cli, ENTRY, &["insert", "-m", "web/github"])?;
+        Ok(())
+    }
+
+    fn recipient_count(cli: &CommandLine<&'static [u8], Vec<u8>>, entry: &Path) -> Result<usize> {
+        Ok(cli.store.message_recipients(entry)?.len())
+    }
+
+    #[test]
+    fn test_pass_args() {
+        let args =
+            |args: &[&str]| -> Vec<String> { pass_args(args.iter().map(|arg| arg.to_string())) };
+        assert_eq!(
+            args(&["passrs", "web/github", "-c2"]),
+            vec!["passrs", "show", "web/github", "--clip=2"]
+        );
+        assert_eq!(
+            args(&["passrs", "insert", "-m", "--", "-c2"]),
+            vec!["passrs", "insert", "-m", "--", "-c2"]
+        );
+        assert_eq!(args(&["passrs", "ls"]), vec!["passrs", "ls"]);
+        assert_eq!(
+            args(&["passrs", "-c", "web/github"]),
+            vec!["passrs", "show", "-c", "web/github"]
+        );
+        assert_eq!(args(&["passrs", "--help"]), vec!["passrs", "--help"]);
+    }
+
+    #[test]
+    fn test_show() -> Result<()> {
+        let (_tmp_tree, settings) = sample_store()?;
+        let mut cli = sample_cli(&settings)?;
+        sample_entry(&mut cli)?;
+
+        assert_eq!(run_command(&mut cli, "", &["show", "web/github"])?, ENTRY);
+        assert_eq!(
+            run_command(&mut cli, "", &["web/github", "--field", "user"])?,
+            "bob\n"
+        );
+        assert_eq!(
+            run_command(&mut cli, "", &["show", "-c2", "web/github"])?,
+            format!(
+                "Copied web/github to clipboard. Will clear in {} seconds.\n",
+                settings.clipboard_timeout
+            )
+        );
+
+        assert_eq!(
+            run_command(&mut cli, "", &["-c", "web/github"])?,
+            format!(
+                "Copied web/github to clipboard. Will clear in {} seconds.\n",
+                settings.clipboard_timeout
+            )
+        );
+
+        let err = run_command(&mut cli, "", &["show", "--clip=9", "web/github"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "There is no password to put on the clipboard at line 9."
+        );
+        let err = run_command(&mut cli, "", &["show", "web/gitlab"]).unwrap_err();
+        assert_eq!(err.to_string(), "web/gitlab is not in the password store.");
+        assert!(run_command(&mut cli, "", &["show", "../outside"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_and_find() -> Result<()> {
+        let (_tmp_tree, settings) = sample_store()?;
+        let mut cli = sample_cli(&settings)?;
+        sample_entry(&mut cli)?;
+
+        assert_eq!(
+            run_command(&mut cli, "", &["ls"])?,
+            concat!(
+                "Password Store\n",
+                "├── a\n",
+                "│   └── target\n",
+                "├── b\n",
+                "│   └── target\n",
+                "└── web\n",
+                "    └── github\n",
+            )
+        );
+        assert_eq!(
+            run_command(&mut cli, "", &["ls", "web/"])?,
+            "web\n└── github\n"
+        );
+        assert_eq!(
+            run_command(&mut cli, "", &["find", "HUB", "b"])?,
+            "Search Terms: HUB b\n├── b\n│   └── target\n└── web\n    └── github\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_json() -> Result<()> {
+        let (_tmp_tree, settings) = sample_store()?;
+        let mut cli = sample_cli(&settings)?;
+        sample_entry(&mut cli)?;
+
+        let shown: serde_json::Value = serde_json::from_str(&run_command(
+            &mut cli,
+            "",
+            &["show", "--json", "web/github"],
+        )?)?;
+        assert_eq!(shown["schema_version"], json::SCHEMA_VERSION);
+        assert_eq!(shown["entry"]["name"], "web/github");
+        assert_eq!(shown["password"], "hunter2");
+        assert_eq!(
+            shown["fields"],
+            serde_json::json!([{ "name": "user", "value": "bob" }])
+        );
+        assert_eq!(
+            shown["otpauth"][0],
+            "otpauth://hotp/sam?secret=GEZDGNBV&counter=1"
+        );
+        assert_eq!(shown["signature"]["status"], "unsigned");
+        assert_eq!(shown["recipients"], serde_json::json!(["3a80df469a8445d4"]));
+
+        let field: serde_json::Value = serde_json::from_str(&run_command(
+            &mut cli,
+            "",
+            &["show", "--json", "--field", "user", "web/github"],
+        )?)?;
+        assert_eq!(
+            field["field"],
+            serde_json::json!({ "name": "user", "value": "bob" })
+        );
+
+        let listing: serde_json::Value =
+            serde_json::from_str(&run_command(&mut cli, "", &["ls", "--json", "web"])?)?;
+        assert_eq!(listing["folder"], "web");
+        assert_eq!(listing["entries"][0]["name"], "web/github");
+        assert!(listing["entries"][0]["modified"].is_u64());
+
+        let found: serde_json::Value = serde_json::from_str(&run_command(
+            &mut cli,
+            "",
+            &["find", "--fuzzy", "--json", "gthb"],
+        )?)?;
+        assert_eq!(found["fuzzy"], true);
+        let matches = found["matches"].as_array().unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0]["name"], "web/github");
+        assert!(matches[0]["score"].as_i64().unwrap() > 0);
+
+        let found: serde_json::Value =
+            serde_json::from_str(&run_command(&mut cli, "", &["find", "--json", "target"])?)?;
+        let names: Vec<&serde_json::Value> = found["matches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|found| &found["name"])
+            .collect();
+        assert_eq!(names, ["a/target", "b/target"]);
+        assert!(found["matches"][0]["score"].is_null());
+
+        let err = run_command(&mut cli, "", &["show", "--json", "web/gitlab"]).unwrap_err();
+        assert_eq!(json::Failure::new(&err).error.code, "not_in_store");
+        let err = run_command(
+            &mut cli,
+            "",
+            &["show", "--json", "--field=pin", "web/github"],
+        )
+        .unwrap_err();
+        assert_eq!(json::Failure::new(&err).error.code, "no_field");
+        let err =
+            run_command(&mut cli, "", &["show", "--json", "--clip=9", "web/github"]).unwrap_err();
+        assert_eq!(json::Failure::new(&err).error.code, "no_line");
+        let err = run_command(&mut cli, "", &["show", "--json", "../outside"]).unwrap_err();
+        assert_eq!(json::Failure::new(&err).error.code, "outside_store");
+
+        // Failures parsing the arguments themselves are JSON too when --json is among them.
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert!(json_requested(args(&[
+            "passrs", "show", "--json", "--bogus"
+        ])));
+        assert!(!json_requested(args(&["passrs", "insert", "--", "--json"])));
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_and_generate() -> Result<()> {
+        let (tmp_tree, settings) = sample_store()?;
+        let mut cli = sample_cli(&settings)?;
+        sample_entry(&mut cli)?;
+        let store = &tmp_tree.base_path;
+
+        run_command(&mut cli, "s3cret\n", &["insert", "-e", "mail"])?;
+        assert_eq!(run_command(&mut cli, "", &["mail"])?, "s3cret\n");
+        let err = run_command(&mut cli, "one\ntwo\n", &["insert", "mail"]).unwrap_err();
+        assert_eq!(err.to_string(), "the entered passwords do not match.");
+
+        fs::create_dir_all(store.join("bank"))?;
+        fs::write(
+            store.join("bank").join(folder_policy::POLICY_FILE),
+            "max_length = 16\nsymbols = false\n",
+        )?;
+        let printed = run_command(&mut cli, "", &["generate", "bank/main"])?;
+        let password = printed
+            .strip_prefix("The generated password for bank/main is:\n")
+            .unwrap()
+            .trim_end();
+        assert_eq!(password.len(), 16);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(
+            run_command(&mut cli, "", &["show", "bank/main"])?,
+            format!("{}\n", password)
+        );
+
+        // In place, only the first line changes.
+        run_command(&mut cli, "", &["generate", "-n", "-i", "web/github", "30"])?;
+        let shown = run_command(&mut cli, "", &["show", "web/github"])?;
+        let (password, rest) = shown.split_once('\n').unwrap();
+        assert_eq!(password.len(), 30);
+        assert_eq!(rest, ENTRY.split_once('\n').unwrap().1);
+
+        assert!(run_command(&mut cli, "", &["generate", "-i", "nothing"]).is_err());
+        assert!(run_command(&mut cli, "", &["generate", "web/new", "0"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit() -> Result<()> {
+        let (_tmp_tree, settings) = sample_store()?;
+        let mut cli = sample_cli(&settings)?;
+        sample_entry(&mut cli)?;
+
+        cli.editor = "sed -i s/bob/alice/".to_string();
+        cli.edit("web/github")?;
+        assert_eq!(
+            run_command(&mut cli, "", &["show", "--field", "user", "web/github"])?,
+            "alice\n"
+        );
+
+        cli.editor = "true".to_string();
+        let err = cli.edit("web/github").unwrap_err();
+        assert_eq!(err.to_string(), "Password unchanged.");
+        cli.editor = "false".to_string();
+        assert!(cli.edit("web/github").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_copy_and_remove() -> Result<()> {
+        let (tmp_tree, settings) = sample_store()?;
+        let mut cli = sample_cli(&settings)?;
+        sample_entry(&mut cli)?;
+        let store = &tmp_tree.base_path;
+        fs::create_dir_all(store.join("shared"))?;
+        fs::write(
+            store.join("shared").join(GPG_ID_FILE),
+            format!("{}\n{}\n", SAMPLE_FINGERPRINT, ALT_FINGERPRINT),
+        )?;
+
+        // Entries moved under another .gpg-id are re-encrypted to its recipients.
+        run_command(&mut cli, "", &["cp", "web/github", "shared/"])?;
+        assert_eq!(recipient_count(&cli, &store.join("shared/github.gpg"))?, 2);
+        assert_eq!(recipient_count(&cli, &store.join("web/github.gpg"))?, 1);
+
+        run_command(&mut cli, "", &["mv", "web/github", "personal/code"])?;
+        assert!(!store.join("web").exists());
+        assert_eq!(run_command(&mut cli, "", &["personal/code"])?, ENTRY);
+
+        run_command(&mut cli, "", &["mv", "personal", "archive"])?;
+        assert!(store.join("archive/code.gpg").is_file());
+        let err = run_command(&mut cli, "", &["mv", "personal/code", "x"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "personal/code is not in the password store."
+        );
+
+        // Without -r a folder isn't an entry.
+        assert!(run_command(&mut cli, "", &["rm", "archive"]).is_err());
+        run_command(&mut cli, "", &["rm", "-r", "archive"])?;
+        assert!(!store.join("archive").exists());
+        run_command(&mut cli, "", &["rm", "-f", "shared/github"])?;
+        assert!(store.join("shared").join(GPG_ID_FILE).is_file());
+
+        // Names resolving to the store itself mustn't reach the file beside it.
+        let beside = store.with_extension("gpg");
+        fs::write(&beside, "not an entry")?;
+        for name in [".", "./", ""] {
+            assert!(run_command(&mut cli, "", &["rm", "-f", name]).is_err());
+            assert!(run_command(&mut cli, "", &["rm", "-rf", name]).is_err());
+            assert!(run_command(&mut cli, "", &["mv", name, "elsewhere"]).is_err());
+            assert!(run_command(&mut cli, "", &["edit", name]).is_err());
+        }
+        let survived = beside.is_file();
+        fs::remove_file(&beside)?;
+        assert!(survived);
+        assert!(store.join("shared").join(GPG_ID_FILE).is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn test_git() -> Result<()> {
+        let (tmp_tree, settings) = sample_store()?;
+        let mut cli = sample_cli(&settings)?;
+        let store = &tmp_tree.base_path;
+        let git = |args: &[&str]| -> Result<String> {
+            let output = Process::new("git")
+                .arg("-C")
+                .arg(store)
+                .args(args)
+                .output()?;
+            assert!(output.status.success(), "git {:?} failed", args);
+            Ok(String::from_utf8(output.stdout)?)
+        };
+
+        let err = run_command(&mut cli, "", &["git", "log"]).unwrap_err();
+        assert!(err.to_string().contains("not a git repository"));
+
+        // An identity is set up front, as there may be no global one to commit as.
+        git(&["init", "--quiet"])?;
+        git(&["config", "user.name", "Sam"])?;
+        git(&["config", "user.email", "sam@example.com"])?;
+        git(&["config", "commit.gpgsign", "false"])?;
+        run_command(&mut cli, "", &["git", "init", "--quiet"])?;
+
+        sample_entry(&mut cli)?;
+        run_command(&mut cli, "", &["mv", "web/github", "code/github"])?;
+        run_command(&mut cli, "", &["cp", "code/github", "code/gitlab"])?;
+        run_command(&mut cli, "", &["rm", "-f", "code/github"])?;
+
+        assert_eq!(
+            git(&["log", "--format=%s"])?,
+            concat!(
+                "Remove code/github from store.\n",
+                "Copy code/github to code/gitlab.\n",
+                "Rename web/github to code/github.\n",
+                "Add given password for web/github to store.\n",
+                "Add current contents of password store.\n",
+            )
+        );
+        assert_eq!(git(&["status", "--porcelain"])?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_init() -> Result<()> {
+        let (tmp_tree, settings) = sample_store()?;
+        let mut cli = sample_cli(&settings)?;
+        sample_entry(&mut cli)?;
+        let store = &tmp_tree.base_path;
+        let entry = store.join("web/github.gpg");
+
+        let printed = run_command(
+            &mut cli,
+            "",
+            &["init", "-p", "web", SAMPLE_FINGERPRINT, ALT_FINGERPRINT],
+        )?;
+        assert_eq!(
+            printed,
+            format!(
+                "Password store initialized for {}, {} (web)\n",
+                SAMPLE_FINGERPRINT, ALT_FINGERPRINT
+            )
+        );
+        assert_eq!(recipient_count(&cli, &entry)?, 2);
+
+        // Removing the folder's .gpg-id goes back to the store's recipients.
+        run_command(&mut cli, "", &["init", "-p", "web", ""])?;
+        assert!(!store.join("web").join(GPG_ID_FILE).exists());
+        assert_eq!(recipient_count(&cli, &entry)?, 1);
+        assert!(run_command(&mut cli, "", &["init", "-p", "web", ""]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_otp() -> Result<()> {
+        let (_tmp_tree, settings) = sample_store()?;
+        let mut cli = sample_cli(&settings)?;
+        sample_entry(&mut cli)?;
+
+        let expected = otp::hotp(b"12345", 2, otp::OtpAlgorithm::Sha1, 6);
+        assert_eq!(
+            run_command(&mut cli, "", &["otp", "web/github"])?,
+            format!("{}\n", expected)
+        );
+        // The counter was saved, so the same code is never shown twice.
+        assert_eq!(
+            run_command(&mut cli, "", &["otp", "uri", "web/github"])?,
+            "otpauth://hotp/sam?secret=GEZDGNBV&counter=2\n"
+        );
+
+        run_command(&mut cli, "pw\n", &["insert", "-e", "plain"])?;
+        let err = run_command(&mut cli, "", &["otp", "-c", "plain"]).unwrap_err();
+        assert_eq!(err.to_string(), "plain has no OTP secret.");
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+
+/** Entries and folders below a folder, keyed by name and whether they're a folder, since an
+ * entry and a folder can share a name.
+*/
+#[derive(Default)]
+struct Node {
+    children: BTreeMap<(String, bool), Node>,
+}
+
+/** Draw entry names as a tree, the way `pass ls` shows them through `tree`:
+ *
+ * ```text
+ * ├── email
+ * │   └── work
+ * └── bank
+ * ```
+*/
+pub fn render(names: &[String]) -> Vec<String> {
+    let mut root = Node::default();
+    for name in names {
+        let mut components: Vec<&str> = name.split('/').filter(|c| !c.is_empty()).collect();
+        let Some(entry) = components.pop() else {
+            continue;
+        };
+
+        let mut node = &mut root;
+        for folder in components {
+            node = node.children.entry((folder.to_string(), true)).or_default();
+        }
+        node.children.entry((entry.to_string(), false)).or_default();
+    }
+
+    let mut lines = vec![];
+    render_node(&root, "", &mut lines);
+    lines
+}
+
+fn render_node(node: &Node, prefix: &str, lines: &mut Vec<String>) {
+    let count = node.children.len();
+    for (index, ((name, _), child)) in node.children.iter().enumerate() {
+        let last = index + 1 == count;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        lines.push(format!("{}{}{}", prefix, branch, name));
+        render_node(child, &format!("{}{}", prefix, indent), lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let names: Vec<String> = [
+            "web/github",
+            "bank",
+            "web/mail/work",
+            "web/mail/home",
+            "web",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+        assert_eq!(
+            render(&names),
+            vec![
+                "├── bank",
+                "├── web",
+                "└── web",
+                "    ├── github",
+                "    └── mail",
+                "        ├── home",
+                "        └── work",
+            ]
+        );
+        assert!(render(&[]).is_empty());
+    }
+}
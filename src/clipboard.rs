@@ -0,0 +1,318 @@
+use std::io::{BufRead, Write};
+use std::process::{Command, Stdio};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::{debug, info};
+use zeroize::Zeroizing;
+
+use crate::errors::Error;
+
+/** A system clipboard holding text.
+*/
+pub trait Clipboard: Send {
+    /// Current clipboard contents, or None if the clipboard is empty.
+    fn get(&mut self) -> Result<Option<String>, Error>;
+    fn set(&mut self, contents: &str) -> Result<(), Error>;
+}
+
+/** Clipboard held in memory, for tests.
+*/
+#[derive(Debug, Default)]
+pub struct MemoryClipboard {
+    contents: Option<String>,
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.contents.clone())
+    }
+
+    fn set(&mut self, contents: &str) -> Result<(), Error> {
+        self.contents = Some(contents.to_string());
+        Ok(())
+    }
+}
+
+/** Clipboard backed by an external copy/paste tool, the same approach `pass` takes.
+ *
+ * Using a separate process means X11 selections outlive passrs itself.
+*/
+#[derive(Debug)]
+pub struct CommandClipboard {
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+
+impl CommandClipboard {
+    /// X11 CLIPBOARD selection, via `xclip`.
+    pub fn x11() -> Self {
+        Self {
+            copy: &["xclip", "-selection", "clipboard", "-in"],
+            paste: &["xclip", "-selection", "clipboard", "-out"],
+        }
+    }
+
+    /// Wayland clipboard, via `wl-clipboard`.
+    pub fn wayland() -> Self {
+        Self {
+            copy: &["wl-copy"],
+            paste: &["wl-paste", "--no-newline"],
+        }
+    }
+}
+
+impl Clipboard for CommandClipboard {
+    fn get(&mut self) -> Result<Option<String>, Error> {
+        let output = Command::new(self.paste[0])
+            .args(&self.paste[1..])
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|err| Error::ClipboardError {
+                err: format!("Failed to run '{}': {}", self.paste[0], err),
+            })?;
+
+        // Both tools exit non-zero when there's nothing to paste.
+        if !output.status.success() {
+            debug!("'{}' exited with {}", self.paste[0], output.status);
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8(output.stdout)?))
+    }
+
+    fn set(&mut self, contents: &str) -> Result<(), Error> {
+        let mut child = Command::new(self.copy[0])
+            .args(&self.copy[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| Error::ClipboardError {
+                err: format!("Failed to run '{}': {}", self.copy[0], err),
+            })?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(contents.as_bytes())?;
+        }
+
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(Error::ClipboardError {
+                err: format!("'{}' exited with {}", self.copy[0], status),
+            });
+        }
+        Ok(())
+    }
+}
+
+/** The clipboard of the current display server, preferring Wayland over X11, or None without
+ * one.
+*/
+pub fn display_clipboard() -> Option<Box<dyn Clipboard>> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        debug!("Using Wayland clipboard");
+        Some(Box::new(CommandClipboard::wayland()))
+    } else if std::env::var_os("DISPLAY").is_some() {
+        debug!("Using X11 clipboard");
+        Some(Box::new(CommandClipboard::x11()))
+    } else {
+        None
+    }
+}
+
+/** Leave a `clip-restore` process behind to restore the clipboard once `timeout` seconds pass,
+ * so the secret is cleared even if passrs exits first. The process is the running executable,
+ * which is expected to take the copy on stdin as the `clip-restore` command does.
+*/
+pub fn restore_in_background(copied: &CopiedSecret, timeout: u64) -> Result<(), Error> {
+    let spawn = || -> Result<u32, Error> {
+        let mut child = Command::new(std::env::current_exe()?)
+            .arg("clip-restore")
+            .arg(timeout.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            copied.send(&mut stdin)?;
+        }
+        Ok(child.id())
+    };
+    let child = spawn().map_err(|err| Error::ClipboardError {
+        err: format!("Failed to leave the clipboard to be restored: {}", err),
+    })?;
+    debug!("Clipboard will be restored by process {}", child);
+    Ok(())
+}
+
+/** A secret placed on the clipboard, remembering what was there beforehand.
+ *
+ * Like `pass -c`, the previous contents are only restored if the clipboard still holds the
+ * secret, so anything copied in the meantime is left alone.
+*/
+pub struct CopiedSecret {
+    secret: Zeroizing<String>,
+    previous: Option<Zeroizing<String>>,
+}
+
+impl CopiedSecret {
+    /** Copy a secret to the clipboard.
+     *
+     * If `pending` is a secret copied earlier that is still on the clipboard, its saved contents
+     * carry over, rather than saving the earlier secret as the "previous" contents.
+     */
+    pub fn copy(
+        clipboard: &mut dyn Clipboard,
+        secret: &str,
+        pending: Option<CopiedSecret>,
+    ) -> Result<Self, Error> {
+        let current = clipboard.get()?.map(Zeroizing::new);
+        let previous = match pending {
+            Some(pending) if current.as_deref() == Some(&*pending.secret) => pending.previous,
+            _ => current,
+        };
+
+        clipboard.set(secret)?;
+        Ok(Self {
+            secret: Zeroizing::new(secret.to_string()),
+            previous,
+        })
+    }
+
+    /** Put back what was on the clipboard before the secret, if the secret is still there.
+     */
+    pub fn restore(self, clipboard: &mut dyn Clipboard) -> Result<(), Error> {
+        let current = clipboard.get()?.map(Zeroizing::new);
+        if current.as_deref() != Some(&*self.secret) {
+            info!("Clipboard changed since copying, leaving it alone");
+            return Ok(());
+        }
+
+        info!("Restoring previous clipboard contents");
+        match &self.previous {
+            Some(previous) => clipboard.set(previous.as_str()),
+            None => clipboard.set(""),
+        }
+    }
+
+    /** Hand the copy over to another process, which restores the clipboard once the invoking
+     * command has exited, the way `pass -c` leaves a background job behind.
+     *
+     * Both the secret and the previous contents are written base64 encoded, one per line, with
+     * an empty line standing for an empty clipboard.
+     */
+    pub fn send(&self, out: &mut dyn Write) -> Result<(), Error> {
+        let previous = self
+            .previous
+            .as_ref()
+            .map(|previous| Zeroizing::new(STANDARD.encode(previous.as_bytes())));
+        let encoded = Zeroizing::new(format!(
+            "{}\n{}\n",
+            STANDARD.encode(self.secret.as_bytes()),
+            previous.as_deref().map(String::as_str).unwrap_or_default()
+        ));
+        out.write_all(encoded.as_bytes())?;
+        Ok(())
+    }
+
+    /** Read a copy written by `send`.
+     */
+    pub fn receive(input: &mut dyn BufRead) -> Result<Self, Error> {
+        let mut decode = || -> Result<Option<Zeroizing<String>>, Error> {
+            let mut line = Zeroizing::new(String::new());
+            input.read_line(&mut line)?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                return Ok(None);
+            }
+            let bytes =
+                Zeroizing::new(STANDARD.decode(line).map_err(|err| Error::ClipboardError {
+                    err: format!("Malformed clipboard hand-over: {}", err),
+                })?);
+            Ok(Some(Zeroizing::new(String::from_utf8(bytes.to_vec())?)))
+        };
+
+        let secret = decode()?.ok_or_else(|| Error::ClipboardError {
+            err: "No secret in clipboard hand-over".to_string(),
+        })?;
+        let previous = decode()?;
+        Ok(Self { secret, previous })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_copy_and_restore() -> Result<()> {
+        let mut clipboard = MemoryClipboard::default();
+        clipboard.set("previous")?;
+
+        let copied = CopiedSecret::copy(&mut clipboard, "hunter2", None)?;
+        assert_eq!(clipboard.get()?.as_deref(), Some("hunter2"));
+
+        copied.restore(&mut clipboard)?;
+        assert_eq!(clipboard.get()?.as_deref(), Some("previous"));
+
+        // An empty clipboard is cleared again, rather than left holding the secret.
+        let mut clipboard = MemoryClipboard::default();
+        let copied = CopiedSecret::copy(&mut clipboard, "hunter2", None)?;
+        copied.restore(&mut clipboard)?;
+        assert_eq!(clipboard.get()?.as_deref(), Some(""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_leaves_newer_contents() -> Result<()> {
+        let mut clipboard = MemoryClipboard::default();
+        clipboard.set("previous")?;
+
+        let copied = CopiedSecret::copy(&mut clipboard, "hunter2", None)?;
+        clipboard.set("copied since")?;
+
+        copied.restore(&mut clipboard)?;
+        assert_eq!(clipboard.get()?.as_deref(), Some("copied since"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_consecutive_copies_restore_original() -> Result<()> {
+        let mut clipboard = MemoryClipboard::default();
+        clipboard.set("previous")?;
+
+        let first = CopiedSecret::copy(&mut clipboard, "hunter2", None)?;
+        let second = CopiedSecret::copy(&mut clipboard, "correct horse", Some(first))?;
+        assert_eq!(clipboard.get()?.as_deref(), Some("correct horse"));
+
+        second.restore(&mut clipboard)?;
+        assert_eq!(clipboard.get()?.as_deref(), Some("previous"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hand_over() -> Result<()> {
+        let mut clipboard = MemoryClipboard::default();
+        clipboard.set("previous\nlines")?;
+
+        let copied = CopiedSecret::copy(&mut clipboard, "hunter2", None)?;
+        let mut handed = vec![];
+        copied.send(&mut handed)?;
+        let received = CopiedSecret::receive(&mut handed.as_slice())?;
+        received.restore(&mut clipboard)?;
+        assert_eq!(clipboard.get()?.as_deref(), Some("previous\nlines"));
+
+        let copied = CopiedSecret::copy(&mut MemoryClipboard::default(), "hunter2", None)?;
+        let mut handed = vec![];
+        copied.send(&mut handed)?;
+        assert!(CopiedSecret::receive(&mut handed.as_slice())?
+            .previous
+            .is_none());
+        assert!(CopiedSecret::receive(&mut "".as_bytes()).is_err());
+
+        Ok(())
+    }
+}
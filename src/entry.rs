@@ -0,0 +1,241 @@
+use std::fmt::Display;
+
+/// Scheme prefix of one-time-password seeds, as stored by pass-otp.
+pub const OTPAUTH_SCHEME: &str = "otpauth://";
+
+/** A decrypted pass entry, split into its conventional parts.
+ *
+ * The first line is the password. Following lines of the form `key: value` are fields (kept in
+ * order, indented lines continue the previous field's value), `otpauth://` URIs are collected
+ * separately, and anything else is free-form notes. A YAML-style `---` separator is skipped.
+ *
+ * The original text is retained, so an entry is written back exactly as it was read.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassEntry {
+    pub password: String,
+    pub fields: Vec<(String, String)>,
+    pub notes: String,
+    pub otpauth: Vec<String>,
+    raw: String,
+}
+
+impl PassEntry {
+    pub fn parse(text: &str) -> Self {
+        let mut lines = text.lines();
+        let password = lines.next().unwrap_or_default().to_string();
+
+        let mut fields: Vec<(String, String)> = vec![];
+        let mut notes: Vec<&str> = vec![];
+        let mut otpauth = vec![];
+        // Whether the previous line was a field, which indented lines may continue.
+        let mut in_field = false;
+
+        for line in lines {
+            let trimmed = line.trim();
+
+            if in_field && !trimmed.is_empty() && line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = fields.last_mut() {
+                    if !value.is_empty() {
+                        value.push('\n');
+                    }
+                    value.push_str(trimmed);
+                }
+                continue;
+            }
+            in_field = false;
+
+            if trimmed == "---" {
+                continue;
+            }
+
+            if trimmed.starts_with(OTPAUTH_SCHEME) {
+                otpauth.push(trimmed.to_string());
+                continue;
+            }
+
+            if let Some((key, value)) = parse_field(line) {
+                if value.starts_with(OTPAUTH_SCHEME) {
+                    otpauth.push(value.to_string());
+                }
+                fields.push((key.to_string(), value.to_string()));
+                in_field = true;
+                continue;
+            }
+
+            notes.push(line);
+        }
+
+        // Drop blank lines surrounding the notes, but keep any inside them.
+        let notes = notes.join("\n").trim_matches('\n').to_string();
+
+        Self {
+            password,
+            fields,
+            notes,
+            otpauth,
+            raw: text.to_string(),
+        }
+    }
+
+    /// First field with the given name, ignoring case.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The login name, under any of the field names commonly used for it.
+    pub fn username(&self) -> Option<&str> {
+        ["username", "user", "login", "email"]
+            .iter()
+            .find_map(|name| self.field(name))
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        ["url", "website", "site"]
+            .iter()
+            .find_map(|name| self.field(name))
+    }
+
+    /** A copy of the entry with a new password, like `pass generate --in-place`: only the first
+     * line is replaced, the rest of the text is untouched.
+     */
+    pub fn with_password(&self, password: &str) -> Self {
+        let rest = self
+            .raw
+            .find('\n')
+            .map_or("\n", |newline| &self.raw[newline..]);
+        Self::parse(&format!("{}{}", password, rest))
+    }
+
+    /** A copy of the entry with an otpauth URI swapped for another, e.g. after advancing a HOTP
+     * counter. The rest of the text is untouched.
+     */
+    pub fn replace_otpauth(&self, old: &str, new: &str) -> Self {
+        Self::parse(&self.raw.replacen(old, new, 1))
+    }
+}
+
+impl Display for PassEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/** Split a `key: value` line, where the key is a single word.
+*/
+fn parse_field(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = match line.split_once(": ") {
+        Some((key, value)) => (key, value),
+        None => (line.strip_suffix(':')?, ""),
+    };
+
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((key, value.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::SAMPLE_CONTENT;
+
+    #[test]
+    fn test_parse_sample_entry() {
+        let entry = PassEntry::parse(SAMPLE_CONTENT);
+
+        assert_eq!(entry.password, "4gG2y&9?-]AAE(wUnD]v22zs\"nx}ad");
+        assert_eq!(
+            entry.fields,
+            vec![
+                ("username".to_string(), "sample@example.com".to_string()),
+                ("some_key".to_string(), "foobar".to_string()),
+            ]
+        );
+        assert_eq!(entry.username(), Some("sample@example.com"));
+        assert_eq!(entry.field("SOME_KEY"), Some("foobar"));
+        assert!(entry.notes.is_empty());
+        assert!(entry.otpauth.is_empty());
+        assert_eq!(entry.to_string(), SAMPLE_CONTENT);
+    }
+
+    #[test]
+    fn test_parse_fields_notes_and_otp() {
+        let content = concat!(
+            "hunter2\n",
+            "login: sam\n",
+            "url: https://example.com/login\n",
+            "recovery:\n",
+            "  abcd-efgh\n",
+            "  ijkl-mnop\n",
+            "otpauth://totp/Example:sam?secret=JBSWY3DPEHPK3PXP&issuer=Example\n",
+            "\n",
+            "Security question answers are\n",
+            "in the other entry.\n",
+        );
+        let entry = PassEntry::parse(content);
+
+        assert_eq!(entry.password, "hunter2");
+        assert_eq!(entry.username(), Some("sam"));
+        assert_eq!(entry.url(), Some("https://example.com/login"));
+        assert_eq!(entry.field("recovery"), Some("abcd-efgh\nijkl-mnop"));
+        assert_eq!(
+            entry.otpauth,
+            vec!["otpauth://totp/Example:sam?secret=JBSWY3DPEHPK3PXP&issuer=Example"]
+        );
+        assert_eq!(
+            entry.notes,
+            "Security question answers are\nin the other entry."
+        );
+        assert_eq!(entry.to_string(), content);
+    }
+
+    #[test]
+    fn test_with_password() {
+        let entry = PassEntry::parse(SAMPLE_CONTENT).with_password("correct horse");
+        assert_eq!(entry.password, "correct horse");
+        assert_eq!(entry.username(), Some("sample@example.com"));
+        assert_eq!(
+            entry.to_string(),
+            SAMPLE_CONTENT.replacen("4gG2y&9?-]AAE(wUnD]v22zs\"nx}ad", "correct horse", 1)
+        );
+
+        assert_eq!(
+            PassEntry::parse("").with_password("new").to_string(),
+            "new\n"
+        );
+    }
+
+    #[test]
+    fn test_replace_otpauth() {
+        let content = "hunter2\notp: otpauth://hotp/sam?secret=GEZDGNBV&counter=1\nnotes\n";
+        let entry = PassEntry::parse(content).replace_otpauth(
+            "otpauth://hotp/sam?secret=GEZDGNBV&counter=1",
+            "otpauth://hotp/sam?secret=GEZDGNBV&counter=2",
+        );
+
+        assert_eq!(
+            entry.otpauth,
+            vec!["otpauth://hotp/sam?secret=GEZDGNBV&counter=2"]
+        );
+        assert_eq!(
+            entry.to_string(),
+            "hunter2\notp: otpauth://hotp/sam?secret=GEZDGNBV&counter=2\nnotes\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_password_only() {
+        let entry = PassEntry::parse("hunter2");
+        assert_eq!(entry.password, "hunter2");
+        assert!(entry.fields.is_empty());
+        assert!(entry.notes.is_empty());
+
+        let entry = PassEntry::parse("");
+        assert_eq!(entry.password, "");
+    }
+}
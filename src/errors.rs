@@ -4,6 +4,7 @@ use std::io;
 use std::path::PathBuf;
 use std::string::FromUtf8Error;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Error {
     InvalidPath { path: PathBuf },
@@ -12,10 +13,52 @@ pub enum Error {
     PGPError { err: pgp::errors::Error },
     UnsupportedMessageType { err: String },
     GeneralError { err: String },
+    InvalidConfig { path: PathBuf, err: String },
+    BadPassphrase { key_id: String },
+    ClipboardError { err: String },
+    InvalidOtp { err: String },
+    InvalidPolicy { err: String },
+    NotInStore { name: String },
+    KeyLocked { key_id: String, key_user: String },
+    OutsideStore { name: String },
+    EmptyStore,
+    NoField { name: String, field: String },
+    NoLine { line: usize },
+    NoDisplay,
+    InvalidArguments { err: String },
 }
 
 impl std::error::Error for Error {}
 
+impl Error {
+    /** Identifier for the kind of error, for machine-readable output. These are part of the
+     * JSON schema, so existing codes must not change.
+     */
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidPath { .. } => "invalid_path",
+            Self::IOError { .. } => "io_error",
+            Self::NoKey { .. } => "no_key",
+            Self::PGPError { .. } => "pgp_error",
+            Self::UnsupportedMessageType { .. } => "unsupported_message_type",
+            Self::GeneralError { .. } => "general_error",
+            Self::InvalidConfig { .. } => "invalid_config",
+            Self::BadPassphrase { .. } => "bad_passphrase",
+            Self::ClipboardError { .. } => "clipboard_error",
+            Self::InvalidOtp { .. } => "invalid_otp",
+            Self::InvalidPolicy { .. } => "invalid_policy",
+            Self::NotInStore { .. } => "not_in_store",
+            Self::KeyLocked { .. } => "key_locked",
+            Self::OutsideStore { .. } => "outside_store",
+            Self::EmptyStore => "empty_store",
+            Self::NoField { .. } => "no_field",
+            Self::NoLine { .. } => "no_line",
+            Self::NoDisplay => "no_display",
+            Self::InvalidArguments { .. } => "invalid_arguments",
+        }
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -37,6 +80,58 @@ impl Display for Error {
             Self::GeneralError { err } => {
                 write!(f, "{}", err)
             }
+            Self::BadPassphrase { key_id } => {
+                write!(f, "Incorrect passphrase for key '{}'.", key_id)
+            }
+            Self::ClipboardError { err } => {
+                write!(f, "Clipboard error: {}", err)
+            }
+            Self::InvalidOtp { err } => {
+                write!(f, "Invalid otpauth URI: {}", err)
+            }
+            Self::InvalidPolicy { err } => {
+                write!(f, "Invalid password policy: {}", err)
+            }
+            Self::NotInStore { name } => {
+                write!(f, "{} is not in the password store.", name)
+            }
+            Self::KeyLocked { key_id, key_user } => {
+                write!(
+                    f,
+                    "Key {} ({}) is locked, and there's no terminal to ask for its passphrase.",
+                    key_user, key_id
+                )
+            }
+            Self::OutsideStore { name } => {
+                write!(f, "'{}' is outside the password store.", name)
+            }
+            Self::EmptyStore => {
+                write!(f, "password store is empty. Try \"passrs init\".")
+            }
+            Self::NoField { name, field } => {
+                write!(f, "{} has no field '{}'.", name, field)
+            }
+            Self::NoLine { line } => {
+                write!(
+                    f,
+                    "There is no password to put on the clipboard at line {}.",
+                    line
+                )
+            }
+            Self::NoDisplay => {
+                write!(f, "No display server found to copy to.")
+            }
+            Self::InvalidArguments { err } => {
+                write!(f, "{}", err)
+            }
+            Self::InvalidConfig { path, err } => {
+                write!(
+                    f,
+                    "Invalid configuration in '{}': {}",
+                    path.to_string_lossy(),
+                    err
+                )
+            }
         }
     }
 }
@@ -47,6 +142,12 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<nix::errno::Errno> for Error {
+    fn from(error: nix::errno::Errno) -> Self {
+        Error::IOError { err: error.into() }
+    }
+}
+
 impl From<pgp::errors::Error> for Error {
     fn from(error: pgp::errors::Error) -> Self {
         Error::PGPError { err: error }
@@ -55,6 +156,8 @@ impl From<pgp::errors::Error> for Error {
 
 impl From<FromUtf8Error> for Error {
     fn from(error: FromUtf8Error) -> Self {
-        Error::GeneralError { err: error.to_string() }
+        Error::GeneralError {
+            err: error.to_string(),
+        }
     }
 }
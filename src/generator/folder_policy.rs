@@ -0,0 +1,208 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::{CharClass, PasswordPolicy};
+use crate::errors::Error;
+use crate::pass_scanner::find_store_file;
+
+/// File holding the password rules for the entries in its directory and below.
+pub const POLICY_FILE: &str = ".passrs-policy";
+
+/** Password rules for a folder of the store, read from a `.passrs-policy` TOML file:
+ *
+ * ```toml
+ * max_length = 16
+ * symbols = false
+ * min_digits = 1
+ * ```
+ *
+ * Every field is optional. A class is allowed unless set to `false`, and generated passwords
+ * include at least one character from each allowed class unless its minimum says otherwise.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FolderPolicy {
+    /// Length generated passwords use, instead of the configured default.
+    pub length: Option<usize>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub upper: Option<bool>,
+    pub lower: Option<bool>,
+    pub digits: Option<bool>,
+    pub symbols: Option<bool>,
+    pub min_upper: Option<usize>,
+    pub min_lower: Option<usize>,
+    pub min_digits: Option<usize>,
+    pub min_symbols: Option<usize>,
+    pub exclude_ambiguous: bool,
+}
+
+impl FolderPolicy {
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|err| Error::InvalidConfig {
+            path: path.to_path_buf(),
+            err: err.to_string(),
+        })
+    }
+
+    fn allows(&self, class: CharClass) -> bool {
+        let allowed = match class {
+            CharClass::Upper => self.upper,
+            CharClass::Lower => self.lower,
+            CharClass::Digits => self.digits,
+            CharClass::Symbols => self.symbols,
+        };
+        allowed.unwrap_or(true)
+    }
+
+    fn minimum(&self, class: CharClass) -> Option<usize> {
+        match class {
+            CharClass::Upper => self.min_upper,
+            CharClass::Lower => self.min_lower,
+            CharClass::Digits => self.min_digits,
+            CharClass::Symbols => self.min_symbols,
+        }
+    }
+
+    /** The generator policy for this folder, starting from `default_length` when the file doesn't
+     * set a length, and keeping it within the file's bounds.
+     */
+    pub fn password_policy(&self, default_length: usize) -> PasswordPolicy {
+        let mut length = self.length.unwrap_or(default_length);
+        if let Some(max_length) = self.max_length {
+            length = length.min(max_length);
+        }
+        if let Some(min_length) = self.min_length {
+            length = length.max(min_length);
+        }
+
+        PasswordPolicy {
+            length,
+            classes: CharClass::ALL
+                .into_iter()
+                .filter(|class| self.allows(*class))
+                .map(|class| (class, self.minimum(class).unwrap_or(1)))
+                .collect(),
+            exclude_ambiguous: self.exclude_ambiguous,
+        }
+    }
+
+    /** Every way the password breaks this policy; empty when it complies. Characters outside the
+     * four classes, such as spaces, count as symbols.
+     */
+    pub fn violations(&self, password: &str) -> Vec<String> {
+        let mut violations = vec![];
+        let length = password.chars().count();
+        if let Some(min_length) = self.min_length.filter(|min| length < *min) {
+            violations.push(format!("shorter than {} characters", min_length));
+        }
+        if let Some(max_length) = self.max_length.filter(|max| length > *max) {
+            violations.push(format!("longer than {} characters", max_length));
+        }
+
+        for class in CharClass::ALL {
+            let count = password
+                .chars()
+                .filter(|c| CharClass::of(*c) == class)
+                .count();
+            let name = class.to_string().to_lowercase();
+            if !self.allows(class) && count > 0 {
+                violations.push(format!("contains {}", name));
+            }
+            if let Some(minimum) = self.minimum(class).filter(|min| count < *min) {
+                violations.push(format!("needs {} or more {}", minimum, name));
+            }
+        }
+        violations
+    }
+}
+
+/** The `.passrs-policy` that applies to an entry, if there is one.
+*/
+pub fn entry_policy(
+    entry_path: &Path,
+    store_path: &Path,
+) -> Result<Option<(PathBuf, FolderPolicy)>, Error> {
+    match find_store_file(entry_path, store_path, POLICY_FILE)? {
+        Some(path) => {
+            let policy = FolderPolicy::from_file(&path)?;
+            Ok(Some((path, policy)))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::generate;
+    use crate::test_util::TmpTree;
+    use anyhow::Result;
+
+    #[test]
+    fn test_policy_resolution() -> Result<()> {
+        let tmp_tree = TmpTree::new();
+        let store = &tmp_tree.base_path;
+        std::fs::write(store.join(POLICY_FILE), "max_length = 16\n")?;
+        std::fs::create_dir_all(store.join("bank"))?;
+        std::fs::write(
+            store.join("bank").join(POLICY_FILE),
+            "symbols = false\nmin_digits = 2\n",
+        )?;
+
+        let (path, policy) = entry_policy(&store.join("a/target.gpg"), store)?.unwrap();
+        assert_eq!(path, store.join(POLICY_FILE));
+        assert_eq!(policy.max_length, Some(16));
+
+        // The nearest file wins outright, it isn't merged with the ones above it.
+        let (_, policy) = entry_policy(&store.join("bank/new.gpg"), store)?.unwrap();
+        assert_eq!(policy.max_length, None);
+        assert_eq!(policy.symbols, Some(false));
+
+        std::fs::write(store.join("bank").join(POLICY_FILE), "max_len = 3\n")?;
+        assert!(matches!(
+            entry_policy(&store.join("bank/new.gpg"), store),
+            Err(Error::InvalidConfig { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generated_passwords_comply() -> Result<()> {
+        let policy: FolderPolicy =
+            toml::from_str("max_length = 16\nsymbols = false\nmin_digits = 3")?;
+
+        let generator_policy = policy.password_policy(25);
+        assert_eq!(generator_policy.length, 16);
+        assert!(!generator_policy.includes(CharClass::Symbols));
+
+        for _ in 0..20 {
+            let password = generate(&generator_policy)?;
+            assert_eq!(policy.violations(&password), Vec::<String>::new());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_violations() -> Result<()> {
+        let policy: FolderPolicy =
+            toml::from_str("min_length = 8\nmax_length = 12\nsymbols = false\nmin_digits = 1")?;
+
+        assert!(policy.violations("abcdef12").is_empty());
+        assert_eq!(
+            policy.violations("abc de!"),
+            vec![
+                "shorter than 8 characters",
+                "needs 1 or more digits",
+                "contains symbols"
+            ]
+        );
+        assert_eq!(
+            policy.violations("abcdefghijklm1"),
+            vec!["longer than 12 characters"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+pub mod folder_policy;
+pub mod passphrase;
+
+use rand::{rngs::OsRng, seq::SliceRandom, CryptoRng, Rng};
+use zeroize::Zeroizing;
+
+use crate::errors::Error;
+
+/// Length `pass generate` uses when none is given.
+pub const DEFAULT_LENGTH: usize = 25;
+
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const DIGITS: &str = "0123456789";
+/// The POSIX `[:punct:]` class, which `pass generate` draws symbols from.
+const SYMBOLS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+/// Characters easily mistaken for one another when read or typed.
+const AMBIGUOUS: &str = "0Oo1lI|";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Upper,
+    Lower,
+    Digits,
+    Symbols,
+}
+
+impl CharClass {
+    pub const ALL: [CharClass; 4] = [Self::Upper, Self::Lower, Self::Digits, Self::Symbols];
+
+    /** The class a character belongs to, counting anything that isn't a letter or digit as a
+     * symbol.
+     */
+    pub fn of(c: char) -> Self {
+        [Self::Upper, Self::Lower, Self::Digits]
+            .into_iter()
+            .find(|class| class.chars().contains(c))
+            .unwrap_or(Self::Symbols)
+    }
+
+    fn chars(self) -> &'static str {
+        match self {
+            Self::Upper => UPPER,
+            Self::Lower => LOWER,
+            Self::Digits => DIGITS,
+            Self::Symbols => SYMBOLS,
+        }
+    }
+}
+
+impl std::fmt::Display for CharClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Upper => write!(f, "Uppercase"),
+            Self::Lower => write!(f, "Lowercase"),
+            Self::Digits => write!(f, "Digits"),
+            Self::Symbols => write!(f, "Symbols"),
+        }
+    }
+}
+
+/** What a generated password must look like.
+ *
+ * A class with a minimum count of zero may still appear; a disabled class never does.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    /// Enabled classes, with the minimum number of characters from each.
+    pub classes: Vec<(CharClass, usize)>,
+    pub exclude_ambiguous: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            length: DEFAULT_LENGTH,
+            classes: CharClass::ALL.iter().map(|class| (*class, 1)).collect(),
+            exclude_ambiguous: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /** Letters and digits only, like `pass generate --no-symbols`.
+     */
+    pub fn no_symbols(length: usize) -> Self {
+        Self {
+            length,
+            classes: vec![
+                (CharClass::Upper, 1),
+                (CharClass::Lower, 1),
+                (CharClass::Digits, 1),
+            ],
+            exclude_ambiguous: false,
+        }
+    }
+
+    pub fn includes(&self, class: CharClass) -> bool {
+        self.classes.iter().any(|(enabled, _)| *enabled == class)
+    }
+
+    /** Enable or disable a class, requiring at least one of it when enabled.
+     */
+    pub fn set_class(&mut self, class: CharClass, enabled: bool) {
+        self.classes.retain(|(existing, _)| *existing != class);
+        if enabled {
+            self.classes.push((class, 1));
+            self.classes
+                .sort_by_key(|(class, _)| CharClass::ALL.iter().position(|c| c == class));
+        }
+    }
+
+    fn alphabet(&self, class: CharClass) -> Vec<char> {
+        class
+            .chars()
+            .chars()
+            .filter(|c| !(self.exclude_ambiguous && AMBIGUOUS.contains(*c)))
+            .collect()
+    }
+
+    /** Bits of entropy in a password from this policy, treating every character as drawn from
+     * all enabled classes. The minimum counts make this a slight overestimate.
+     */
+    pub fn entropy(&self) -> f64 {
+        let alphabet: usize = self
+            .classes
+            .iter()
+            .map(|(class, _)| self.alphabet(*class).len())
+            .sum();
+        self.length as f64 * (alphabet as f64).log2()
+    }
+
+    /** Check the policy can be satisfied at all.
+     */
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |err: String| Err(Error::InvalidPolicy { err });
+
+        if self.classes.is_empty() {
+            return invalid("at least one character class is needed".to_string());
+        }
+        if self.length == 0 {
+            return invalid("length must be at least 1".to_string());
+        }
+        let required: usize = self.classes.iter().map(|(_, min)| min).sum();
+        if required > self.length {
+            return invalid(format!(
+                "minimum counts add up to {}, more than the length of {}",
+                required, self.length
+            ));
+        }
+        Ok(())
+    }
+}
+
+/** Generate a password from the operating system's CSPRNG.
+*/
+pub fn generate(policy: &PasswordPolicy) -> Result<Zeroizing<String>, Error> {
+    generate_with(policy, &mut OsRng)
+}
+
+/** Generate a password with the given cryptographically secure RNG.
+ *
+ * Each class's minimum is drawn first, the remainder comes from every enabled class, and the
+ * result is shuffled so the required characters don't sit at the front.
+*/
+pub fn generate_with<R: Rng + CryptoRng>(
+    policy: &PasswordPolicy,
+    rng: &mut R,
+) -> Result<Zeroizing<String>, Error> {
+    policy.validate()?;
+
+    let mut all = vec![];
+    let mut password = Zeroizing::new(Vec::with_capacity(policy.length));
+    for (class, min) in &policy.classes {
+        let alphabet = policy.alphabet(*class);
+        for _ in 0..*min {
+            password.push(
+                *alphabet
+                    .choose(rng)
+                    .expect("character classes are non-empty"),
+            );
+        }
+        all.extend(alphabet);
+    }
+    while password.len() < policy.length {
+        password.push(*all.choose(rng).expect("character classes are non-empty"));
+    }
+    password.shuffle(rng);
+
+    Ok(Zeroizing::new(password.iter().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn count(password: &str, class: CharClass) -> usize {
+        password
+            .chars()
+            .filter(|c| class.chars().contains(*c))
+            .count()
+    }
+
+    #[test]
+    fn test_generate_default_policy() -> Result<(), Error> {
+        let password = generate(&PasswordPolicy::default())?;
+        assert_eq!(password.chars().count(), DEFAULT_LENGTH);
+        for class in CharClass::ALL {
+            assert!(count(&password, class) >= 1, "{} missing", class);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_honours_policy() -> Result<(), Error> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let policy = PasswordPolicy {
+            length: 12,
+            classes: vec![(CharClass::Digits, 4), (CharClass::Upper, 2)],
+            exclude_ambiguous: true,
+        };
+
+        for _ in 0..50 {
+            let password = generate_with(&policy, &mut rng)?;
+            assert_eq!(password.len(), 12);
+            assert!(count(&password, CharClass::Digits) >= 4);
+            assert!(count(&password, CharClass::Upper) >= 2);
+            assert_eq!(count(&password, CharClass::Lower), 0);
+            assert!(!password.contains(|c| AMBIGUOUS.contains(c)));
+        }
+
+        // 24 unambiguous capitals and 8 digits, then 24 lowercase letters on top.
+        assert!((policy.entropy() - 12.0 * 32f64.log2()).abs() < 1e-9);
+        let expected = 12.0 * 56f64.log2();
+        let no_symbols = PasswordPolicy {
+            exclude_ambiguous: true,
+            ..PasswordPolicy::no_symbols(12)
+        };
+        assert!((no_symbols.entropy() - expected).abs() < 1e-9);
+
+        let password = generate_with(&PasswordPolicy::no_symbols(40), &mut rng)?;
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_policies() {
+        let mut policy = PasswordPolicy::default();
+        policy.set_class(CharClass::Symbols, false);
+        assert!(!policy.includes(CharClass::Symbols));
+        policy.set_class(CharClass::Symbols, true);
+        assert_eq!(policy, PasswordPolicy::default());
+
+        let impossible = [
+            PasswordPolicy {
+                classes: vec![],
+                ..PasswordPolicy::default()
+            },
+            PasswordPolicy {
+                length: 3,
+                ..PasswordPolicy::default()
+            },
+            PasswordPolicy {
+                length: 0,
+                classes: vec![(CharClass::Lower, 0)],
+                ..PasswordPolicy::default()
+            },
+        ];
+        for policy in impossible {
+            assert!(matches!(
+                generate(&policy),
+                Err(Error::InvalidPolicy { .. })
+            ));
+        }
+    }
+}
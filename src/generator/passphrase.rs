@@ -0,0 +1,222 @@
+use std::path::Path;
+
+use rand::{rngs::OsRng, seq::SliceRandom, CryptoRng, Rng};
+use zeroize::Zeroizing;
+
+use crate::errors::Error;
+
+/// Six words from a 7776 word list like EFF's large wordlist give roughly 77 bits, EFF's own
+/// recommendation.
+pub const DEFAULT_WORDS: usize = 6;
+pub const DEFAULT_SEPARATOR: &str = "-";
+
+/** Distinct words a passphrase is drawn from.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    /** Load a user-supplied wordlist, such as EFF's `eff_large_wordlist.txt`.
+     */
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path)?).map_err(|err| match err {
+            Error::InvalidPolicy { err } => Error::InvalidPolicy {
+                err: format!("wordlist '{}' {}", path.to_string_lossy(), err),
+            },
+            err => err,
+        })
+    }
+
+    /** One word per line. Blank lines and `#` comments are skipped, as is a leading dice number
+     * like the EFF lists have, and duplicates are dropped so they can't skew the entropy.
+     */
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut words: Vec<String> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_whitespace().last())
+            .map(str::to_string)
+            .collect();
+        words.sort();
+        words.dedup();
+
+        if words.len() < 2 {
+            return Err(Error::InvalidPolicy {
+                err: format!("has {} distinct words, at least 2 are needed", words.len()),
+            });
+        }
+        Ok(Self { words })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Always false, as parsing rejects lists with fewer than 2 words.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+/** How a passphrase is put together.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassphrasePolicy {
+    pub words: usize,
+    pub separator: String,
+    /// Capitalise the first letter of every word.
+    pub capitalize: bool,
+    /// Append a random digit to one randomly chosen word.
+    pub digit: bool,
+}
+
+impl Default for PassphrasePolicy {
+    fn default() -> Self {
+        Self {
+            words: DEFAULT_WORDS,
+            separator: DEFAULT_SEPARATOR.to_string(),
+            capitalize: false,
+            digit: false,
+        }
+    }
+}
+
+impl PassphrasePolicy {
+    /** Bits of entropy in a passphrase from this policy, assuming an attacker knows the wordlist
+     * and every setting. Capitalising every word adds nothing; the digit adds its own value and
+     * the choice of word it follows.
+     */
+    pub fn entropy(&self, wordlist: &Wordlist) -> f64 {
+        let mut bits = self.words as f64 * (wordlist.len() as f64).log2();
+        if self.digit && self.words > 0 {
+            bits += (10.0 * self.words as f64).log2();
+        }
+        bits
+    }
+}
+
+/** A generated passphrase and its entropy estimate.
+*/
+pub struct Passphrase {
+    pub phrase: Zeroizing<String>,
+    pub entropy: f64,
+}
+
+/** Generate a passphrase from the operating system's CSPRNG.
+*/
+pub fn generate(policy: &PassphrasePolicy, wordlist: &Wordlist) -> Result<Passphrase, Error> {
+    generate_with(policy, wordlist, &mut OsRng)
+}
+
+/** Generate a passphrase with the given cryptographically secure RNG.
+*/
+pub fn generate_with<R: Rng + CryptoRng>(
+    policy: &PassphrasePolicy,
+    wordlist: &Wordlist,
+    rng: &mut R,
+) -> Result<Passphrase, Error> {
+    if policy.words == 0 {
+        return Err(Error::InvalidPolicy {
+            err: "a passphrase needs at least one word".to_string(),
+        });
+    }
+
+    let mut words: Vec<Zeroizing<String>> = (0..policy.words)
+        .map(|_| {
+            let word = wordlist.words.choose(rng).expect("wordlists are non-empty");
+            Zeroizing::new(word.clone())
+        })
+        .collect();
+    if policy.capitalize {
+        for word in &mut words {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                **word = first.to_uppercase().chain(chars).collect();
+            }
+        }
+    }
+    if policy.digit {
+        let index = rng.gen_range(0..words.len());
+        words[index].push(char::from(b'0' + rng.gen_range(0..10u8)));
+    }
+
+    let words: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+    Ok(Passphrase {
+        phrase: Zeroizing::new(words.join(&policy.separator)),
+        entropy: policy.entropy(wordlist),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TmpTree;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_entropy() -> Result<(), Error> {
+        let words: Vec<String> = (0..7776).map(|idx| format!("word{}", idx)).collect();
+        let wordlist = Wordlist::parse(&words.join("\n"))?;
+        assert_eq!(wordlist.len(), 7776);
+
+        // Six words from a list the size of EFF's is the ~77 bit passphrase EFF recommends.
+        let bits = PassphrasePolicy::default().entropy(&wordlist);
+        assert!((77.0..78.0).contains(&bits), "{}", bits);
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_passphrase() -> Result<(), Error> {
+        let wordlist =
+            Wordlist::parse("# comment\n11111\tapple\n11112\tbanana\n\ncherry\napple\n")?;
+        assert_eq!(wordlist.words, vec!["apple", "banana", "cherry"]);
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let policy = PassphrasePolicy {
+            words: 4,
+            separator: " ".to_string(),
+            capitalize: true,
+            digit: true,
+        };
+        let passphrase = generate_with(&policy, &wordlist, &mut rng)?;
+
+        let words: Vec<&str> = passphrase.phrase.split(' ').collect();
+        assert_eq!(words.len(), 4);
+        assert!(words
+            .iter()
+            .all(|word| word.starts_with(char::is_uppercase)));
+        assert_eq!(
+            words
+                .iter()
+                .filter(|word| word.ends_with(|c: char| c.is_ascii_digit()))
+                .count(),
+            1
+        );
+        let expected = 4.0 * 3f64.log2() + 40f64.log2();
+        assert!((passphrase.entropy - expected).abs() < 1e-9);
+
+        let no_words = PassphrasePolicy {
+            words: 0,
+            ..PassphrasePolicy::default()
+        };
+        assert!(generate(&no_words, &wordlist).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wordlist_from_file() -> Result<(), Error> {
+        let tmp_tree = TmpTree::new();
+        let path = tmp_tree.base_path.join("words.txt");
+        std::fs::write(&path, "only\nonly\n")?;
+
+        let err = Wordlist::from_file(&path).unwrap_err();
+        assert!(err.to_string().contains("words.txt"), "{}", err);
+
+        std::fs::write(&path, "one\ntwo\n")?;
+        assert_eq!(Wordlist::from_file(&path)?.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,467 @@
+use std::{
+    collections::HashMap,
+    fs::{self, DirBuilder, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::ffi::OsStrExt,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::errors::Error;
+use crate::pass_scanner::{Listing, ScanOptions, Scanner, StoreEntry, IGNORE_FILE};
+use crate::pgp::recipients::{self, GPG_ID_FILE};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Bumped whenever the cache layout changes, which discards caches written before.
+const INDEX_VERSION: u32 = 1;
+
+/// A folder modified this recently may change again within the same timestamp, so its cached
+/// listing isn't trusted on the next load.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/** The store's entries as last scanned, with the modification time of every folder they were
+ * found in.
+ *
+ * Adding, removing or renaming a file updates its folder's modification time, so a folder
+ * whose time hasn't changed still holds what the cache says and isn't read again. An entry
+ * rewritten in place keeps its cached size and time until something else changes its folder.
+*/
+#[derive(Debug)]
+pub struct StoreIndex {
+    cache_file: PathBuf,
+    contents: IndexFile,
+    /// Folders the last refresh had to read, rather than take from the cache.
+    rescanned: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexFile {
+    version: u32,
+    store_path: PathBuf,
+    options: ScanOptions,
+    /// Contents of the store's `.passrsignore`, which decides what the listings leave out.
+    ignore: Option<String>,
+    folders: Vec<FolderRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FolderRecord {
+    path: PathBuf,
+    /// None when it was too recent to tell later changes apart.
+    modified: Option<SystemTime>,
+    listing: Listing,
+    /// Entries directly in the folder, their recipients filled in after a refresh.
+    entries: Vec<StoreEntry>,
+    gpg_id: Option<GpgIdRecord>,
+}
+
+/// The folder's own `.gpg-id`, which can be edited in place without touching the folder.
+#[derive(Debug, Serialize, Deserialize)]
+struct GpgIdRecord {
+    modified: Option<SystemTime>,
+    recipients: Vec<String>,
+}
+
+impl StoreIndex {
+    /** Read the cache for a scanner's store. A missing, corrupt or outdated cache, or one made
+     * with other scan options or ignore rules, gives an empty index, which refreshes with a full
+     * scan.
+     */
+    pub fn load(cache_file: &Path, scanner: &Scanner) -> Self {
+        let expected = IndexFile {
+            version: INDEX_VERSION,
+            store_path: scanner.store_path().to_path_buf(),
+            options: scanner.options().clone(),
+            ignore: fs::read_to_string(scanner.store_path().join(IGNORE_FILE)).ok(),
+            folders: vec![],
+        };
+        let contents = match fs::read(cache_file) {
+            Ok(bytes) => match serde_json::from_slice::<IndexFile>(&bytes) {
+                Ok(cached) if cached.describes(&expected) => cached,
+                Ok(_) => {
+                    debug!("Discarding outdated index {}", cache_file.display());
+                    expected
+                }
+                Err(err) => {
+                    warn!("Discarding corrupt index {}: {}", cache_file.display(), err);
+                    expected
+                }
+            },
+            Err(err) if err.kind() == ErrorKind::NotFound => expected,
+            Err(err) => {
+                warn!("Failed to read index {}: {}", cache_file.display(), err);
+                expected
+            }
+        };
+
+        Self {
+            cache_file: cache_file.to_path_buf(),
+            contents,
+            rescanned: 0,
+        }
+    }
+
+    /** Bring the index up to date with the store, reading only the folders which changed, and
+     * return the entries in name order.
+     */
+    pub fn refresh(&mut self, scanner: &Scanner) -> Result<Vec<StoreEntry>, Error> {
+        let mut cached: HashMap<PathBuf, FolderRecord> = self
+            .contents
+            .folders
+            .drain(..)
+            .map(|record| (record.path.clone(), record))
+            .collect();
+        let mut folders = vec![];
+        let mut rescanned = 0;
+        let now = SystemTime::now();
+
+        scanner.walk_with(scanner.store_path(), |folder| {
+            let modified = fs::metadata(folder)?.modified().ok();
+            let record = match cached.remove(folder) {
+                Some(mut record) if record.modified.is_some() && record.modified == modified => {
+                    record.check_gpg_id(now);
+                    record
+                }
+                _ => {
+                    rescanned += 1;
+                    FolderRecord::scan(scanner, folder, trusted(modified, now), now)?
+                }
+            };
+            let listing = record.listing.clone();
+            folders.push(record);
+            Ok(listing)
+        })?;
+        debug!(
+            "Read {} of {} folders, the rest from the index",
+            rescanned,
+            folders.len()
+        );
+
+        // Folders are walked after the folder they're in, so the recipients applying to it are
+        // known by the time its own are looked at.
+        let mut applying: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        let mut entries = vec![];
+        for record in &mut folders {
+            let recipients = match &record.gpg_id {
+                Some(gpg_id) => gpg_id.recipients.clone(),
+                None => record
+                    .path
+                    .parent()
+                    .and_then(|parent| applying.get(parent))
+                    .cloned()
+                    .unwrap_or_default(),
+            };
+            for entry in &mut record.entries {
+                entry.recipients_hint = recipients.clone();
+            }
+            entries.extend(record.entries.iter().cloned());
+            applying.insert(record.path.clone(), recipients);
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        self.contents.folders = folders;
+        self.rescanned = rescanned;
+        Ok(entries)
+    }
+
+    /** Whether there's nothing cached, so a refresh has to scan the whole store.
+     */
+    pub fn is_empty(&self) -> bool {
+        self.contents.folders.is_empty()
+    }
+
+    /** How many folders the last refresh read from the store.
+     */
+    pub fn rescanned(&self) -> usize {
+        self.rescanned
+    }
+
+    /** Write the index out, readable only by the user since it names every entry.
+     */
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(cache_dir) = self.cache_file.parent() {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(cache_dir)?;
+        }
+        let data = serde_json::to_vec(&self.contents).map_err(std::io::Error::from)?;
+
+        // Written aside and renamed over, so an interrupted save can't leave half an index.
+        let tmp_file = self.cache_file.with_extension("tmp");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp_file)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        fs::rename(&tmp_file, &self.cache_file)?;
+        Ok(())
+    }
+}
+
+impl IndexFile {
+    /// Whether the cache was made for the same store, scanned the same way.
+    fn describes(&self, expected: &IndexFile) -> bool {
+        self.version == expected.version
+            && self.store_path == expected.store_path
+            && self.options == expected.options
+            && self.ignore == expected.ignore
+    }
+}
+
+impl FolderRecord {
+    fn scan(
+        scanner: &Scanner,
+        folder: &Path,
+        modified: Option<SystemTime>,
+        now: SystemTime,
+    ) -> Result<Self, Error> {
+        let listing = scanner.list_folder(folder)?;
+        let entries = listing
+            .files
+            .iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "gpg"))
+            .filter_map(|path| {
+                match StoreEntry::with_recipients(path, scanner.store_path(), vec![]) {
+                    Ok(entry) => Some(entry),
+                    Err(err) => {
+                        warn!("Skipping {}: {}", path.display(), err);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        let mut record = Self {
+            path: folder.to_path_buf(),
+            modified,
+            listing,
+            entries,
+            gpg_id: None,
+        };
+        record.check_gpg_id(now);
+        Ok(record)
+    }
+
+    /// Read the folder's `.gpg-id` again if it changed since it was cached.
+    fn check_gpg_id(&mut self, now: SystemTime) {
+        let gpg_id_path = self.path.join(GPG_ID_FILE);
+        let modified = match fs::metadata(&gpg_id_path) {
+            Ok(metadata) if metadata.is_file() => metadata.modified().ok(),
+            _ => {
+                self.gpg_id = None;
+                return;
+            }
+        };
+        if let Some(gpg_id) = &self.gpg_id {
+            if gpg_id.modified.is_some() && gpg_id.modified == modified {
+                return;
+            }
+        }
+
+        let recipients = match fs::read_to_string(&gpg_id_path) {
+            Ok(contents) => recipients::parse_gpg_id(&contents),
+            Err(err) => {
+                warn!("Failed to read {}: {}", gpg_id_path.display(), err);
+                vec![]
+            }
+        };
+        self.gpg_id = Some(GpgIdRecord {
+            modified: trusted(modified, now),
+            recipients,
+        });
+    }
+}
+
+/// A modification time, unless it's too recent to rule out changes within the same timestamp.
+fn trusted(modified: Option<SystemTime>, now: SystemTime) -> Option<SystemTime> {
+    modified.filter(|modified| {
+        now.duration_since(*modified)
+            .is_ok_and(|age| age >= RACY_WINDOW)
+    })
+}
+
+/** Where the index of a store is cached: `$XDG_CACHE_HOME/passrs`, one file per store.
+*/
+pub fn cache_path(store_path: &Path) -> PathBuf {
+    cache_file(store_path, |key| std::env::var(key).ok())
+}
+
+fn cache_file<F>(store_path: &Path, vars: F) -> PathBuf
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut cache_dir = match vars("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home::home_dir().unwrap_or_default().join(".cache"),
+    };
+    let digest = Sha256::digest(store_path.as_os_str().as_bytes());
+    cache_dir.push("passrs");
+    cache_dir.push(format!("index-{}.json", hex::encode(&digest[..8])));
+    cache_dir
+}
+
+/** The entries of the scanner's store, in name order, taking whatever is still valid from the
+ * cached index and saving it refreshed. Any trouble with the cache only costs a full scan.
+*/
+pub fn load_entries(scanner: &Scanner) -> Result<Vec<StoreEntry>, Error> {
+    load_entries_from(&cache_path(scanner.store_path()), scanner)
+}
+
+/** The entries of the scanner's store from its cached index, brought up to date, or None when
+ * there's no usable cache to start from, leaving the scan to the caller.
+*/
+pub fn cached_entries(scanner: &Scanner) -> Option<Vec<StoreEntry>> {
+    let mut index = StoreIndex::load(&cache_path(scanner.store_path()), scanner);
+    if index.is_empty() {
+        return None;
+    }
+    match refresh_and_save(&mut index, scanner) {
+        Ok(entries) => Some(entries),
+        Err(err) => {
+            warn!("Failed to refresh index: {}", err);
+            None
+        }
+    }
+}
+
+fn load_entries_from(cache_file: &Path, scanner: &Scanner) -> Result<Vec<StoreEntry>, Error> {
+    let mut index = StoreIndex::load(cache_file, scanner);
+    match refresh_and_save(&mut index, scanner) {
+        Ok(entries) => Ok(entries),
+        Err(err) => {
+            warn!("Failed to refresh index, scanning the store: {}", err);
+            scanner.collect_store_entries(scanner.store_path())
+        }
+    }
+}
+
+fn refresh_and_save(index: &mut StoreIndex, scanner: &Scanner) -> Result<Vec<StoreEntry>, Error> {
+    let entries = index.refresh(scanner)?;
+    info!(
+        "Indexed {} entries, reading {} folders",
+        entries.len(),
+        index.rescanned()
+    );
+    if let Err(err) = index.save() {
+        warn!(
+            "Failed to save index {}: {}",
+            index.cache_file.display(),
+            err
+        );
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{init_logs, TmpTree};
+    use anyhow::Result;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::time::UNIX_EPOCH;
+
+    /// Age a file or folder past the racy window, as it would be by the next launch.
+    fn age(path: &Path, secs: u64) -> Result<()> {
+        fs::File::open(path)?.set_modified(UNIX_EPOCH + Duration::from_secs(secs))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_index() -> Result<()> {
+        init_logs();
+        let tmp_tree = TmpTree::new();
+        let store = &tmp_tree.base_path;
+        let cache_file = store.join("cache").join("index.json");
+        fs::create_dir(store.join("cache"))?;
+        fs::write(store.join(IGNORE_FILE), "cache/\n")?;
+        let gpg_id = store.join("a").join(GPG_ID_FILE);
+        fs::write(&gpg_id, "alice@example.com\n")?;
+        let scanner = Scanner::new(store, ScanOptions::default())?;
+        for path in scanner.collect_folders(store)? {
+            age(&path, 1_600_000_000)?;
+        }
+        age(&gpg_id, 1_600_000_000)?;
+        let full_scan = scanner.collect_store_entries(store)?;
+
+        // Nothing cached yet: everything is read, and the result matches a plain scan.
+        assert_eq!(load_entries_from(&cache_file, &scanner)?, full_scan);
+        let mut index = StoreIndex::load(&cache_file, &scanner);
+        assert!(!index.is_empty());
+        assert_eq!(index.refresh(&scanner)?, full_scan);
+        assert_eq!(index.rescanned(), 0);
+
+        // A new entry is picked up by reading its folder alone.
+        fs::write(store.join("b").join("baz.gpg"), "")?;
+        age(&store.join("b"), 1_600_000_100)?;
+        let mut index = StoreIndex::load(&cache_file, &scanner);
+        let entries = index.refresh(&scanner)?;
+        assert_eq!(index.rescanned(), 1);
+        assert_eq!(entries, scanner.collect_store_entries(store)?);
+        index.save()?;
+
+        // Recipients follow a `.gpg-id` edited in place.
+        fs::write(&gpg_id, "bob@example.com\n")?;
+        let mut index = StoreIndex::load(&cache_file, &scanner);
+        let entries = index.refresh(&scanner)?;
+        assert_eq!(index.rescanned(), 0);
+        let target = entries
+            .iter()
+            .find(|entry| entry.name == "a/target")
+            .unwrap();
+        assert_eq!(target.recipients_hint, vec!["bob@example.com"]);
+        assert_eq!(entries, scanner.collect_store_entries(store)?);
+
+        // An entry that can't be described is left out, not the rest of its folder.
+        fs::write(store.join("b").join(OsStr::from_bytes(b"\xff.gpg")), "")?;
+        age(&store.join("b"), 1_600_000_200)?;
+        let mut index = StoreIndex::load(&cache_file, &scanner);
+        let entries = index.refresh(&scanner)?;
+        assert_eq!(index.rescanned(), 1);
+        assert!(entries.iter().any(|entry| entry.name == "b/baz"));
+        assert_eq!(entries, scanner.collect_store_entries(store)?);
+
+        // Other scan options, or a corrupt cache, mean a full scan.
+        let other = Scanner::new(
+            store,
+            ScanOptions {
+                include_vcs: true,
+                ..ScanOptions::default()
+            },
+        )?;
+        let mut index = StoreIndex::load(&cache_file, &other);
+        index.refresh(&other)?;
+        assert_eq!(index.rescanned(), 3);
+
+        fs::write(&cache_file, "{ not json")?;
+        let mut index = StoreIndex::load(&cache_file, &scanner);
+        assert!(index.is_empty());
+        assert_eq!(
+            index.refresh(&scanner)?,
+            scanner.collect_store_entries(store)?
+        );
+        assert_eq!(index.rescanned(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_file() {
+        let store = Path::new("/home/user/.password-store");
+        let with_xdg = cache_file(store, |key| {
+            (key == "XDG_CACHE_HOME").then(|| "/tmp/cache".to_string())
+        });
+        assert_eq!(with_xdg.parent(), Some(Path::new("/tmp/cache/passrs")));
+        assert!(with_xdg.to_string_lossy().ends_with(".json"));
+
+        let without = cache_file(store, |_| None);
+        assert!(without.ends_with(Path::new(".cache/passrs").join(with_xdg.file_name().unwrap())));
+        assert_ne!(cache_file(Path::new("/other"), |_| None), without);
+    }
+}
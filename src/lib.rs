@@ -0,0 +1,28 @@
+/*!
+ * Reading and writing `pass` compatible password stores.
+ *
+ * [`Store`] is the entry point: it lists, searches, decrypts and encrypts the entries of a store
+ * configured by [`settings::Settings`]. The OpenPGP handling behind it is internal; the types it
+ * hands out are re-exported here.
+*/
+
+pub mod clipboard;
+pub mod entry;
+pub mod errors;
+pub mod generator;
+pub mod index;
+pub mod otp;
+pub mod pass_scanner;
+mod pgp;
+pub mod settings;
+pub mod store;
+pub mod watcher;
+
+pub use pgp::decrypt::{DecryptedMessage, SignatureStatus};
+pub use pgp::recipients::{GpgIdRecipients, Recipient, GPG_ID_FILE};
+pub use pgp::reencrypt::{ReencryptReport, Reencryption};
+pub use pgp::session::KeySession;
+pub use store::{DecryptOutcome, SearchMatch, Store};
+
+#[cfg(test)]
+mod test_util;
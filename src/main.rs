@@ -1,24 +1,99 @@
-mod errors;
-mod pass_scanner;
-mod settings;
+mod cli;
+#[cfg(feature = "gui")]
 mod ui;
-mod pgp;
 
+use clap::error::ErrorKind;
 use env_logger::Builder;
-use iced::{Application, Settings};
-use log::LevelFilter;
+use log::{error, LevelFilter};
+use passrs::errors::Error;
+use passrs::settings::Settings;
 
-fn main() -> iced::Result {
-    let _log_builder = Builder::from_default_env()
-        .filter(Some("passrs"), LevelFilter::Debug)
+fn main() {
+    let args = match cli::Cli::try_parse_args() {
+        Ok(args) => args,
+        Err(err)
+            if cli::json_requested(std::env::args())
+                && !matches!(
+                    err.kind(),
+                    ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+                ) =>
+        {
+            let err = Error::InvalidArguments {
+                err: err.render().to_string().trim_end().to_string(),
+            };
+            cli::report_failure(&err.into(), true);
+            std::process::exit(2);
+        }
+        Err(err) => err.exit(),
+    };
+    let json = args.command.as_ref().is_some_and(cli::PassCommand::json);
+
+    // Subcommands are used from scripts, so they print their own messages rather than logs.
+    let level = match args.command {
+        Some(_) => LevelFilter::Error,
+        None if cfg!(feature = "gui") => LevelFilter::Debug,
+        None => LevelFilter::Error,
+    };
+    Builder::from_default_env()
+        .filter(Some("passrs"), level)
         .init();
 
-    let mut settings = Settings::default();
-    settings.id = Some("PassRS".to_string());
-    settings.window.always_on_top = true;
+    let config = match Settings::load() {
+        Ok(config) => config,
+        Err(err) if json => {
+            cli::report_failure(&err.into(), true);
+            std::process::exit(1);
+        }
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let code = match args.command {
+        Some(command) => cli::run(command, &config),
+        None => run_gui(config),
+    };
+    std::process::exit(code);
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(config: Settings) -> i32 {
+    use iced::Application;
+
+    let store = match passrs::Store::open(config) {
+        Ok(store) => store,
+        Err(err) => {
+            error!("{}", err);
+            return 1;
+        }
+    };
+    let settings = iced::Settings {
+        id: Some("PassRS".to_string()),
+        window: iced::window::Settings {
+            always_on_top: true,
+            ..Default::default()
+        },
+        ..iced::Settings::with_flags(store)
+    };
+
+    match ui::PassRS::run(settings) {
+        Ok(()) => 0,
+        Err(err) => {
+            error!("{}", err);
+            1
+        }
+    }
+}
 
-    ui::PassRS::run(settings)
+/** Without the GUI, running with no command lists the store, as `pass` does.
+*/
+#[cfg(not(feature = "gui"))]
+fn run_gui(config: Settings) -> i32 {
+    cli::run(cli::PassCommand::list(), &config)
 }
 
+// Shared with the library's tests, so the frontends' tests use only part of it.
 #[cfg(test)]
+#[allow(dead_code)]
 mod test_util;
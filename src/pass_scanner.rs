@@ -1,101 +1,810 @@
 use std::{
-    path::{Path, PathBuf},
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Condvar, Mutex},
+    time::SystemTime,
     vec::Vec,
 };
 
 use crate::errors::Error;
-use log::debug;
+use crate::pgp::recipients::{self, GPG_ID_FILE};
+use crate::settings::Settings;
+use log::{debug, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 
-/** Recursively collect files from the provided base path.
+/// Position of an entry in the list a scan returned.
+pub type EntryId = usize;
+
+/** An entry found in the store.
 */
-pub fn collect_files(base_dir: &Path) -> Result<Vec<PathBuf>, Error> {
-    if !base_dir.is_dir() {
-        return Err(Error::InvalidPath {
-            path: base_dir.to_path_buf(),
-        });
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreEntry {
+    /// Name as `pass` prints it: the path below the store, without the `.gpg` extension.
+    pub name: String,
+    pub path: PathBuf,
+    /// Folders from the store root down to the entry.
+    pub folders: Vec<String>,
+    pub modified: Option<SystemTime>,
+    /// Size of the encrypted file, in bytes.
+    pub size: u64,
+    /// Recipients listed by the `.gpg-id` applying to the entry. It's what the entry should be
+    /// encrypted to, which is only a hint as to what it is encrypted to.
+    pub recipients_hint: Vec<String>,
+}
+
+impl StoreEntry {
+    /** Describe an entry's file, looking up the `.gpg-id` applying to it.
+     */
+    pub fn new(entry_path: &Path, store_path: &Path) -> Result<Self, Error> {
+        let mut gpg_ids = HashMap::new();
+        Self::with_gpg_ids(entry_path, store_path, &mut gpg_ids)
     }
 
-    let mut targets = vec![base_dir.to_owned()];
-    let mut results = vec![];
+    /** Describe an entry's file, remembering the recipients found per folder so a scan reads
+     * every `.gpg-id` only once.
+     */
+    fn with_gpg_ids(
+        entry_path: &Path,
+        store_path: &Path,
+        gpg_ids: &mut HashMap<PathBuf, Vec<String>>,
+    ) -> Result<Self, Error> {
+        let folder = entry_path.parent().unwrap_or(store_path).to_path_buf();
+        let recipients_hint = match gpg_ids.get(&folder) {
+            Some(recipients) => recipients.clone(),
+            None => {
+                let recipients = match find_store_file(entry_path, store_path, GPG_ID_FILE)? {
+                    Some(gpg_id) => match std::fs::read_to_string(&gpg_id) {
+                        Ok(contents) => recipients::parse_gpg_id(&contents),
+                        Err(err) => {
+                            warn!("Failed to read {}: {}", gpg_id.display(), err);
+                            vec![]
+                        }
+                    },
+                    None => vec![],
+                };
+                gpg_ids.insert(folder, recipients.clone());
+                recipients
+            }
+        };
 
-    while !targets.is_empty() {
-        let cwd = targets.pop().ok_or(Error::InvalidPath {
-            path: "unexpected_path".into(),
+        Self::with_recipients(entry_path, store_path, recipients_hint)
+    }
+
+    /** Describe an entry's file, with recipients already looked up.
+     */
+    pub(crate) fn with_recipients(
+        entry_path: &Path,
+        store_path: &Path,
+        recipients_hint: Vec<String>,
+    ) -> Result<Self, Error> {
+        let name = entry_name(entry_path, store_path).ok_or_else(|| Error::InvalidPath {
+            path: entry_path.to_path_buf(),
         })?;
-        debug!("Searching {}", cwd.to_string_lossy());
+        let folders = name
+            .rsplit_once('/')
+            .map(|(folders, _)| folders.split('/').map(str::to_string).collect())
+            .unwrap_or_default();
+        let metadata = std::fs::metadata(entry_path)?;
+
+        Ok(Self {
+            name,
+            path: entry_path.to_path_buf(),
+            folders,
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+            recipients_hint,
+        })
+    }
+}
+
+/** The name of the entry stored in a file, as `pass` prints it. None if the file isn't an entry
+ * below the store.
+*/
+pub fn entry_name(entry_path: &Path, store_path: &Path) -> Option<String> {
+    let relative = entry_path.strip_prefix(store_path).ok()?;
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let name = relative.to_str()?.strip_suffix(".gpg")?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Gitignore-style file at the store root, naming files and folders the scanner skips.
+pub const IGNORE_FILE: &str = ".passrsignore";
+
+/// Version control metadata folders, skipped unless `ScanOptions::include_vcs` is set.
+const VCS_FOLDERS: [&str; 3] = [".git", ".hg", ".svn"];
+
+/** How the store is walked.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ScanOptions {
+    /// Descend into symlinked folders, such as a team's store mounted into one's own.
+    pub follow_symlinks: bool,
+    /// Scan version control metadata folders, such as `.git`, as well.
+    pub include_vcs: bool,
+}
+
+impl From<&Settings> for ScanOptions {
+    fn from(settings: &Settings) -> Self {
+        Self {
+            follow_symlinks: settings.follow_symlinks,
+            ..Self::default()
+        }
+    }
+}
+
+/** Walks a store, skipping version control metadata and whatever `.passrsignore` names.
+ *
+ * Every folder is scanned once however many ways it can be reached, so symlink loops end, and a
+ * folder linked into the store as well as being in it is listed under its own path.
+*/
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    store_path: PathBuf,
+    options: ScanOptions,
+    ignore: IgnoreRules,
+}
+
+/// Files and folders found by a walk.
+#[derive(Default)]
+pub(crate) struct Walk {
+    pub folders: Vec<PathBuf>,
+    pub files: Vec<PathBuf>,
+}
+
+/** How far a parallel scan has got.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanProgress {
+    /// Folders found so far, scanned or still waiting to be.
+    pub folders_found: usize,
+    pub folders_scanned: usize,
+    pub entries: usize,
+}
+
+/** Entries found by a parallel scan since its last batch.
+*/
+#[derive(Debug, Clone)]
+pub struct ScanBatch {
+    pub entries: Vec<StoreEntry>,
+    pub progress: ScanProgress,
+}
+
+/// Entries a scan worker collects before handing them over.
+const SCAN_BATCH_SIZE: usize = 256;
+
+/// Work shared by the threads of a parallel scan.
+#[derive(Default)]
+struct ScanQueue {
+    targets: Vec<PathBuf>,
+    links: Vec<PathBuf>,
+    visited: HashSet<PathBuf>,
+    /// Workers scanning a folder, which may yet queue more.
+    busy: usize,
+    progress: ScanProgress,
+    error: Option<Error>,
+}
+
+/** What a folder directly holds, as far as a scan is concerned.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Listing {
+    pub files: Vec<PathBuf>,
+    pub folders: Vec<PathBuf>,
+    /// Symlinked folders, only listed when they're followed.
+    pub links: Vec<PathBuf>,
+}
+
+impl Scanner {
+    /** A scanner for the store, reading its `.passrsignore` if it has one.
+     */
+    pub fn new(store_path: &Path, options: ScanOptions) -> Result<Self, Error> {
+        let ignore_path = store_path.join(IGNORE_FILE);
+        let ignore = if ignore_path.is_file() {
+            IgnoreRules::parse(&std::fs::read_to_string(&ignore_path)?)
+        } else {
+            IgnoreRules::default()
+        };
+        Ok(Self {
+            store_path: store_path.to_path_buf(),
+            options,
+            ignore,
+        })
+    }
+
+    pub fn store_path(&self) -> &Path {
+        &self.store_path
+    }
+
+    pub fn options(&self) -> &ScanOptions {
+        &self.options
+    }
+
+    /** Whether a path of the store, or any folder it's in, is skipped by scans.
+     */
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.store_path) else {
+            return false;
+        };
+        let mut prefix = self.store_path.clone();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            prefix.push(component);
+            let is_dir = components.peek().is_some() || path.is_dir();
+            if self.skip(&prefix, is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /** Recursively collect the folders to scan at or below a folder.
+     */
+    pub fn collect_folders(&self, folder: &Path) -> Result<Vec<PathBuf>, Error> {
+        Ok(self.walk(folder)?.folders)
+    }
+
+    /** Recursively collect files from the provided base path.
+     */
+    pub fn collect_files(&self, base_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+        let files = self.walk(base_dir)?.files;
+        debug!("Found {} files", files.len());
+        Ok(files)
+    }
+
+    pub fn collect_pass_files(&self, base_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+        debug!(
+            "Searching '{}' for password entries",
+            base_dir.to_string_lossy()
+        );
+
+        let pass_files: Vec<PathBuf> = self
+            .collect_files(base_dir)?
+            .into_iter()
+            .filter(|i| match i.extension() {
+                Some(ext) => ext.to_string_lossy() == "gpg",
+                None => false,
+            })
+            .collect();
+
+        debug!("Found {} entries with .gpg extension", pass_files.len());
+
+        Ok(pass_files)
+    }
+
+    /** Collect the entries at or below a folder of the store, in name order. Entries that can't
+     * be described, such as files whose names aren't valid UTF-8, are skipped with a warning
+     * rather than failing the whole listing.
+     */
+    pub fn collect_store_entries(&self, folder: &Path) -> Result<Vec<StoreEntry>, Error> {
+        let mut gpg_ids = HashMap::new();
+        let mut entries: Vec<StoreEntry> = self
+            .collect_pass_files(folder)?
+            .iter()
+            .filter_map(|entry_path| {
+                match StoreEntry::with_gpg_ids(entry_path, &self.store_path, &mut gpg_ids) {
+                    Ok(entry) => Some(entry),
+                    Err(err) => {
+                        warn!("Skipping {}: {}", entry_path.display(), err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /** Collect the entries at or below a folder on several threads, handing them to `on_batch`
+     * as they're found, in no particular order. The scan stops early once `cancel` is set, or
+     * `on_batch` returns false.
+     */
+    pub fn scan_parallel<F>(
+        &self,
+        folder: &Path,
+        threads: usize,
+        cancel: &AtomicBool,
+        on_batch: F,
+    ) -> Result<ScanProgress, Error>
+    where
+        F: Fn(ScanBatch) -> bool + Sync,
+    {
+        if !folder.is_dir() {
+            return Err(Error::InvalidPath {
+                path: folder.to_path_buf(),
+            });
+        }
+
+        let queue = Mutex::new(ScanQueue {
+            targets: vec![folder.to_owned()],
+            visited: HashSet::from([folder.canonicalize()?]),
+            progress: ScanProgress {
+                folders_found: 1,
+                ..ScanProgress::default()
+            },
+            ..ScanQueue::default()
+        });
+        let wakeup = Condvar::new();
+
+        // As with `walk`, symlinked folders are only followed once every real folder is scanned.
+        loop {
+            std::thread::scope(|scope| {
+                for _ in 0..threads.max(1) {
+                    scope.spawn(|| self.scan_worker(&queue, &wakeup, cancel, &on_batch));
+                }
+            });
+
+            let mut state = queue.lock().unwrap();
+            if let Some(err) = state.error.take() {
+                return Err(err);
+            }
+            if cancel.load(Ordering::Relaxed) {
+                debug!("Scan of {} cancelled", folder.display());
+                return Ok(state.progress);
+            }
+
+            let mut links = std::mem::take(&mut state.links);
+            links.sort();
+            for link in links {
+                match link.canonicalize() {
+                    Ok(target) if state.visited.insert(target.clone()) => {
+                        debug!("Following {} to {}", link.display(), target.display());
+                        state.targets.push(link);
+                        state.progress.folders_found += 1;
+                    }
+                    Ok(target) => debug!(
+                        "Skipping {}, {} is already scanned",
+                        link.display(),
+                        target.display()
+                    ),
+                    Err(err) => warn!("Skipping {}: {}", link.display(), err),
+                }
+            }
+            if state.targets.is_empty() {
+                return Ok(state.progress);
+            }
+        }
+    }
+
+    /// Scan folders off the queue until there are none left, or the scan is stopped.
+    fn scan_worker<F>(
+        &self,
+        queue: &Mutex<ScanQueue>,
+        wakeup: &Condvar,
+        cancel: &AtomicBool,
+        on_batch: &F,
+    ) where
+        F: Fn(ScanBatch) -> bool,
+    {
+        let mut gpg_ids = HashMap::new();
+        let mut found = vec![];
+        let mut state = queue.lock().unwrap();
+
+        while !cancel.load(Ordering::Relaxed) && state.error.is_none() {
+            let Some(folder) = state.targets.pop() else {
+                if state.busy == 0 {
+                    break;
+                }
+                state = wakeup.wait(state).unwrap();
+                continue;
+            };
+            state.busy += 1;
+            drop(state);
+
+            let scanned = self.list_folder(&folder).and_then(|listing| {
+                let entries = listing
+                    .files
+                    .iter()
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "gpg"))
+                    .filter_map(|path| {
+                        match StoreEntry::with_gpg_ids(path, &self.store_path, &mut gpg_ids) {
+                            Ok(entry) => Some(entry),
+                            Err(err) => {
+                                warn!("Skipping {}: {}", path.display(), err);
+                                None
+                            }
+                        }
+                    })
+                    .collect::<Vec<StoreEntry>>();
+                let folders = listing
+                    .folders
+                    .into_iter()
+                    .map(|folder| Ok((folder.canonicalize()?, folder)))
+                    .collect::<Result<Vec<(PathBuf, PathBuf)>, Error>>()?;
+                Ok((folders, listing.links, entries))
+            });
+
+            state = queue.lock().unwrap();
+            state.busy -= 1;
+            match scanned {
+                Ok((folders, links, entries)) => {
+                    for (target, folder) in folders {
+                        if state.visited.insert(target) {
+                            state.targets.push(folder);
+                            state.progress.folders_found += 1;
+                        }
+                    }
+                    state.links.extend(links);
+                    state.progress.folders_scanned += 1;
+                    state.progress.entries += entries.len();
+                    found.extend(entries);
+                }
+                Err(err) => {
+                    state.error.get_or_insert(err);
+                }
+            }
+            wakeup.notify_all();
+
+            if found.len() >= SCAN_BATCH_SIZE {
+                let batch = ScanBatch {
+                    entries: std::mem::take(&mut found),
+                    progress: state.progress,
+                };
+                drop(state);
+                if !on_batch(batch) {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+                state = queue.lock().unwrap();
+            }
+        }
 
-        for entry in std::fs::read_dir(cwd)? {
+        // Whoever is waiting has to look again at whether the scan is over.
+        wakeup.notify_all();
+        let progress = state.progress;
+        drop(state);
+        if !found.is_empty()
+            && !cancel.load(Ordering::Relaxed)
+            && !on_batch(ScanBatch {
+                entries: found,
+                progress,
+            })
+        {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether a file or folder is skipped, going by its own name and path only.
+    fn skip(&self, path: &Path, is_dir: bool) -> bool {
+        if is_dir
+            && !self.options.include_vcs
+            && path
+                .file_name()
+                .is_some_and(|name| VCS_FOLDERS.iter().any(|vcs| name == *vcs))
+        {
+            return true;
+        }
+        match path.strip_prefix(&self.store_path) {
+            Ok(relative) => self.ignore.is_ignored(&relative.to_string_lossy(), is_dir),
+            Err(_) => false,
+        }
+    }
+
+    /** What a folder directly holds, leaving out skipped paths, and symlinked folders unless
+     * they're followed.
+     */
+    pub fn list_folder(&self, folder: &Path) -> Result<Listing, Error> {
+        debug!("Searching {}", folder.to_string_lossy());
+
+        let mut listing = Listing::default();
+        for entry in std::fs::read_dir(folder)? {
             let entry = entry?;
             let path = entry.path();
+            let file_type = entry.file_type()?;
 
             if path.is_dir() {
-                //debug!(
-                //    "Found directory to recurse into: {}",
-                //    path.to_string_lossy()
-                //);
-                targets.push(path);
+                if self.skip(&path, true) {
+                    debug!("Ignoring {}", path.display());
+                } else if !file_type.is_symlink() {
+                    listing.folders.push(path);
+                } else if self.options.follow_symlinks {
+                    listing.links.push(path);
+                } else {
+                    debug!("Not following symlinked folder {}", path.display());
+                }
                 continue;
             }
-            if path.is_file() {
-                //debug!("Found file: {}", path.to_string_lossy());
-                results.push(path)
+            if path.is_file() && !self.skip(&path, false) {
+                listing.files.push(path)
             }
         }
+        Ok(listing)
     }
 
-    debug!("Found {} files", results.len());
-    Ok(results)
+    fn walk(&self, base_dir: &Path) -> Result<Walk, Error> {
+        self.walk_with(base_dir, |folder| self.list_folder(folder))
+    }
+
+    /** Walk the store from a folder, getting what every folder holds from `list`, which the
+     * index answers from its cache where it can.
+     */
+    pub(crate) fn walk_with<F>(&self, base_dir: &Path, mut list: F) -> Result<Walk, Error>
+    where
+        F: FnMut(&Path) -> Result<Listing, Error>,
+    {
+        if !base_dir.is_dir() {
+            return Err(Error::InvalidPath {
+                path: base_dir.to_path_buf(),
+            });
+        }
+
+        let mut walk = Walk::default();
+        let mut visited = HashSet::from([base_dir.canonicalize()?]);
+        let mut targets = vec![base_dir.to_owned()];
+        // Symlinked folders wait until everything else is walked, so a folder which is both in
+        // the store and linked into it is listed at its real place.
+        let mut links = vec![];
+
+        loop {
+            while let Some(cwd) = targets.pop() {
+                let listing = list(&cwd)?;
+                for folder in listing.folders {
+                    if visited.insert(folder.canonicalize()?) {
+                        targets.push(folder);
+                    }
+                }
+                links.extend(listing.links);
+                walk.files.extend(listing.files);
+                walk.folders.push(cwd);
+            }
+
+            if links.is_empty() {
+                break;
+            }
+            links.sort();
+            for link in links.drain(..) {
+                match link.canonicalize() {
+                    Ok(target) if visited.insert(target.clone()) => {
+                        debug!("Following {} to {}", link.display(), target.display());
+                        targets.push(link);
+                    }
+                    Ok(target) => debug!(
+                        "Skipping {}, {} is already scanned",
+                        link.display(),
+                        target.display()
+                    ),
+                    Err(err) => warn!("Skipping {}: {}", link.display(), err),
+                }
+            }
+        }
+
+        Ok(walk)
+    }
 }
 
-pub fn collect_pass_files(base_dir: &Path) -> Result<Vec<PathBuf>, Error> {
-    debug!(
-        "Searching '{}' for password entries",
-        base_dir.to_string_lossy()
-    );
+/** Patterns from a gitignore-style file, matched against paths relative to the store root.
+ *
+ * Supported are comments, `!` negation, a trailing `/` matching folders only, a leading or
+ * inner `/` anchoring the pattern to the root, `*`, `?`, `[...]` and `**`. As with git, a path
+ * in an ignored folder can't be included again.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
 
-    let pass_files: Vec<PathBuf> = collect_files(base_dir)?
-        .into_iter()
-        .filter(|i| match i.extension() {
-            Some(ext) => return ext.to_string_lossy() == "gpg",
-            None => return false,
-        })
-        .collect();
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRules {
+    /** Parse rules, skipping any pattern that can't be understood, as git does.
+     */
+    pub fn parse(contents: &str) -> Self {
+        let rules = contents
+            .lines()
+            .filter_map(|line| {
+                let line = line.trim_end();
+                if line.is_empty() || line.starts_with('#') {
+                    return None;
+                }
+                let (negated, pattern) = match line.strip_prefix('!') {
+                    Some(pattern) => (true, pattern),
+                    None => (false, line),
+                };
+                let (dir_only, pattern) = match pattern.strip_suffix('/') {
+                    Some(pattern) => (true, pattern),
+                    None => (false, pattern),
+                };
+                match Regex::new(&pattern_regex(pattern)) {
+                    Ok(regex) => Some(IgnoreRule {
+                        regex,
+                        negated,
+                        dir_only,
+                    }),
+                    Err(err) => {
+                        warn!("Skipping ignore pattern '{}': {}", line, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /** Whether a path, relative to the store root with `/` separators, is ignored. The last
+     * matching pattern decides.
+     */
+    pub fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| is_dir || !rule.dir_only)
+            .fold(false, |ignored, rule| {
+                if rule.regex.is_match(relative) {
+                    !rule.negated
+                } else {
+                    ignored
+                }
+            })
+    }
+}
 
-    debug!("Found {} entries with .gpg extension", pass_files.len());
+/** Translate a gitignore pattern, without its `!` or trailing `/`, into a regex.
+*/
+fn pattern_regex(pattern: &str) -> String {
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    let chars: Vec<char> = pattern.chars().collect();
+
+    let mut translated = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+                match chars.get(i + 2) {
+                    // `**/` matches any number of folders, including none.
+                    Some('/') if at_start => {
+                        translated.push_str("(?:.*/)?");
+                        i += 3;
+                    }
+                    // A trailing `/**` matches everything inside.
+                    None if at_start => {
+                        translated.push_str(".*");
+                        i += 2;
+                    }
+                    _ => {
+                        translated.push_str("[^/]*");
+                        i += 2;
+                    }
+                }
+                continue;
+            }
+            '*' => translated.push_str("[^/]*"),
+            '?' => translated.push_str("[^/]"),
+            '[' => {
+                let start = i + 1;
+                let mut end = start;
+                if matches!(chars.get(end), Some('!' | '^')) {
+                    end += 1;
+                }
+                if chars.get(end) == Some(&']') {
+                    end += 1;
+                }
+                while end < chars.len() && chars[end] != ']' {
+                    end += 1;
+                }
+                if end == chars.len() {
+                    translated.push_str(r"\[");
+                } else {
+                    translated.push('[');
+                    for (offset, c) in chars[start..end].iter().enumerate() {
+                        match c {
+                            '!' if offset == 0 => translated.push('^'),
+                            '\\' | '[' | '&' | '~' => {
+                                translated.push('\\');
+                                translated.push(*c);
+                            }
+                            _ => translated.push(*c),
+                        }
+                    }
+                    translated.push(']');
+                    i = end;
+                }
+            }
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                translated.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            c => translated.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    translated.push('$');
+    translated
+}
 
-    Ok(pass_files)
+/** Find the nearest file with the given name in the entry's directory or a parent, stopping at
+ * the store root, the way pass resolves `.gpg-id`.
+*/
+pub fn find_store_file(
+    entry_path: &Path,
+    store_path: &Path,
+    file_name: &str,
+) -> Result<Option<PathBuf>, Error> {
+    if !entry_path.starts_with(store_path) {
+        return Err(Error::InvalidPath {
+            path: entry_path.to_path_buf(),
+        });
+    }
+
+    for dir in entry_path.ancestors().skip(1) {
+        let found = dir.join(file_name);
+        if found.is_file() {
+            debug!("Using {} for {}", found.display(), entry_path.display());
+            return Ok(Some(found));
+        }
+        if dir == store_path {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/** An entry matching a filter.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryMatch {
+    pub id: EntryId,
+    /// Higher is better.
+    pub score: i64,
+    /// Positions, counted in characters, of the letters of the entry's name the filter matched,
+    /// in order.
+    pub indices: Vec<usize>,
 }
 
-/** Fuzzy filter the provided vec of pass entries with the filter key.
- * Returns Some vector of matching strings, or None if there are no matches left.
+/** Fuzzy filter entries by name with the filter key, best match first, then by name. An empty
+ * filter keeps every entry, in order, with a score of 0 and nothing matched.
+ * Returns None if there are no matches left.
 */
-pub fn filter_pass_entries(pass_entries: &Vec<String>, filter: &str) -> Option<Vec<String>> {
+pub fn filter_pass_entries(entries: &[StoreEntry], filter: &str) -> Option<Vec<EntryMatch>> {
     use fuzzy_matcher::skim::SkimMatcherV2;
     use fuzzy_matcher::FuzzyMatcher;
 
     debug!("Filter string: {:?}", filter);
-    if filter == "" {
-        let res = pass_entries.to_vec();
-        return Some(res);
+    if filter.is_empty() {
+        return Some(
+            (0..entries.len())
+                .map(|id| EntryMatch {
+                    id,
+                    score: 0,
+                    indices: vec![],
+                })
+                .collect(),
+        );
     }
 
     let matcher = SkimMatcherV2::default().ignore_case();
-    let mut matched_entries: Vec<String> = pass_entries
+    let mut matched_entries: Vec<EntryMatch> = entries
         .iter()
-        .filter_map(|x| match matcher.fuzzy_match(x, &filter) {
-            Some(val) => {
-                return Some(format!("{}: {}", val, x));
-            }
-            None => {
-                return None;
-            }
+        .enumerate()
+        .filter_map(|(id, entry)| {
+            matcher
+                .fuzzy_indices(&entry.name, filter)
+                .map(|(score, indices)| EntryMatch {
+                    id,
+                    score: score.into(),
+                    indices: indices.into_iter().map(|index| index as usize).collect(),
+                })
         })
         .collect();
-    matched_entries.sort();
-    matched_entries.reverse();
-    // TODO: is poentially more elegant to use `.unstable_sort_by(|a, b| b.cmp(a))` per
-    // rust docs: https://doc.rust-lang.org/std/primitive.slice.html#method.sort_unstable_by
-    return Some(matched_entries);
+    if matched_entries.is_empty() {
+        return None;
+    }
+    matched_entries.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| entries[a.id].name.cmp(&entries[b.id].name))
+    });
+    Some(matched_entries)
 }
 
 #[cfg(test)]
@@ -103,21 +812,26 @@ mod tests {
     use super::*;
     use crate::test_util::TmpTree;
     use anyhow::Result;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
 
     #[test]
     fn test_collecting_files() -> Result<()> {
         let tmp_tree = TmpTree::new();
 
-        let collected_files = collect_files(&tmp_tree.base_path);
+        let scanner = Scanner::new(&tmp_tree.base_path, ScanOptions::default())?;
+        let collected_files = scanner.collect_files(&tmp_tree.base_path);
 
         match collected_files {
             Ok(collected) => {
-                let expected = tmp_tree.expected_files.to_owned().sort();
-                let collected = collected.to_owned().sort();
+                let mut expected = tmp_tree.expected_files.to_owned();
+                expected.sort();
+                let mut collected = collected.to_owned();
+                collected.sort();
                 assert_eq!(collected, expected);
             }
             Err(err) => {
-                assert!(false, "file collection failed: {:?}", err);
+                panic!("file collection failed: {:?}", err);
             }
         }
 
@@ -128,7 +842,8 @@ mod tests {
     fn test_filter_to_pass_entries() -> Result<()> {
         let tmp_tree = TmpTree::new();
 
-        let pass_entries = collect_pass_files(&tmp_tree.base_path);
+        let scanner = Scanner::new(&tmp_tree.base_path, ScanOptions::default())?;
+        let pass_entries = scanner.collect_pass_files(&tmp_tree.base_path);
         match pass_entries {
             Ok(entries) => {
                 for entry in entries {
@@ -142,7 +857,7 @@ mod tests {
                 }
             }
             Err(err) => {
-                assert!(false, "pass file collection failed: {:?}", err);
+                panic!("pass file collection failed: {:?}", err);
             }
         }
 
@@ -150,24 +865,232 @@ mod tests {
     }
 
     #[test]
-    fn test_filter_pass_entries() -> Result<()> {
-        let sample_entries: Vec<String> = vec![
-            "p/foo".into(),
-            "p/bar".into(),
-            "w/welp".into(),
-            "w/winning".into(),
-        ];
+    fn test_collect_store_entries() -> Result<()> {
+        let tmp_tree = TmpTree::new();
+        let base = &tmp_tree.base_path;
+        std::fs::create_dir_all(base.join("web"))?;
+        std::fs::write(base.join("web/keys.gpg.old.gpg"), "entry")?;
+        std::fs::write(
+            base.join("web").join(GPG_ID_FILE),
+            "f711232219df6593 # sample\n",
+        )?;
 
-        // An empty filter should return all results
-        let res = filter_pass_entries(&sample_entries, "");
-        match res {
-            Some(res) => {
-                assert_eq!(sample_entries, res);
-            }
-            None => {
-                assert!(false, "No results from filter");
+        let scanner = Scanner::new(base, ScanOptions::default())?;
+        let entries = scanner.collect_store_entries(base)?;
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["a/target", "b/target", "web/keys.gpg.old"]);
+
+        let entry = &entries[2];
+        assert_eq!(entry.path, base.join("web/keys.gpg.old.gpg"));
+        assert_eq!(entry.folders, ["web"]);
+        assert_eq!(entry.size, 5);
+        assert!(entry.modified.is_some());
+        assert_eq!(entry.recipients_hint, ["f711232219df6593"]);
+        assert!(entries[0].recipients_hint.is_empty());
+        assert_eq!(StoreEntry::new(&entry.path, base)?, *entry);
+
+        let in_web = scanner.collect_store_entries(&base.join("web"))?;
+        assert_eq!(in_web, &entries[2..]);
+
+        // An entry without a name `pass` could print is left out, not the whole listing.
+        std::fs::write(
+            base.join("web").join(OsStr::from_bytes(b"\xff.gpg")),
+            "entry",
+        )?;
+        assert_eq!(scanner.collect_store_entries(base)?, entries);
+
+        assert_eq!(entry_name(&base.join("a/foo"), base), None);
+        assert_eq!(entry_name(Path::new("/elsewhere/x.gpg"), base), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_rules() {
+        let rules = IgnoreRules::parse(concat!(
+            "# comment\n",
+            "\n",
+            "*.bak\n",
+            "/archive/\n",
+            "build/\n",
+            "docs/**/draft?\n",
+            "**/secret[0-9]\n",
+            "keep/**\n",
+            "!keep/this\n",
+            "\\#literal\n",
+            "[broken\n",
+        ));
+        let ignored = |path: &str, is_dir: bool| rules.is_ignored(path, is_dir);
+
+        assert!(ignored("old.bak", false));
+        assert!(ignored("web/old.bak", false));
+        assert!(ignored("archive", true));
+        assert!(!ignored("web/archive", true));
+        assert!(!ignored("archive", false));
+        assert!(ignored("web/build", true));
+        assert!(!ignored("web/build", false));
+        assert!(ignored("docs/draft1", false));
+        assert!(ignored("docs/a/b/draftX", false));
+        assert!(!ignored("web/docs/draft1", false));
+        assert!(ignored("secret7", false));
+        assert!(ignored("a/b/secret7", false));
+        assert!(!ignored("a/b/secretX", false));
+        assert!(ignored("keep/that", false));
+        assert!(!ignored("keep/this", false));
+        assert!(ignored("#literal", false));
+        assert!(ignored("[broken", false));
+        assert!(!ignored("web/github.gpg", false));
+    }
+
+    #[test]
+    fn test_scan_skips_ignored() -> Result<()> {
+        let tmp_tree = TmpTree::new();
+        let base = &tmp_tree.base_path;
+        std::fs::create_dir_all(base.join(".git/objects"))?;
+        std::fs::write(base.join(".git/objects/object.gpg"), "object")?;
+        std::fs::create_dir_all(base.join("archive"))?;
+        std::fs::write(base.join("archive/old.gpg"), "entry")?;
+        std::fs::write(base.join("b/target.bak.gpg"), "entry")?;
+        std::fs::write(base.join(IGNORE_FILE), "/archive/\n*.bak.gpg\n")?;
+
+        let names = |scanner: &Scanner| -> Result<Vec<String>> {
+            Ok(scanner
+                .collect_store_entries(base)?
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect())
+        };
+
+        let scanner = Scanner::new(base, ScanOptions::default())?;
+        assert_eq!(names(&scanner)?, ["a/target", "b/target"]);
+        assert!(scanner.is_ignored(&base.join("archive/old.gpg")));
+        assert!(scanner.is_ignored(&base.join(".git")));
+        assert!(!scanner.is_ignored(&base.join("a/target.gpg")));
+
+        let scanner = Scanner::new(
+            base,
+            ScanOptions {
+                include_vcs: true,
+                ..ScanOptions::default()
+            },
+        )?;
+        assert_eq!(
+            names(&scanner)?,
+            [".git/objects/object", "a/target", "b/target"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_symlinks() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let tmp_tree = TmpTree::new();
+        let team_store = TmpTree::new();
+        let base = &tmp_tree.base_path;
+        // A loop back to the root, a second way into a folder, and another store mounted in.
+        symlink(base, base.join("a/loop"))?;
+        symlink(base.join("b"), base.join("also-b"))?;
+        symlink(&team_store.base_path, base.join("team"))?;
+
+        let names = |options: ScanOptions| -> Result<Vec<String>> {
+            Ok(Scanner::new(base, options)?
+                .collect_store_entries(base)?
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect())
+        };
+
+        assert_eq!(names(ScanOptions::default())?, ["a/target", "b/target"]);
+        assert_eq!(
+            names(ScanOptions {
+                follow_symlinks: true,
+                ..ScanOptions::default()
+            })?,
+            ["a/target", "b/target", "team/a/target", "team/b/target"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_parallel() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let tmp_tree = TmpTree::new();
+        let base = &tmp_tree.base_path;
+        // Enough entries for several batches, spread over nested folders.
+        for folder in 0..20 {
+            let folder = base.join(format!("c/{}", folder));
+            std::fs::create_dir_all(&folder)?;
+            for entry in 0..30 {
+                std::fs::write(folder.join(format!("{}.gpg", entry)), "")?;
             }
         }
+        symlink(base, base.join("a/loop"))?;
+        symlink(base.join("b"), base.join("also-b"))?;
+        // An entry that can't be described is skipped, not the rest of the scan.
+        std::fs::write(base.join("c/0").join(OsStr::from_bytes(b"\xff.gpg")), "")?;
+
+        let scanner = Scanner::new(
+            base,
+            ScanOptions {
+                follow_symlinks: true,
+                ..ScanOptions::default()
+            },
+        )?;
+        let found = Mutex::new(vec![]);
+        let batches = Mutex::new(0);
+        let progress = scanner.scan_parallel(base, 4, &AtomicBool::new(false), |batch| {
+            *batches.lock().unwrap() += 1;
+            found.lock().unwrap().extend(batch.entries);
+            true
+        })?;
+
+        let mut found = found.into_inner().unwrap();
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(found, scanner.collect_store_entries(base)?);
+        assert_eq!(
+            found
+                .iter()
+                .filter(|entry| entry.folders == ["c", "0"])
+                .count(),
+            30
+        );
+        assert!(batches.into_inner().unwrap() > 1);
+        assert_eq!(progress.entries, 602);
+        assert_eq!(progress.folders_scanned, 24);
+        assert_eq!(progress.folders_found, progress.folders_scanned);
+
+        // Refusing a batch stops the scan.
+        let cancel = AtomicBool::new(false);
+        let progress = scanner.scan_parallel(base, 1, &cancel, |_| false)?;
+        assert!(cancel.load(Ordering::Relaxed));
+        assert!(progress.entries < 602);
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_pass_entries() -> Result<()> {
+        let sample_entries: Vec<StoreEntry> = ["p/foo", "p/bar", "w/welp", "w/winning"]
+            .iter()
+            .map(|name| StoreEntry {
+                name: name.to_string(),
+                path: PathBuf::from(format!("{}.gpg", name)),
+                folders: vec![],
+                modified: None,
+                size: 0,
+                recipients_hint: vec![],
+            })
+            .collect();
+
+        // An empty filter should return all results
+        let res = filter_pass_entries(&sample_entries, "").unwrap();
+        assert_eq!(
+            res.iter().map(|found| found.id).collect::<Vec<_>>(),
+            [0, 1, 2, 3]
+        );
+        assert!(res
+            .iter()
+            .all(|found| found.score == 0 && found.indices.is_empty()));
 
         // A non-empty filter should return fuzzy matches.
         let res = filter_pass_entries(&sample_entries, "wp");
@@ -176,7 +1099,17 @@ mod tests {
         let res = res.unwrap();
         assert_eq!(res.len(), 1);
 
-        assert!(res[0].ends_with("w/welp"));
+        assert_eq!(res[0].id, 2);
+        assert!(res[0].score > 0);
+        // "w/welp": the first `w` and the `p`.
+        assert_eq!(res[0].indices, [0, 5]);
+
+        // Better matches come first.
+        let res = filter_pass_entries(&sample_entries, "w").unwrap();
+        assert_eq!(res.len(), 2);
+        assert!(res[0].score >= res[1].score);
+
+        assert_eq!(filter_pass_entries(&sample_entries, "xyz"), None);
 
         Ok(())
     }
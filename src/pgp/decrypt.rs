@@ -1,12 +1,59 @@
 use crate::errors::Error;
-use anyhow::{anyhow, Result};
-use log::{error, trace, warn, debug};
+use log::{debug, error, trace, warn};
 use pgp::{
     composed::{Deserializable, Message},
-    types::KeyTrait,
-    PublicOrSecret, SignedSecretKey,
+    packet::{
+        CompressedData, LiteralData, PacketTrait, SecretKey, SecretSubkey, Signature, SubpacketData,
+    },
+    ser::Serialize,
+    types::{
+        CompressionAlgorithm, KeyId, KeyTrait, PublicKeyTrait, PublicParams, SecretKeyTrait,
+        SecretParams,
+    },
+    PublicOrSecret, SignedPublicKey, SignedSecretKey,
 };
 use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Upper bound on compression/signature layers unwrapped from a single message.
+const MAX_MESSAGE_DEPTH: usize = 8;
+
+/** Result of checking the signature on a decrypted entry, if it carried one.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Unsigned,
+    Good { fingerprint: String, signer: String },
+    Bad { fingerprint: String, signer: String },
+    UnknownSigner { fingerprint: String },
+}
+
+impl std::fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsigned => write!(f, "Unsigned"),
+            Self::Good {
+                fingerprint,
+                signer,
+            } => write!(f, "Good signature from {} ({})", signer, fingerprint),
+            Self::Bad {
+                fingerprint,
+                signer,
+            } => write!(f, "BAD signature from {} ({})", signer, fingerprint),
+            Self::UnknownSigner { fingerprint } => {
+                write!(f, "Signed by unknown key {}", fingerprint)
+            }
+        }
+    }
+}
+
+/** A decrypted entry, alongside the verification status of its signature.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptedMessage {
+    pub plaintext: String,
+    pub signature: SignatureStatus,
+}
 
 pub struct KeyAndPassphrasePair<'a> {
     passphrase: &'a str,
@@ -21,7 +68,7 @@ impl<'a> KeyAndPassphrasePair<'a> {
 
 /** Deserialize and de-armour a signing key from disk.
 */
-pub fn load_signing_key(key_path: &Path) -> Result<SignedSecretKey> {
+pub fn load_signing_key(key_path: &Path) -> Result<SignedSecretKey, Error> {
     let armoured_key = std::fs::File::open(key_path)?;
 
     let from_armor_res = pgp::composed::signed_key::parse::from_armor_many(armoured_key);
@@ -31,7 +78,9 @@ pub fn load_signing_key(key_path: &Path) -> Result<SignedSecretKey> {
                 Ok(data) => match data {
                     PublicOrSecret::Public(pub_key) => {
                         warn!("Public key fingerprint: {:?}", pub_key.fingerprint());
-                        return Err(anyhow!("incorrect key type, expected secret, got public"));
+                        return Err(Error::NoKey {
+                            err: "incorrect key type, expected secret, got public".to_string(),
+                        });
                     }
                     PublicOrSecret::Secret(sec_key) => {
                         warn!("Private key fingerprint: {:?}", sec_key.fingerprint());
@@ -48,13 +97,140 @@ pub fn load_signing_key(key_path: &Path) -> Result<SignedSecretKey> {
         }
     }
 
-    Err(anyhow!("Failed to load key"))
+    Err(Error::NoKey {
+        err: "Failed to load key".to_string(),
+    })
+}
+
+/** Deserialize and de-armour every public key in a keyring file.
+*/
+pub fn load_public_keys(key_path: &Path) -> Result<Vec<SignedPublicKey>, Error> {
+    let armoured_keys = std::fs::File::open(key_path)?;
+
+    let (items, _) = pgp::composed::signed_key::parse::from_armor_many(armoured_keys)?;
+    let mut public_keys = vec![];
+    for elem in items {
+        match elem {
+            Ok(PublicOrSecret::Public(pub_key)) => {
+                debug!("Public key fingerprint: {:?}", pub_key.fingerprint());
+                public_keys.push(pub_key);
+            }
+            Ok(PublicOrSecret::Secret(sec_key)) => {
+                warn!(
+                    "Ignoring secret key in public keyring: {:?}",
+                    sec_key.fingerprint()
+                );
+            }
+            Err(err) => {
+                error!(
+                    "from_armor hit an error with an element of the armoured data: {}",
+                    err
+                );
+            }
+        }
+    }
+
+    if public_keys.is_empty() {
+        return Err(Error::NoKey {
+            err: "No public keys found in keyring".to_string(),
+        });
+    }
+    Ok(public_keys)
+}
+
+/** Whether the secret key material is passphrase protected.
+*/
+pub fn is_locked(key: &SignedSecretKey) -> bool {
+    key.primary_key.secret_params().is_encrypted()
+        || key
+            .secret_subkeys
+            .iter()
+            .any(|subkey| subkey.key.secret_params().is_encrypted())
+}
+
+/** Check a passphrase against a secret key, by attempting to unlock it.
+*/
+pub fn check_passphrase(key: &SignedSecretKey, passphrase: &str) -> Result<(), Error> {
+    key.unlock(|| passphrase.to_string(), |_| Ok(()))
+        .map_err(|err| {
+            debug!("Failed to unlock key: {:?}", err);
+            Error::BadPassphrase {
+                key_id: hex::encode(key.key_id()),
+            }
+        })
+}
+
+/** Make a copy of a secret key with all of its secret material decrypted.
+ *
+ * Decrypting with the copy skips the passphrase's string-to-key derivation, which is the slow
+ * part of unlocking. The pgp crate zeroizes the plaintext parameters when the copy is dropped.
+ */
+pub fn unlock_key(key: &SignedSecretKey, passphrase: &str) -> Result<SignedSecretKey, Error> {
+    check_passphrase(key, passphrase)?;
+
+    let mut unlocked = key.clone();
+    if let Some(body) = unlocked_body(
+        &key.primary_key.public_key(),
+        key.primary_key.secret_params(),
+        key.primary_key.public_params(),
+        passphrase,
+    )? {
+        unlocked.primary_key = SecretKey::from_slice(key.primary_key.packet_version(), &body)?;
+    }
+    for (subkey, unlocked_subkey) in key.secret_subkeys.iter().zip(&mut unlocked.secret_subkeys) {
+        if let Some(body) = unlocked_body(
+            &subkey.key.public_key(),
+            subkey.key.secret_params(),
+            subkey.key.public_params(),
+            passphrase,
+        )? {
+            unlocked_subkey.key = SecretSubkey::from_slice(subkey.key.packet_version(), &body)?;
+        }
+    }
+    Ok(unlocked)
+}
+
+/** Serialise a secret key packet body with its secret parameters decrypted.
+ *
+ * `None` when the parameters are not encrypted in the first place.
+ */
+fn unlocked_body(
+    public_key: &(impl PublicKeyTrait + Serialize),
+    secret_params: &SecretParams,
+    public_params: &PublicParams,
+    passphrase: &str,
+) -> Result<Option<Zeroizing<Vec<u8>>>, Error> {
+    let SecretParams::Encrypted(encrypted) = secret_params else {
+        return Ok(None);
+    };
+    let plain = encrypted.unlock(
+        || passphrase.to_string(),
+        public_key.algorithm(),
+        public_params,
+    )?;
+
+    // A secret key packet body is the public key body followed by the secret parameters.
+    let mut body = Zeroizing::new(public_key.to_bytes()?);
+    plain.to_writer(&mut *body)?;
+    Ok(Some(body))
+}
+
+/** Select the secret keys which the message was encrypted to.
+*/
+pub fn recipient_keys<'a>(
+    message: &Message,
+    keys: &'a [SignedSecretKey],
+) -> Vec<&'a SignedSecretKey> {
+    let recipients = message.get_recipients();
+    keys.iter()
+        .filter(|key| recipients.iter().any(|id| key_matches_id(key, id)))
+        .collect()
 }
 
 /** Deserialize a GPG file into memory.
 */
 pub fn deserialise_message(message_path: &Path) -> Result<Message, Error> {
-    let sample_message = std::fs::read(message_path.to_owned())?;
+    let sample_message = std::fs::read(message_path)?;
     let message_cursor = std::io::Cursor::new(sample_message);
 
     match Message::from_bytes(message_cursor) {
@@ -64,105 +240,318 @@ pub fn deserialise_message(message_path: &Path) -> Result<Message, Error> {
 }
 
 /** Deccrypt a Message using the provided signing keys.
+ *
+ * Any signature found inside the encrypted payload is checked against `verification_keys`.
  *
  * Consumes a `Message` instance when decrypting.
 */
 pub fn decrypt_message(
     message: Message,
     signing_keys: &[KeyAndPassphrasePair],
-) -> Result<String, Error> {
-    // Iterate provided key&pw pairs (to support multi-key password-stores), return on first
-    // success.
-    //
-    // TODO: I believe we should be able to identify precisely which key can decrypt a message
-    // using metadata, rather than having to iterate them all...
-    // Or - we just pass all the keys to the decrypt function and it'll figure that out for us..
-    for key_and_pw in signing_keys {
-        match &message {
-            Message::Encrypted { .. } => {
-                // TODO: Pretty sure this array could be used instead of the top-level for loop
-                // here...
-                let (mut decrypter, key_ids) = match message
-                    .decrypt(|| key_and_pw.passphrase.to_string(), &[&key_and_pw.key])
-                {
-                    Ok(data) => data,
-                    Err(..) => continue,
-                };
-
-                // MessageDecrypter doesn't seem to resolve, but its in
-                // pgp::composed::message::decrypt, in-essence its an iterator of Results...
-                //
-                // TODO: Could this be neeater using "if let"?
-                let decrypted = match decrypter.next() {
-                    Some(dec_res) => match dec_res {
-                        Ok(decrypted_m) => {
-                            decrypted_m
-                        }
-                        Err(err) => {
-                            debug!("Failed to decrypt message: {:?}", err);
-                            continue;
-                        }
-                    },
-                    None => continue,
-                };
-
-                match decrypted {
-                    Message::Literal(data) => {
-                        trace!("Final message: {:?}", data);
-
-                        if data.is_binary() {
-                            return match String::from_utf8(data.data().to_vec()) {
-                                Err(err) => Err(err.into()),
-                                Ok(output) => Ok(output),
-                            };
-                        } else {
-                            return data
-                                .to_string()
-                                .ok_or_else(|| Error::UnsupportedMessageType {
-                                    err: "Failed to decode message data from Str-type Message."
-                                        .to_string(),
-                                });
-                        }
-                    }
-                    _ => {
-                        return Err(Error::UnsupportedMessageType { err: String::new() });
-                    }
-                }
-            }
-            _ => {
-                return Err(Error::UnsupportedMessageType {
-                    err: "Unsupported Message type, only Encrypted messages currently supported."
-                        .to_string(),
+    verification_keys: &[SignedPublicKey],
+) -> Result<DecryptedMessage, Error> {
+    if !matches!(message, Message::Encrypted { .. }) {
+        return Err(Error::UnsupportedMessageType {
+            err: "Unsupported Message type, only Encrypted messages currently supported."
+                .to_string(),
+        });
+    }
+
+    // Only keys named by the message's public-key encrypted session key packets can decrypt it.
+    let recipients: Vec<KeyId> = message.get_recipients().into_iter().cloned().collect();
+    debug!("Message recipients: {}", format_key_ids(&recipients));
+
+    let candidates: Vec<&KeyAndPassphrasePair> = signing_keys
+        .iter()
+        .filter(|pair| recipients.iter().any(|id| key_matches_id(pair.key, id)))
+        .collect();
+
+    if candidates.is_empty() {
+        return Err(Error::NoKey {
+            err: format!(
+                "No loaded key matches the message recipients [{}]",
+                format_key_ids(&recipients)
+            ),
+        });
+    }
+
+    // Multiple loaded keys may be recipients, keep the last failure to report if none succeed.
+    let mut last_err = None;
+    for key_and_pw in candidates {
+        debug!(
+            "Decrypting with key {}",
+            hex::encode(key_and_pw.key.key_id())
+        );
+        match decrypt_with_key(&message, key_and_pw) {
+            Ok(decrypted) => {
+                let (data, signature) = unwrap_literal(decrypted, verification_keys)?;
+                trace!("Final message: {:?}", data);
+                return Ok(DecryptedMessage {
+                    plaintext: literal_to_string(data)?,
+                    signature,
                 });
             }
+            Err(err) => {
+                warn!(
+                    "Key {} failed to decrypt message: {}",
+                    hex::encode(key_and_pw.key.key_id()),
+                    err
+                );
+                last_err = Some(err);
+            }
         }
     }
-    Err(Error::NoKey {
+
+    Err(last_err.unwrap_or_else(|| Error::NoKey {
         err: "No suitable keys to decrypt message".to_string(),
+    }))
+}
+
+/** Decrypt an encrypted message with a single key, yielding the (still packed) inner message.
+*/
+fn decrypt_with_key(
+    message: &Message,
+    key_and_pw: &KeyAndPassphrasePair,
+) -> Result<Message, Error> {
+    let (mut decrypter, _key_ids) =
+        message.decrypt(|| key_and_pw.passphrase.to_string(), &[key_and_pw.key])?;
+
+    // MessageDecrypter lives in pgp::composed::message::decrypt, in-essence its an iterator of
+    // Results...
+    match decrypter.next() {
+        Some(decrypted) => Ok(decrypted?),
+        None => Err(Error::UnsupportedMessageType {
+            err: "Encrypted message contained no data.".to_string(),
+        }),
+    }
+}
+
+/** Whether the key ID belongs to the secret key, or any of its subkeys.
+*/
+fn key_matches_id(key: &SignedSecretKey, id: &KeyId) -> bool {
+    &key.key_id() == id
+        || key
+            .secret_subkeys
+            .iter()
+            .any(|subkey| &subkey.key_id() == id)
+}
+
+fn format_key_ids(ids: &[KeyId]) -> String {
+    ids.iter()
+        .map(hex::encode)
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/** Peel any compression and signature layers off a decrypted message, down to its literal data.
+ *
+ * `gpg` compresses entries by default (and nothing stops a message from being compressed more
+ * than once), so this loops until it hits the literal, giving up after `MAX_MESSAGE_DEPTH`
+ * layers. The outermost signature encountered on the way down is verified.
+*/
+fn unwrap_literal(
+    message: Message,
+    verification_keys: &[SignedPublicKey],
+) -> Result<(LiteralData, SignatureStatus), Error> {
+    let mut message = message;
+    let mut status = SignatureStatus::Unsigned;
+
+    for _ in 0..MAX_MESSAGE_DEPTH {
+        if let Message::Signed { signature, .. } = &message {
+            if status == SignatureStatus::Unsigned {
+                status = verify_signature(&message, signature, verification_keys);
+                debug!("Signature status: {}", status);
+            } else {
+                warn!("Ignoring nested signature, only the outermost one is verified");
+            }
+        }
+
+        message = match message {
+            Message::Literal(data) => return Ok((data, status)),
+            Message::Compressed(data) => decompress(&data)?,
+            Message::Signed {
+                message: Some(inner),
+                ..
+            } => *inner,
+            _ => {
+                return Err(Error::UnsupportedMessageType {
+                    err: "Unsupported Message type inside encrypted payload.".to_string(),
+                })
+            }
+        };
+    }
+
+    Err(Error::UnsupportedMessageType {
+        err: format!(
+            "Message is nested more than {} layers deep.",
+            MAX_MESSAGE_DEPTH
+        ),
     })
 }
 
+/** Decompress a single compressed layer back into a `Message`.
+ *
+ * The pgp crate only ships ZIP and ZLIB decoders, so BZip2 is handled here directly.
+*/
+fn decompress(data: &CompressedData) -> Result<Message, Error> {
+    if compression_algorithm(data)? == Some(CompressionAlgorithm::BZip2 as u8) {
+        debug!("Decompressing BZip2 message layer");
+        let decoder = bzip2::read::BzDecoder::new(data.compressed_data());
+        return Ok(Message::from_bytes(decoder)?);
+    }
+    debug!("Decompressing message layer");
+    Ok(Message::from_bytes(data.decompress()?)?)
+}
+
+/** The algorithm octet of a compressed packet. CompressedData doesn't expose its algorithm, but
+ * its serialised body starts with it, so only that first octet is kept as it's written.
+*/
+fn compression_algorithm(data: &CompressedData) -> Result<Option<u8>, Error> {
+    struct FirstOctet(Option<u8>);
+
+    impl std::io::Write for FirstOctet {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.0.is_none() {
+                self.0 = buf.first().copied();
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut octet = FirstOctet(None);
+    data.to_writer(&mut octet)?;
+    Ok(octet.0)
+}
+
+/** Identify who made a signature, preferring the issuer fingerprint over the short key ID.
+*/
+fn signature_issuer(signature: &Signature) -> (Option<KeyId>, Option<Vec<u8>>) {
+    let fingerprint = signature
+        .config
+        .hashed_subpackets
+        .iter()
+        .chain(signature.config.unhashed_subpackets.iter())
+        .find_map(|packet| match &packet.data {
+            SubpacketData::IssuerFingerprint(_, fpr) => Some(fpr.to_vec()),
+            _ => None,
+        });
+
+    // A v4 key ID is the low 64 bits of its fingerprint.
+    let key_id = signature.issuer().cloned().or_else(|| {
+        fingerprint
+            .as_ref()
+            .filter(|fpr| fpr.len() >= 8)
+            .and_then(|fpr| KeyId::from_slice(&fpr[fpr.len() - 8..]).ok())
+    });
+
+    (key_id, fingerprint)
+}
+
+/** Verify a signed message against whichever of the provided public keys issued it.
+*/
+fn verify_signature(
+    message: &Message,
+    signature: &Signature,
+    verification_keys: &[SignedPublicKey],
+) -> SignatureStatus {
+    let (key_id, fingerprint) = signature_issuer(signature);
+    let issuer = match (&fingerprint, &key_id) {
+        (Some(fpr), _) => hex::encode_upper(fpr),
+        (None, Some(id)) => hex::encode_upper(id),
+        (None, None) => "unknown".to_string(),
+    };
+
+    let Some(key_id) = key_id else {
+        warn!("Signature carries no issuer, unable to verify it");
+        return SignatureStatus::UnknownSigner {
+            fingerprint: issuer,
+        };
+    };
+
+    for public_key in verification_keys {
+        let verified = if public_key.key_id() == key_id {
+            message.verify(public_key)
+        } else if let Some(subkey) = public_key
+            .public_subkeys
+            .iter()
+            .find(|subkey| subkey.key_id() == key_id)
+        {
+            message.verify(subkey)
+        } else {
+            continue;
+        };
+
+        let fingerprint = hex::encode_upper(public_key.fingerprint());
+        let signer = public_key
+            .details
+            .users
+            .first()
+            .map(|user| user.id.id().to_string())
+            .unwrap_or_else(|| fingerprint.clone());
+
+        return match verified {
+            Ok(()) => SignatureStatus::Good {
+                fingerprint,
+                signer,
+            },
+            Err(err) => {
+                warn!("Signature from {} failed to verify: {}", fingerprint, err);
+                SignatureStatus::Bad {
+                    fingerprint,
+                    signer,
+                }
+            }
+        };
+    }
+
+    SignatureStatus::UnknownSigner {
+        fingerprint: issuer,
+    }
+}
+
+/** Convert literal message data into a String.
+*/
+fn literal_to_string(data: LiteralData) -> Result<String, Error> {
+    if data.is_binary() {
+        match String::from_utf8(data.data().to_vec()) {
+            Err(err) => Err(err.into()),
+            Ok(output) => Ok(output),
+        }
+    } else {
+        data.to_string()
+            .ok_or_else(|| Error::UnsupportedMessageType {
+                err: "Failed to decode message data from Str-type Message.".to_string(),
+            })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::path::PathBuf;
 
     use super::*;
-    use crate::test_util::{init_logs, TmpTree};
+    use crate::test_util::{init_logs, TmpTree, SAMPLE_CONTENT};
+    use anyhow::{anyhow, Result};
     use log::{error, info, warn};
     use pgp::from_armor_many;
 
     const KEYPHRASE: &str = "sample";
 
-    const SAMPLE_CONTENT: &str = concat!(
-        "4gG2y&9?-]AAE(wUnD]v22zs\"nx}ad\n",
-        "---\n",
-        "username: sample@example.com\n",
-        "some_key: foobar\n",
-    );
-
     const SAMPLE_ENTRY: &str = "./src/pgp/sample_entry.gpg";
+    const SAMPLE_COMPRESSED_ENTRIES: [&str; 3] = [
+        "./src/pgp/sample_entry_zip.gpg",
+        "./src/pgp/sample_entry_zlib.gpg",
+        "./src/pgp/sample_entry_bzip2.gpg",
+    ];
+
+    const SAMPLE_SIGNED_ENTRY: &str = "./src/pgp/sample_entry_signed.gpg";
 
     const SAMPLE_KEY_ID: &str = "f711232219df6593";
+    const SAMPLE_FINGERPRINT: &str = "A2FF365C3F7C667011ADD3BAF711232219DF6593";
     const SAMPLE_ARMOURED_KEY: &str = "./src/pgp/sample_key.asc";
     const SAMPLE_ARMOURED_PUB_KEY: &str = "./src/pgp/sample_key.pub.asc";
     const ALT_ARMOURED_KEY: &str = "./src/pgp/invalid_key.asc";
@@ -270,6 +659,27 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_check_passphrase() -> Result<()> {
+        init_logs();
+
+        let signing_key = load_signing_key(&PathBuf::from(SAMPLE_ARMOURED_KEY))?;
+        assert!(is_locked(&signing_key));
+        check_passphrase(&signing_key, KEYPHRASE)?;
+
+        let wrong = check_passphrase(&signing_key, "not-the-passphrase").unwrap_err();
+        assert!(matches!(wrong, Error::BadPassphrase { .. }));
+
+        // Only the sample key is a recipient of the sample entry.
+        let other_key = load_signing_key(&PathBuf::from(ALT_ARMOURED_KEY))?;
+        let keys = [other_key, signing_key];
+        let message = deserialise_message(&PathBuf::from(SAMPLE_ENTRY))?;
+        let recipients = recipient_keys(&message, &keys);
+        assert_eq!(recipients.len(), 1);
+        assert_eq!(hex::encode(recipients[0].key_id()), SAMPLE_KEY_ID);
+        Ok(())
+    }
+
     #[test]
     fn test_decrypt_message() -> Result<()> {
         init_logs();
@@ -279,8 +689,154 @@ mod test {
         let signing_key = load_signing_key(&PathBuf::from(SAMPLE_ARMOURED_KEY))?;
         let signing_pairs = [KeyAndPassphrasePair::new(KEYPHRASE, &signing_key)];
 
-        let decrypted_message = decrypt_message(message, &signing_pairs)?;
-        assert_eq!(decrypted_message, SAMPLE_CONTENT);
+        let decrypted_message = decrypt_message(message, &signing_pairs, &[])?;
+        assert_eq!(decrypted_message.plaintext, SAMPLE_CONTENT);
+        assert_eq!(decrypted_message.signature, SignatureStatus::Unsigned);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_message_selects_recipient_key() -> Result<()> {
+        init_logs();
+
+        let message = deserialise_message(&PathBuf::from(SAMPLE_ENTRY))?;
+
+        // The non-recipient key is skipped rather than tried, so its passphrase is irrelevant.
+        let other_key = load_signing_key(&PathBuf::from(ALT_ARMOURED_KEY))?;
+        let signing_key = load_signing_key(&PathBuf::from(SAMPLE_ARMOURED_KEY))?;
+        let signing_pairs = [
+            KeyAndPassphrasePair::new("unused", &other_key),
+            KeyAndPassphrasePair::new(KEYPHRASE, &signing_key),
+        ];
+
+        let decrypted_message = decrypt_message(message, &signing_pairs, &[])?;
+        assert_eq!(decrypted_message.plaintext, SAMPLE_CONTENT);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_compressed_message() -> Result<()> {
+        init_logs();
+
+        let signing_key = load_signing_key(&PathBuf::from(SAMPLE_ARMOURED_KEY))?;
+        let signing_pairs = [KeyAndPassphrasePair::new(KEYPHRASE, &signing_key)];
+
+        for entry in SAMPLE_COMPRESSED_ENTRIES {
+            info!("Decrypting {}", entry);
+            let message = deserialise_message(&PathBuf::from(entry))?;
+            let decrypted_message = decrypt_message(message, &signing_pairs, &[])?;
+            assert_eq!(decrypted_message.plaintext, SAMPLE_CONTENT);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_nested_compressed_message() -> Result<()> {
+        use pgp::crypto::sym::SymmetricKeyAlgorithm;
+        use pgp::SignedPublicKey;
+
+        init_logs();
+
+        let armoured_pub_key = std::fs::File::open(SAMPLE_ARMOURED_PUB_KEY)?;
+        let (public_key, _) = SignedPublicKey::from_armor_single(armoured_pub_key)?;
+        let encryption_key = &public_key.public_subkeys[0];
+
+        let message = Message::new_literal_bytes("", SAMPLE_CONTENT.as_bytes())
+            .compress(CompressionAlgorithm::ZLIB)?
+            .compress(CompressionAlgorithm::ZIP)?
+            .encrypt_to_keys(
+                &mut rand::thread_rng(),
+                SymmetricKeyAlgorithm::AES256,
+                &[encryption_key],
+            )?;
+
+        let signing_key = load_signing_key(&PathBuf::from(SAMPLE_ARMOURED_KEY))?;
+        let signing_pairs = [KeyAndPassphrasePair::new(KEYPHRASE, &signing_key)];
+
+        let decrypted_message = decrypt_message(message, &signing_pairs, &[])?;
+        assert_eq!(decrypted_message.plaintext, SAMPLE_CONTENT);
+        assert_eq!(decrypted_message.signature, SignatureStatus::Unsigned);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_signed_message() -> Result<()> {
+        init_logs();
+
+        let signing_key = load_signing_key(&PathBuf::from(SAMPLE_ARMOURED_KEY))?;
+        let signing_pairs = [KeyAndPassphrasePair::new(KEYPHRASE, &signing_key)];
+        let public_keys = load_public_keys(&PathBuf::from(SAMPLE_ARMOURED_PUB_KEY))?;
+
+        // Signed by a key we hold the public half of.
+        let message = deserialise_message(&PathBuf::from(SAMPLE_SIGNED_ENTRY))?;
+        let decrypted_message = decrypt_message(message, &signing_pairs, &public_keys)?;
+        assert_eq!(decrypted_message.plaintext, SAMPLE_CONTENT);
+        assert_eq!(
+            decrypted_message.signature,
+            SignatureStatus::Good {
+                fingerprint: SAMPLE_FINGERPRINT.to_string(),
+                signer: "sample (sample) <sample@example.com>".to_string(),
+            }
+        );
+
+        // Without the signer's public key, the entry still decrypts.
+        let message = deserialise_message(&PathBuf::from(SAMPLE_SIGNED_ENTRY))?;
+        let decrypted_message = decrypt_message(message, &signing_pairs, &[])?;
+        assert_eq!(decrypted_message.plaintext, SAMPLE_CONTENT);
+        assert_eq!(
+            decrypted_message.signature,
+            SignatureStatus::UnknownSigner {
+                fingerprint: SAMPLE_FINGERPRINT.to_string(),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_tampered_signed_message() -> Result<()> {
+        use pgp::crypto::{hash::HashAlgorithm, sym::SymmetricKeyAlgorithm};
+
+        init_logs();
+
+        let signing_key = load_signing_key(&PathBuf::from(SAMPLE_ARMOURED_KEY))?;
+        let signing_pairs = [KeyAndPassphrasePair::new(KEYPHRASE, &signing_key)];
+        let public_keys = load_public_keys(&PathBuf::from(SAMPLE_ARMOURED_PUB_KEY))?;
+
+        // Swap the signed content out from under its signature.
+        let signed = Message::new_literal_bytes("", b"original content").sign(
+            &signing_key,
+            || KEYPHRASE.to_string(),
+            HashAlgorithm::SHA2_256,
+        )?;
+        let Message::Signed {
+            one_pass_signature,
+            signature,
+            ..
+        } = signed
+        else {
+            panic!("expected a signed message");
+        };
+        let tampered = Message::Signed {
+            message: Some(Box::new(Message::new_literal_bytes(
+                "",
+                SAMPLE_CONTENT.as_bytes(),
+            ))),
+            one_pass_signature,
+            signature,
+        };
+
+        let message = tampered.encrypt_to_keys(
+            &mut rand::thread_rng(),
+            SymmetricKeyAlgorithm::AES256,
+            &[&public_keys[0].public_subkeys[0]],
+        )?;
+
+        let decrypted_message = decrypt_message(message, &signing_pairs, &public_keys)?;
+        assert_eq!(decrypted_message.plaintext, SAMPLE_CONTENT);
+        assert!(matches!(
+            decrypted_message.signature,
+            SignatureStatus::Bad { .. }
+        ));
         Ok(())
     }
 
@@ -305,9 +861,19 @@ mod test {
         let message = deserialise_message(&PathBuf::from(SAMPLE_ENTRY)).unwrap();
         let incorrect_key = load_signing_key(&PathBuf::from(ALT_ARMOURED_KEY)).unwrap();
         let key_pw = KeyAndPassphrasePair::new(KEYPHRASE, &incorrect_key);
-        let wrong_key = decrypt_message(message, &[key_pw]).unwrap_err();
+        let wrong_key = decrypt_message(message, &[key_pw], &[]).unwrap_err();
         info!("{:?}", wrong_key);
         assert!(matches!(wrong_key, Error::NoKey { .. }));
+        // The error names the recipient (encryption subkey) the message was actually for.
+        assert!(wrong_key.to_string().contains("3a80df469a8445d4"));
+
+        // A matching key with the wrong passphrase surfaces the underlying error.
+        let message = deserialise_message(&PathBuf::from(SAMPLE_ENTRY)).unwrap();
+        let signing_key = load_signing_key(&PathBuf::from(SAMPLE_ARMOURED_KEY)).unwrap();
+        let key_pw = KeyAndPassphrasePair::new("not-the-passphrase", &signing_key);
+        let wrong_passphrase = decrypt_message(message, &[key_pw], &[]).unwrap_err();
+        info!("{:?}", wrong_passphrase);
+        assert!(matches!(wrong_passphrase, Error::PGPError { .. }));
 
         Ok(())
     }
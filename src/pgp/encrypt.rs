@@ -0,0 +1,137 @@
+use crate::errors::Error;
+use log::{debug, info};
+use pgp::{
+    composed::Message, crypto::sym::SymmetricKeyAlgorithm, packet::PublicSubkey, ser::Serialize,
+    types::CompressionAlgorithm,
+};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+/** Encrypt an entry's plaintext to the given recipients, as a binary (unarmoured) message.
+ *
+ * Like `gpg`, the literal data is compressed before encryption.
+*/
+pub fn encrypt_message(plaintext: &str, recipients: &[PublicSubkey]) -> Result<Vec<u8>, Error> {
+    if recipients.is_empty() {
+        return Err(Error::NoKey {
+            err: "No recipients to encrypt to".to_string(),
+        });
+    }
+
+    let recipients: Vec<&PublicSubkey> = recipients.iter().collect();
+    let message = Message::new_literal_bytes("", plaintext.as_bytes())
+        .compress(CompressionAlgorithm::ZLIB)?
+        .encrypt_to_keys(
+            &mut rand::thread_rng(),
+            SymmetricKeyAlgorithm::AES256,
+            &recipients,
+        )?;
+    Ok(message.to_bytes()?)
+}
+
+/** Write an encrypted entry to disk atomically, creating its directory if needed.
+ *
+ * The data goes to a temporary file alongside the entry, which is synced and then renamed over
+ * it, so a crash leaves either the old entry or the new one, never a truncated file.
+*/
+pub fn write_entry(entry_path: &Path, data: &[u8]) -> Result<(), Error> {
+    let parent = entry_path.parent().ok_or_else(|| Error::InvalidPath {
+        path: entry_path.to_path_buf(),
+    })?;
+    let file_name = entry_path
+        .file_name()
+        .ok_or_else(|| Error::InvalidPath {
+            path: entry_path.to_path_buf(),
+        })?
+        .to_string_lossy();
+    fs::create_dir_all(parent)?;
+    let tmp_path = parent.join(format!(".{}.{}.tmp", file_name, uuid::Uuid::new_v4()));
+    debug!(
+        "Writing {} via {}",
+        entry_path.display(),
+        tmp_path.display()
+    );
+
+    let written = (|| {
+        // Readable only by the user from the start, like everything else passrs writes.
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, entry_path)
+    })();
+    if let Err(err) = written {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err.into());
+    }
+
+    // Sync the directory too, so the rename itself is durable.
+    fs::File::open(parent)?.sync_all()?;
+    info!("Wrote {}", entry_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgp::decrypt::{self, KeyAndPassphrasePair, SignatureStatus};
+    use crate::pgp::recipients::{self, GPG_ID_FILE};
+    use crate::test_util::{init_logs, TmpTree, SAMPLE_CONTENT};
+    use anyhow::Result;
+    use std::os::unix::fs::PermissionsExt;
+
+    const KEYPHRASE: &str = "sample";
+    const SAMPLE_FINGERPRINT: &str = "A2FF365C3F7C667011ADD3BAF711232219DF6593";
+    const SAMPLE_ARMOURED_KEY: &str = "./src/pgp/sample_key.asc";
+
+    #[test]
+    fn test_encrypt_and_write_round_trip() -> Result<()> {
+        init_logs();
+
+        let tmp_tree = TmpTree::new();
+        std::fs::write(tmp_tree.base_path.join(GPG_ID_FILE), SAMPLE_FINGERPRINT)?;
+        let entry_path = tmp_tree.base_path.join("a/new/entry.gpg");
+
+        let key = decrypt::load_signing_key(Path::new(SAMPLE_ARMOURED_KEY))?;
+        let recipients = recipients::entry_recipients(
+            &entry_path,
+            &tmp_tree.base_path,
+            std::slice::from_ref(&key),
+            &[],
+        )?;
+        let data = encrypt_message(SAMPLE_CONTENT, &recipients)?;
+        write_entry(&entry_path, &data)?;
+
+        // Only the entry itself is left behind, no temporary files.
+        let leftovers: Vec<_> = fs::read_dir(tmp_tree.base_path.join("a/new"))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+        let mode = fs::metadata(&entry_path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let message = decrypt::deserialise_message(&entry_path)?;
+        let decrypted =
+            decrypt::decrypt_message(message, &[KeyAndPassphrasePair::new(KEYPHRASE, &key)], &[])?;
+        assert_eq!(decrypted.plaintext, SAMPLE_CONTENT);
+        assert_eq!(decrypted.signature, SignatureStatus::Unsigned);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypt_without_recipients() {
+        assert!(matches!(
+            encrypt_message(SAMPLE_CONTENT, &[]),
+            Err(Error::NoKey { .. })
+        ));
+    }
+}
@@ -1 +1,5 @@
 pub mod decrypt;
+pub mod encrypt;
+pub mod recipients;
+pub mod reencrypt;
+pub mod session;
@@ -0,0 +1,406 @@
+use crate::errors::Error;
+use crate::pass_scanner::find_store_file;
+use pgp::{
+    packet::{KeyFlags, PublicSubkey, Signature},
+    types::{KeyId, KeyTrait, SecretKeyTrait},
+    SignedKeyDetails, SignedPublicKey, SignedSecretKey,
+};
+use std::path::{Path, PathBuf};
+
+/// File listing the recipients of the entries in its directory and below, as used by pass.
+pub const GPG_ID_FILE: &str = ".gpg-id";
+
+/** A loaded key which recipients are matched against, with its usable encryption subkeys.
+*/
+struct Candidate {
+    key_id: KeyId,
+    fingerprint: Vec<u8>,
+    users: Vec<String>,
+    subkeys: Vec<PublicSubkey>,
+}
+
+/** A recipient listed in a `.gpg-id`, and the user ID of the loaded key it names, if any.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    pub id: String,
+    pub user: Option<String>,
+}
+
+/** The recipients an entry is encrypted to, and the `.gpg-id` they come from.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpgIdRecipients {
+    pub gpg_id: PathBuf,
+    pub recipients: Vec<Recipient>,
+}
+
+impl std::fmt::Display for GpgIdRecipients {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let recipients: Vec<String> = self
+            .recipients
+            .iter()
+            .map(|recipient| match &recipient.user {
+                Some(user) => user.clone(),
+                None => format!("{} (no key loaded)", recipient.id),
+            })
+            .collect();
+        write!(f, "{}", recipients.join(", "))
+    }
+}
+
+/** Find the `.gpg-id` which applies to an entry: the nearest one in the entry's directory or a
+ * parent, stopping at the store root.
+*/
+pub fn find_gpg_id(entry_path: &Path, store_path: &Path) -> Result<PathBuf, Error> {
+    find_store_file(entry_path, store_path, GPG_ID_FILE)?.ok_or_else(|| Error::NoKey {
+        err: format!(
+            "No {} found for '{}', initialise the store first",
+            GPG_ID_FILE,
+            entry_path.display()
+        ),
+    })
+}
+
+/** Parse `.gpg-id` contents: one recipient per line, ignoring blank lines and `#` comments.
+*/
+pub fn parse_gpg_id(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(id, _)| id).trim())
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/** Map recipients, given as key IDs, fingerprints or email addresses, onto encryption subkeys of
+ * the loaded keys.
+ *
+ * A primary key resolves to all of its encryption subkeys, while a subkey named directly is used
+ * on its own. Every recipient must resolve, so an entry is never written without one of them.
+*/
+pub fn resolve_recipients(
+    recipients: &[String],
+    secret_keys: &[SignedSecretKey],
+    public_keys: &[SignedPublicKey],
+) -> Result<Vec<PublicSubkey>, Error> {
+    let candidates = candidate_keys(secret_keys, public_keys);
+    let mut resolved: Vec<PublicSubkey> = vec![];
+
+    for recipient in recipients {
+        let subkeys = match find_candidate(&candidates, recipient) {
+            Some((_, Some(subkey))) => vec![subkey.clone()],
+            Some((candidate, None)) => candidate.subkeys.clone(),
+            None => vec![],
+        };
+        if subkeys.is_empty() {
+            return Err(Error::NoKey {
+                err: format!(
+                    "No loaded key with an encryption subkey matches recipient '{}'",
+                    recipient
+                ),
+            });
+        }
+
+        for subkey in subkeys {
+            if !resolved.iter().any(|key| key.key_id() == subkey.key_id()) {
+                resolved.push(subkey);
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/** Recipients for writing an entry, from the `.gpg-id` which applies to it.
+*/
+pub fn entry_recipients(
+    entry_path: &Path,
+    store_path: &Path,
+    secret_keys: &[SignedSecretKey],
+    public_keys: &[SignedPublicKey],
+) -> Result<Vec<PublicSubkey>, Error> {
+    let gpg_id = find_gpg_id(entry_path, store_path)?;
+    let recipients = parse_gpg_id(&std::fs::read_to_string(&gpg_id)?);
+    resolve_recipients(&recipients, secret_keys, public_keys)
+}
+
+/** Describe who an entry is encrypted to, for display; recipients without a loaded key are
+ * listed rather than treated as an error.
+*/
+pub fn describe_recipients(
+    entry_path: &Path,
+    store_path: &Path,
+    secret_keys: &[SignedSecretKey],
+    public_keys: &[SignedPublicKey],
+) -> Result<GpgIdRecipients, Error> {
+    let gpg_id = find_gpg_id(entry_path, store_path)?;
+    let candidates = candidate_keys(secret_keys, public_keys);
+
+    let recipients = parse_gpg_id(&std::fs::read_to_string(&gpg_id)?)
+        .into_iter()
+        .map(|id| {
+            let user = find_candidate(&candidates, &id).map(|(candidate, _)| {
+                candidate
+                    .users
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| hex::encode_upper(&candidate.fingerprint))
+            });
+            Recipient { id, user }
+        })
+        .collect();
+
+    Ok(GpgIdRecipients { gpg_id, recipients })
+}
+
+fn candidate_keys(
+    secret_keys: &[SignedSecretKey],
+    public_keys: &[SignedPublicKey],
+) -> Vec<Candidate> {
+    let secret = secret_keys.iter().map(|key| Candidate {
+        key_id: key.key_id(),
+        fingerprint: key.fingerprint(),
+        users: user_ids(&key.details),
+        subkeys: key
+            .secret_subkeys
+            .iter()
+            .filter(|subkey| can_encrypt(&subkey.key, &subkey.signatures))
+            .map(|subkey| subkey.key.public_key())
+            .collect(),
+    });
+    let public = public_keys.iter().map(|key| Candidate {
+        key_id: key.key_id(),
+        fingerprint: key.fingerprint(),
+        users: user_ids(&key.details),
+        subkeys: key
+            .public_subkeys
+            .iter()
+            .filter(|subkey| can_encrypt(&subkey.key, &subkey.signatures))
+            .map(|subkey| subkey.key.clone())
+            .collect(),
+    });
+    secret.chain(public).collect()
+}
+
+/** Whether a subkey may be used for encryption, going by its binding signature's key flags, or by
+ * its algorithm if it carries no flags.
+*/
+fn can_encrypt(subkey: &impl KeyTrait, signatures: &[Signature]) -> bool {
+    match signatures.first().map(Signature::key_flags) {
+        Some(flags) if flags != KeyFlags::default() => {
+            flags.encrypt_comms() || flags.encrypt_storage()
+        }
+        _ => subkey.is_encryption_key(),
+    }
+}
+
+/** Normalise a key ID or fingerprint to uppercase hex, dropping any `0x` prefix and spaces.
+*/
+fn parse_hex_id(recipient: &str) -> Option<String> {
+    let hex_id: String = recipient
+        .trim_start_matches("0x")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+
+    let valid_length = matches!(hex_id.len(), 16 | 40);
+    (valid_length && hex_id.chars().all(|c| c.is_ascii_hexdigit())).then_some(hex_id)
+}
+
+fn user_ids(details: &SignedKeyDetails) -> Vec<String> {
+    details
+        .users
+        .iter()
+        .map(|user| user.id.id().to_string())
+        .collect()
+}
+
+/** The email address in a user ID such as `Name <name@example.com>`, or the whole user ID if it
+ * is just an address.
+*/
+fn user_email(user_id: &str) -> &str {
+    match user_id.rsplit_once('<') {
+        Some((_, email)) => email.trim_end_matches('>').trim(),
+        None => user_id.trim(),
+    }
+}
+
+/** Find the loaded key a recipient names, and the subkey if it names one directly.
+ *
+ * Recipients are key IDs or fingerprints, or email addresses matched against user IDs the way
+ * `gpg -r` matches `<address>`.
+*/
+fn find_candidate<'a>(
+    candidates: &'a [Candidate],
+    recipient: &str,
+) -> Option<(&'a Candidate, Option<&'a PublicSubkey>)> {
+    if let Some(id) = parse_hex_id(recipient) {
+        let matches = |key_id: &KeyId, fingerprint: &[u8]| {
+            hex::encode_upper(key_id) == id || hex::encode_upper(fingerprint) == id
+        };
+
+        return candidates.iter().find_map(|candidate| {
+            if matches(&candidate.key_id, &candidate.fingerprint) {
+                return Some((candidate, None));
+            }
+            candidate
+                .subkeys
+                .iter()
+                .find(|subkey| matches(&subkey.key_id(), &subkey.fingerprint()))
+                .map(|subkey| (candidate, Some(subkey)))
+        });
+    }
+
+    let email = user_email(recipient);
+    if !email.contains('@') {
+        return None;
+    }
+    candidates
+        .iter()
+        .find(|candidate| {
+            candidate
+                .users
+                .iter()
+                .any(|user| user_email(user).eq_ignore_ascii_case(email))
+        })
+        .map(|candidate| (candidate, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgp::decrypt;
+    use crate::test_util::{init_logs, TmpTree};
+    use anyhow::Result;
+
+    const SAMPLE_ARMOURED_KEY: &str = "./src/pgp/sample_key.asc";
+    const SAMPLE_ARMOURED_PUB_KEY: &str = "./src/pgp/sample_key.pub.asc";
+    const SAMPLE_FINGERPRINT: &str = "A2FF365C3F7C667011ADD3BAF711232219DF6593";
+    const SAMPLE_SUBKEY_ID: &str = "3a80df469a8445d4";
+
+    #[test]
+    fn test_find_gpg_id() -> Result<()> {
+        let tmp_tree = TmpTree::new();
+        let store = &tmp_tree.base_path;
+        std::fs::write(store.join(GPG_ID_FILE), SAMPLE_FINGERPRINT)?;
+        std::fs::write(store.join("b").join(GPG_ID_FILE), SAMPLE_SUBKEY_ID)?;
+
+        assert_eq!(
+            find_gpg_id(&store.join("a/target.gpg"), store)?,
+            store.join(GPG_ID_FILE)
+        );
+        // Nested .gpg-id files override the store's.
+        assert_eq!(
+            find_gpg_id(&store.join("b/target.gpg"), store)?,
+            store.join("b").join(GPG_ID_FILE)
+        );
+        assert_eq!(
+            find_gpg_id(&store.join("b/new/entry.gpg"), store)?,
+            store.join("b").join(GPG_ID_FILE)
+        );
+
+        std::fs::remove_file(store.join(GPG_ID_FILE))?;
+        assert!(matches!(
+            find_gpg_id(&store.join("a/target.gpg"), store),
+            Err(Error::NoKey { .. })
+        ));
+        assert!(matches!(
+            find_gpg_id(Path::new("/elsewhere/entry.gpg"), store),
+            Err(Error::InvalidPath { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_gpg_id() {
+        let contents = concat!(
+            "# Team keys\n",
+            "A2FF365C3F7C667011ADD3BAF711232219DF6593\n",
+            "\n",
+            "  0x3A80DF469A8445D4  # laptop subkey\n",
+            "sample@example.com\n",
+        );
+        assert_eq!(
+            parse_gpg_id(contents),
+            vec![
+                SAMPLE_FINGERPRINT,
+                "0x3A80DF469A8445D4",
+                "sample@example.com"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_recipients() -> Result<()> {
+        init_logs();
+
+        let secret_key = decrypt::load_signing_key(Path::new(SAMPLE_ARMOURED_KEY))?;
+        let public_keys = decrypt::load_public_keys(Path::new(SAMPLE_ARMOURED_PUB_KEY))?;
+
+        // The primary key's fingerprint or ID resolves to its encryption subkey.
+        // As does an email address from one of its user IDs.
+        for recipient in [
+            SAMPLE_FINGERPRINT,
+            "0xf711232219df6593",
+            SAMPLE_SUBKEY_ID,
+            "Sample@Example.com",
+            "<sample@example.com>",
+        ] {
+            let subkeys = resolve_recipients(&[recipient.to_string()], &[], &public_keys)?;
+            assert_eq!(subkeys.len(), 1, "{}", recipient);
+            assert_eq!(hex::encode(subkeys[0].key_id()), SAMPLE_SUBKEY_ID);
+        }
+
+        // The same key listed twice, or loaded twice, is only encrypted to once.
+        let subkeys = resolve_recipients(
+            &[SAMPLE_FINGERPRINT.to_string(), SAMPLE_SUBKEY_ID.to_string()],
+            std::slice::from_ref(&secret_key),
+            &public_keys,
+        )?;
+        assert_eq!(subkeys.len(), 1);
+
+        let err =
+            resolve_recipients(&["0123456789ABCDEF".to_string()], &[], &public_keys).unwrap_err();
+        assert!(matches!(err, Error::NoKey { .. }));
+        assert!(err.to_string().contains("0123456789ABCDEF"));
+        for unmatched in ["someone@example.com", "sample"] {
+            assert!(resolve_recipients(&[unmatched.to_string()], &[], &public_keys).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_recipients() -> Result<()> {
+        init_logs();
+
+        let tmp_tree = TmpTree::new();
+        let store = &tmp_tree.base_path;
+        std::fs::write(
+            store.join("b").join(GPG_ID_FILE),
+            "sample@example.com\nnew-starter@example.com # pending\n",
+        )?;
+        let public_keys = decrypt::load_public_keys(Path::new(SAMPLE_ARMOURED_PUB_KEY))?;
+
+        let described = describe_recipients(&store.join("b/target.gpg"), store, &[], &public_keys)?;
+        assert_eq!(described.gpg_id, store.join("b").join(GPG_ID_FILE));
+        assert_eq!(
+            described.recipients,
+            vec![
+                Recipient {
+                    id: "sample@example.com".to_string(),
+                    user: Some("sample (sample) <sample@example.com>".to_string()),
+                },
+                Recipient {
+                    id: "new-starter@example.com".to_string(),
+                    user: None,
+                },
+            ]
+        );
+        assert_eq!(
+            described.to_string(),
+            "sample (sample) <sample@example.com>, new-starter@example.com (no key loaded)"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,460 @@
+use crate::errors::Error;
+use crate::pass_scanner::{ScanOptions, Scanner};
+use log::{debug, error, info};
+use pgp::{
+    composed::Message,
+    packet::PublicSubkey,
+    types::{KeyId, KeyTrait},
+    SignedPublicKey,
+};
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use super::{
+    decrypt, encrypt,
+    recipients::{self, GPG_ID_FILE},
+    session::{KeySession, SessionKeys},
+};
+
+/** An entry whose new ciphertext is ready, alongside the old one to roll back to.
+*/
+struct Staged {
+    path: PathBuf,
+    original: Vec<u8>,
+    reencrypted: Vec<u8>,
+}
+
+/** What a finished re-encryption did.
+*/
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReencryptReport {
+    pub reencrypted: Vec<PathBuf>,
+    /// Entries already encrypted to exactly the right recipients.
+    pub skipped: Vec<PathBuf>,
+}
+
+/** Re-encryption of every entry under a folder to a new set of recipients, like
+ * `pass init -p <folder>`.
+ *
+ * Entries are processed one `step` at a time, so callers can report progress. Nothing is written
+ * until `commit`, which writes the folder's new `.gpg-id` and every re-encrypted entry, restoring
+ * the originals if any write fails. Entries under a nested `.gpg-id` keep their own recipients.
+*/
+pub struct Reencryption {
+    folder: PathBuf,
+    store_path: PathBuf,
+    recipient_ids: Vec<String>,
+    recipients: Vec<PublicSubkey>,
+    public_keys: Vec<SignedPublicKey>,
+    pending: Vec<PathBuf>,
+    total: usize,
+    staged: Vec<Staged>,
+    skipped: Vec<PathBuf>,
+}
+
+impl Reencryption {
+    /** Prepare to re-encrypt a folder, checking every new recipient resolves to a loaded key.
+     */
+    pub fn start(
+        folder: &Path,
+        store_path: &Path,
+        recipient_ids: &[String],
+        session: &KeySession,
+        public_keys: Vec<SignedPublicKey>,
+    ) -> Result<Self, Error> {
+        if !folder.starts_with(store_path) {
+            return Err(Error::InvalidPath {
+                path: folder.to_path_buf(),
+            });
+        }
+        if recipient_ids.is_empty() {
+            return Err(Error::NoKey {
+                err: "No recipients given".to_string(),
+            });
+        }
+        let recipients =
+            recipients::resolve_recipients(recipient_ids, session.keys(), &public_keys)?;
+
+        // Symlinked folders are left out: they're usually another store, with its own recipients.
+        let pending = if folder.is_dir() {
+            Scanner::new(store_path, ScanOptions::default())?.collect_pass_files(folder)?
+        } else {
+            vec![]
+        };
+        info!(
+            "Re-encrypting {} entries under {}",
+            pending.len(),
+            folder.display()
+        );
+
+        Ok(Self {
+            folder: folder.to_path_buf(),
+            store_path: store_path.to_path_buf(),
+            recipient_ids: recipient_ids.to_vec(),
+            recipients,
+            public_keys,
+            total: pending.len(),
+            pending,
+            staged: vec![],
+            skipped: vec![],
+        })
+    }
+
+    /// Entries processed so far, and the total to process.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.total - self.pending.len(), self.total)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /** Decrypt and re-encrypt the next entry, holding the result in memory.
+     *
+     * Returns the entry processed, or None once every entry has been.
+     */
+    pub fn step(&mut self, session: &mut KeySession) -> Result<Option<PathBuf>, Error> {
+        let Some(entry) = self.pending.pop() else {
+            return Ok(None);
+        };
+
+        let recipients = self.recipients_for(&entry, session)?;
+        let original = std::fs::read(&entry)?;
+        let message = decrypt::deserialise_message(&entry)?;
+
+        if encrypted_to(&message, &recipients) {
+            debug!("{} already has the right recipients", entry.display());
+            self.skipped.push(entry.clone());
+            return Ok(Some(entry));
+        }
+
+        let decrypted = match session.keys_for(&message, Instant::now()) {
+            SessionKeys::Ready(pairs) => {
+                decrypt::decrypt_message(message, &pairs, &self.public_keys)?
+            }
+            SessionKeys::Locked(key) => {
+                return Err(Error::NoKey {
+                    err: format!(
+                        "Key {} must be unlocked to re-encrypt '{}'",
+                        hex::encode(key.key_id()),
+                        entry.display()
+                    ),
+                })
+            }
+        };
+        let reencrypted = encrypt::encrypt_message(&decrypted.plaintext, &recipients)?;
+
+        self.staged.push(Staged {
+            path: entry.clone(),
+            original,
+            reencrypted,
+        });
+        Ok(Some(entry))
+    }
+
+    /** Write the new `.gpg-id` and every re-encrypted entry.
+     *
+     * If any write fails, everything already written is put back as it was.
+     */
+    pub fn commit(self) -> Result<ReencryptReport, Error> {
+        if !self.is_done() {
+            return Err(Error::GeneralError {
+                err: "Re-encryption committed before every entry was processed".to_string(),
+            });
+        }
+
+        let gpg_id = self.folder.join(GPG_ID_FILE);
+        let previous_gpg_id = std::fs::read(&gpg_id).ok();
+        let contents = format!("{}\n", self.recipient_ids.join("\n"));
+
+        let mut written: Vec<&Staged> = vec![];
+        let result = encrypt::write_entry(&gpg_id, contents.as_bytes()).and_then(|()| {
+            for staged in &self.staged {
+                encrypt::write_entry(&staged.path, &staged.reencrypted)?;
+                written.push(staged);
+            }
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            error!("Re-encryption failed, rolling back: {}", err);
+            for staged in written {
+                if let Err(err) = encrypt::write_entry(&staged.path, &staged.original) {
+                    error!("Failed to restore {}: {}", staged.path.display(), err);
+                }
+            }
+            let restored = match &previous_gpg_id {
+                Some(previous) => encrypt::write_entry(&gpg_id, previous),
+                None => std::fs::remove_file(&gpg_id).map_err(Error::from),
+            };
+            if let Err(err) = restored {
+                error!("Failed to restore {}: {}", gpg_id.display(), err);
+            }
+            return Err(err);
+        }
+
+        info!(
+            "Re-encrypted {} entries, {} already up to date",
+            self.staged.len(),
+            self.skipped.len()
+        );
+        Ok(ReencryptReport {
+            reencrypted: self.staged.into_iter().map(|staged| staged.path).collect(),
+            skipped: self.skipped,
+        })
+    }
+
+    /** The recipients an entry should have: the new ones, unless a `.gpg-id` further down the
+     * tree overrides them.
+     */
+    fn recipients_for(
+        &self,
+        entry: &Path,
+        session: &KeySession,
+    ) -> Result<Vec<PublicSubkey>, Error> {
+        match recipients::find_gpg_id(entry, &self.store_path) {
+            Ok(gpg_id)
+                if gpg_id.starts_with(&self.folder)
+                    && gpg_id.parent() != Some(self.folder.as_path()) =>
+            {
+                debug!("{} is overridden by {}", entry.display(), gpg_id.display());
+                recipients::entry_recipients(
+                    entry,
+                    &self.store_path,
+                    session.keys(),
+                    &self.public_keys,
+                )
+            }
+            _ => Ok(self.recipients.clone()),
+        }
+    }
+}
+
+/** Whether a message is encrypted to exactly these recipients, so re-encrypting it would change
+ * nothing.
+*/
+pub fn encrypted_to(message: &Message, recipients: &[PublicSubkey]) -> bool {
+    let mut current: Vec<KeyId> = message.get_recipients().into_iter().cloned().collect();
+    let mut wanted: Vec<KeyId> = recipients.iter().map(|key| key.key_id()).collect();
+    current.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+    wanted.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+    current == wanted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{init_logs, TmpTree, SAMPLE_CONTENT};
+    use anyhow::Result;
+    use std::time::Duration;
+
+    const KEYPHRASE: &str = "sample";
+    const SAMPLE_KEY_ID: &str = "f711232219df6593";
+    const SAMPLE_FINGERPRINT: &str = "A2FF365C3F7C667011ADD3BAF711232219DF6593";
+    const SAMPLE_ARMOURED_KEY: &str = "./src/pgp/sample_key.asc";
+    const ALT_ARMOURED_PUB_KEY: &str = "./src/pgp/invalid_key.pub.asc";
+    const ALT_FINGERPRINT: &str = "ACF381228DF90F6AC55327BC0B48299C0FA5121E";
+
+    /** A store whose entries are all encrypted to the sample key, with the session unlocked and
+     * the alternative key's public half available.
+     */
+    fn sample_store() -> Result<(TmpTree, KeySession, Vec<SignedPublicKey>)> {
+        let tmp_tree = TmpTree::new();
+        let store = &tmp_tree.base_path;
+        std::fs::write(store.join(GPG_ID_FILE), SAMPLE_FINGERPRINT)?;
+
+        let key = decrypt::load_signing_key(Path::new(SAMPLE_ARMOURED_KEY))?;
+        let recipients = recipients::resolve_recipients(
+            &[SAMPLE_FINGERPRINT.to_string()],
+            std::slice::from_ref(&key),
+            &[],
+        )?;
+        for entry in ["a/target.gpg", "b/target.gpg", "b/nested/target.gpg"] {
+            let data = encrypt::encrypt_message(SAMPLE_CONTENT, &recipients)?;
+            encrypt::write_entry(&store.join(entry), &data)?;
+        }
+
+        let public_keys = decrypt::load_public_keys(Path::new(ALT_ARMOURED_PUB_KEY))?;
+
+        let mut session = KeySession::new(vec![key], Duration::from_secs(60));
+        session.unlock(SAMPLE_KEY_ID, KEYPHRASE, Instant::now())?;
+        Ok((tmp_tree, session, public_keys))
+    }
+
+    /** Re-encrypt a folder in one go, reporting progress after each entry.
+     */
+    fn reencrypt_folder(
+        folder: &Path,
+        store_path: &Path,
+        recipient_ids: &[String],
+        session: &mut KeySession,
+        public_keys: Vec<SignedPublicKey>,
+        mut progress: impl FnMut(usize, usize, &Path),
+    ) -> Result<ReencryptReport, Error> {
+        let mut reencryption =
+            Reencryption::start(folder, store_path, recipient_ids, session, public_keys)?;
+        while let Some(entry) = reencryption.step(session)? {
+            let (done, total) = reencryption.progress();
+            progress(done, total, &entry);
+        }
+        reencryption.commit()
+    }
+
+    fn recipient_count(entry: &Path) -> Result<usize> {
+        Ok(decrypt::deserialise_message(entry)?.get_recipients().len())
+    }
+
+    #[test]
+    fn test_reencrypt_folder() -> Result<()> {
+        init_logs();
+
+        let (tmp_tree, mut session, public_keys) = sample_store()?;
+        let store = &tmp_tree.base_path;
+        // The nested folder keeps its own recipients.
+        std::fs::write(store.join("b/nested").join(GPG_ID_FILE), SAMPLE_FINGERPRINT)?;
+
+        let new_recipients = vec![SAMPLE_FINGERPRINT.to_string(), ALT_FINGERPRINT.to_string()];
+        let mut seen = vec![];
+        let report = reencrypt_folder(
+            &store.join("b"),
+            store,
+            &new_recipients,
+            &mut session,
+            public_keys.clone(),
+            |done, total, _| seen.push((done, total)),
+        )?;
+
+        assert_eq!(seen, vec![(1, 2), (2, 2)]);
+        assert_eq!(report.reencrypted, vec![store.join("b/target.gpg")]);
+        assert_eq!(report.skipped, vec![store.join("b/nested/target.gpg")]);
+        assert_eq!(
+            std::fs::read_to_string(store.join("b").join(GPG_ID_FILE))?,
+            format!("{}\n{}\n", SAMPLE_FINGERPRINT, ALT_FINGERPRINT)
+        );
+        assert_eq!(recipient_count(&store.join("b/target.gpg"))?, 2);
+        assert_eq!(recipient_count(&store.join("b/nested/target.gpg"))?, 1);
+        assert_eq!(recipient_count(&store.join("a/target.gpg"))?, 1);
+
+        // Still decryptable, and a second run has nothing left to do.
+        let message = decrypt::deserialise_message(&store.join("b/target.gpg"))?;
+        let SessionKeys::Ready(pairs) = session.keys_for(&message, Instant::now()) else {
+            panic!("session should be unlocked");
+        };
+        let decrypted = decrypt::decrypt_message(message, &pairs, &[])?;
+        assert_eq!(decrypted.plaintext, SAMPLE_CONTENT);
+
+        let report = reencrypt_folder(
+            &store.join("b"),
+            store,
+            &new_recipients,
+            &mut session,
+            public_keys,
+            |_, _, _| {},
+        )?;
+        assert!(report.reencrypted.is_empty());
+        assert_eq!(report.skipped.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reencrypt_failure_leaves_store_untouched() -> Result<()> {
+        init_logs();
+
+        let (tmp_tree, mut session, public_keys) = sample_store()?;
+        let store = &tmp_tree.base_path;
+
+        // An entry only the alternative key can read stops the re-encryption part way through.
+        let alt_recipients =
+            recipients::resolve_recipients(&[ALT_FINGERPRINT.to_string()], &[], &public_keys)?;
+        let unreadable = encrypt::encrypt_message(SAMPLE_CONTENT, &alt_recipients)?;
+        encrypt::write_entry(&store.join("b/unreadable.gpg"), &unreadable)?;
+
+        let before: Vec<Vec<u8>> = ["b/target.gpg", "b/nested/target.gpg"]
+            .iter()
+            .map(|entry| std::fs::read(store.join(entry)))
+            .collect::<Result<_, _>>()?;
+
+        let result = reencrypt_folder(
+            &store.join("b"),
+            store,
+            &[SAMPLE_FINGERPRINT.to_string(), ALT_FINGERPRINT.to_string()],
+            &mut session,
+            public_keys,
+            |_, _, _| {},
+        );
+        assert!(result.is_err());
+
+        let after: Vec<Vec<u8>> = ["b/target.gpg", "b/nested/target.gpg"]
+            .iter()
+            .map(|entry| std::fs::read(store.join(entry)))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(before, after);
+        assert!(!store.join("b").join(GPG_ID_FILE).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_failure_rolls_back_writes() -> Result<()> {
+        init_logs();
+
+        let (tmp_tree, mut session, public_keys) = sample_store()?;
+        let store = &tmp_tree.base_path;
+        let gpg_id = store.join("b").join(GPG_ID_FILE);
+        std::fs::write(&gpg_id, format!("{}\n", SAMPLE_FINGERPRINT))?;
+        std::fs::copy(store.join("b/target.gpg"), store.join("b/other.gpg"))?;
+
+        let entries = ["b/other.gpg", "b/target.gpg", "b/nested/target.gpg"];
+        let before: Vec<Vec<u8>> = entries
+            .iter()
+            .map(|entry| std::fs::read(store.join(entry)))
+            .collect::<Result<_, _>>()?;
+        let gpg_id_before = std::fs::read(&gpg_id)?;
+
+        let mut reencryption = Reencryption::start(
+            &store.join("b"),
+            store,
+            &[SAMPLE_FINGERPRINT.to_string(), ALT_FINGERPRINT.to_string()],
+            &session,
+            public_keys,
+        )?;
+        while reencryption.step(&mut session)?.is_some() {}
+        assert_eq!(reencryption.staged.len(), 3);
+
+        // The last entry to be written turns into a folder, so its rename fails after the
+        // `.gpg-id` and the other entries have been rewritten.
+        let blocked = reencryption.staged.last().unwrap().path.clone();
+        std::fs::remove_file(&blocked)?;
+        std::fs::create_dir(&blocked)?;
+        std::fs::write(blocked.join("keep"), "")?;
+
+        assert!(reencryption.commit().is_err());
+
+        assert_eq!(std::fs::read(&gpg_id)?, gpg_id_before);
+        for (entry, original) in entries.iter().zip(&before) {
+            let path = store.join(entry);
+            if path != blocked {
+                assert_eq!(&std::fs::read(&path)?, original, "{} changed", entry);
+            }
+        }
+        assert!(blocked.is_dir());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reencrypt_rejects_unknown_recipient() -> Result<()> {
+        let (tmp_tree, session, _) = sample_store()?;
+        let store = &tmp_tree.base_path;
+
+        let result = Reencryption::start(
+            &store.join("b"),
+            store,
+            &["nobody@example.com".to_string()],
+            &session,
+            vec![],
+        );
+        assert!(matches!(result, Err(Error::NoKey { .. })));
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use log::{debug, info};
+use pgp::{composed::Message, types::KeyTrait, SignedPublicKey, SignedSecretKey};
+
+use super::decrypt::{self, KeyAndPassphrasePair};
+use crate::errors::Error;
+
+/** In-memory secret keys, plus decrypted copies of those unlocked this session.
+ *
+ * Keys are loaded once, rather than per decryption, along with the public keys used to resolve
+ * recipients and verify signatures. The passphrase is only used to unlock keys, never kept.
+ * Unlocked keys are locked again once `idle_timeout` passes without them being used, or on an
+ * explicit `lock`. Their secret material is zeroized when dropped.
+*/
+pub struct KeySession {
+    keys: Vec<SignedSecretKey>,
+    public_keys: Vec<SignedPublicKey>,
+    unlocked: HashMap<String, SignedSecretKey>,
+    idle_timeout: Duration,
+    last_used: Instant,
+}
+
+/** The keys available to decrypt a particular message.
+*/
+pub enum SessionKeys<'a> {
+    /// Recipient keys which are ready to use.
+    Ready(Vec<KeyAndPassphrasePair<'a>>),
+    /// No recipient key is usable until this one is unlocked.
+    Locked(&'a SignedSecretKey),
+}
+
+impl KeySession {
+    pub fn new(keys: Vec<SignedSecretKey>, idle_timeout: Duration) -> Self {
+        Self {
+            keys,
+            public_keys: vec![],
+            unlocked: HashMap::new(),
+            idle_timeout,
+            last_used: Instant::now(),
+        }
+    }
+
+    /// Keep the public keys of other recipients and signers alongside the secret keys.
+    pub fn with_public_keys(mut self, public_keys: Vec<SignedPublicKey>) -> Self {
+        self.public_keys = public_keys;
+        self
+    }
+
+    pub fn keys(&self) -> &[SignedSecretKey] {
+        &self.keys
+    }
+
+    pub fn public_keys(&self) -> &[SignedPublicKey] {
+        &self.public_keys
+    }
+
+    /// Whether any passphrase-protected key is currently unlocked.
+    pub fn is_unlocked(&self) -> bool {
+        !self.unlocked.is_empty()
+    }
+
+    /** Unlock a key for the session, if the passphrase is correct.
+     */
+    pub fn unlock(&mut self, key_id: &str, passphrase: &str, now: Instant) -> Result<(), Error> {
+        let key = self
+            .keys
+            .iter()
+            .find(|key| hex::encode(key.key_id()) == key_id)
+            .ok_or_else(|| Error::NoKey {
+                err: format!("Key '{}' is not loaded", key_id),
+            })?;
+
+        let unlocked = decrypt::unlock_key(key, passphrase)?;
+
+        info!("Unlocked key {}", key_id);
+        self.unlocked.insert(key_id.to_string(), unlocked);
+        self.last_used = now;
+        Ok(())
+    }
+
+    /** Drop every unlocked key.
+     */
+    pub fn lock(&mut self) {
+        if self.is_unlocked() {
+            info!("Locking {} unlocked key(s)", self.unlocked.len());
+        }
+        self.unlocked.clear();
+    }
+
+    /** Lock the session if it has sat idle for longer than the timeout.
+     *
+     * Returns whether the session was locked as a result.
+     */
+    pub fn expire(&mut self, now: Instant) -> bool {
+        if self.is_unlocked() && now.saturating_duration_since(self.last_used) >= self.idle_timeout
+        {
+            debug!("Key session idle for {:?}", self.idle_timeout);
+            self.lock();
+            return true;
+        }
+        false
+    }
+
+    /** Collect the recipient keys able to decrypt a message, counting as use of the session.
+     */
+    pub fn keys_for(&mut self, message: &Message, now: Instant) -> SessionKeys<'_> {
+        self.touch(now);
+        self.ready_keys(message)
+    }
+
+    /** Count as use of the session, pushing back the idle timeout unless it has already passed.
+     */
+    pub fn touch(&mut self, now: Instant) {
+        self.expire(now);
+        self.last_used = now;
+    }
+
+    /** Collect the recipient keys able to decrypt a message, without counting as use.
+     *
+     * Passphrase-less keys are always ready.
+     */
+    pub fn ready_keys(&self, message: &Message) -> SessionKeys<'_> {
+        let mut ready = vec![];
+        let mut locked = None;
+        for key in decrypt::recipient_keys(message, &self.keys) {
+            if !decrypt::is_locked(key) {
+                ready.push(KeyAndPassphrasePair::new("", key));
+            } else if let Some(unlocked) = self.unlocked.get(&hex::encode(key.key_id())) {
+                ready.push(KeyAndPassphrasePair::new("", unlocked));
+            } else if locked.is_none() {
+                locked = Some(key);
+            }
+        }
+
+        match locked {
+            Some(key) if ready.is_empty() => SessionKeys::Locked(key),
+            _ => SessionKeys::Ready(ready),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::init_logs;
+    use anyhow::Result;
+    use std::path::PathBuf;
+
+    const KEYPHRASE: &str = "sample";
+    const SAMPLE_ENTRY: &str = "./src/pgp/sample_entry.gpg";
+    const SAMPLE_KEY_ID: &str = "f711232219df6593";
+    const SAMPLE_ARMOURED_KEY: &str = "./src/pgp/sample_key.asc";
+
+    fn sample_session(idle_timeout: Duration) -> Result<KeySession> {
+        let key = decrypt::load_signing_key(&PathBuf::from(SAMPLE_ARMOURED_KEY))?;
+        Ok(KeySession::new(vec![key], idle_timeout))
+    }
+
+    #[test]
+    fn test_unlock_and_lock() -> Result<()> {
+        init_logs();
+
+        let mut session = sample_session(Duration::from_secs(60))?;
+        let message = decrypt::deserialise_message(&PathBuf::from(SAMPLE_ENTRY))?;
+        let now = Instant::now();
+
+        assert!(!session.is_unlocked());
+        assert!(matches!(
+            session.keys_for(&message, now),
+            SessionKeys::Locked(_)
+        ));
+
+        let wrong = session.unlock(SAMPLE_KEY_ID, "not-the-passphrase", now);
+        assert!(matches!(wrong, Err(Error::BadPassphrase { .. })));
+        assert!(!session.is_unlocked());
+
+        session.unlock(SAMPLE_KEY_ID, KEYPHRASE, now)?;
+        assert!(session.is_unlocked());
+        // The session keeps decrypted key material, not the passphrase.
+        assert!(!decrypt::is_locked(&session.unlocked[SAMPLE_KEY_ID]));
+        match session.keys_for(&message, now) {
+            SessionKeys::Ready(pairs) => assert_eq!(pairs.len(), 1),
+            SessionKeys::Locked(_) => panic!("key should be unlocked"),
+        }
+
+        session.lock();
+        assert!(!session.is_unlocked());
+        Ok(())
+    }
+
+    #[test]
+    fn test_idle_expiry() -> Result<()> {
+        init_logs();
+
+        let timeout = Duration::from_secs(60);
+        let mut session = sample_session(timeout)?;
+        let message = decrypt::deserialise_message(&PathBuf::from(SAMPLE_ENTRY))?;
+        let start = Instant::now();
+
+        session.unlock(SAMPLE_KEY_ID, KEYPHRASE, start)?;
+
+        // Using the session pushes back the idle timeout.
+        let later = start + Duration::from_secs(45);
+        assert!(matches!(
+            session.keys_for(&message, later),
+            SessionKeys::Ready(_)
+        ));
+        assert!(!session.expire(start + timeout));
+        assert!(session.is_unlocked());
+
+        assert!(session.expire(later + timeout));
+        assert!(!session.is_unlocked());
+        Ok(())
+    }
+}
@@ -0,0 +1,501 @@
+use std::{
+    convert::TryFrom,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+use serde::Deserialize;
+
+use crate::errors::Error;
+
+/// Clipboard clearing delay used by `pass -c`, in seconds.
+const DEFAULT_CLIPBOARD_TIMEOUT: u64 = 45;
+/// How long an unlocked key may sit idle before locking, in seconds.
+const DEFAULT_KEY_SESSION_TIMEOUT: u64 = 300;
+/// Length of generated passwords, matching `pass generate`.
+const DEFAULT_GENERATED_LENGTH: usize = crate::generator::DEFAULT_LENGTH;
+/// How long a revealed secret stays visible in the detail view, in seconds.
+const DEFAULT_REVEAL_TIMEOUT: u64 = 10;
+
+/** User configuration, loaded from `$XDG_CONFIG_HOME/passrs/config.toml`.
+ *
+ * Every field is optional in the file, and a handful can be overridden from the environment; see
+ * `Settings::apply_env_overrides`.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Root of the password store.
+    pub store_path: PathBuf,
+    /// Armoured secret key files used to decrypt entries.
+    pub secret_keys: Vec<PathBuf>,
+    /// Armoured public keyring files, used to verify signatures and encrypt to `.gpg-id` recipients.
+    pub public_keys: Vec<PathBuf>,
+    /// Seconds before copied secrets are cleared from the clipboard.
+    pub clipboard_timeout: u64,
+    /// Seconds an unlocked key may sit unused before it is locked again.
+    pub key_session_timeout: u64,
+    /// Seconds before revealed secrets are masked again.
+    pub reveal_timeout: u64,
+    /// Length of generated passwords.
+    pub generated_length: usize,
+    /// Wordlist for generated passphrases, such as EFF's large wordlist. Passphrases can't be
+    /// generated without one.
+    pub wordlist: Option<PathBuf>,
+    /// List entries in symlinked folders, such as a team's store mounted into this one.
+    pub follow_symlinks: bool,
+    pub theme: ThemeName,
+    pub keybindings: Keybindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let mut store_path = home::home_dir().unwrap_or_default();
+        store_path.push(".password-store");
+
+        Self {
+            store_path,
+            secret_keys: vec![],
+            public_keys: vec![],
+            clipboard_timeout: DEFAULT_CLIPBOARD_TIMEOUT,
+            key_session_timeout: DEFAULT_KEY_SESSION_TIMEOUT,
+            reveal_timeout: DEFAULT_REVEAL_TIMEOUT,
+            generated_length: DEFAULT_GENERATED_LENGTH,
+            wordlist: None,
+            follow_symlinks: false,
+            theme: ThemeName::default(),
+            keybindings: Keybindings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    Light,
+    #[default]
+    Dark,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keybindings {
+    pub select: KeyBinding,
+    pub up: KeyBinding,
+    pub down: KeyBinding,
+    pub cancel: KeyBinding,
+    pub lock: KeyBinding,
+    pub reveal: KeyBinding,
+    /// Copy the entry's first one-time code.
+    pub copy_otp: KeyBinding,
+    pub new_entry: KeyBinding,
+    pub edit: KeyBinding,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self {
+            select: KeyBinding::new(KeyName::Enter),
+            up: KeyBinding::new(KeyName::Up),
+            down: KeyBinding::new(KeyName::Down),
+            cancel: KeyBinding::new(KeyName::Escape),
+            lock: KeyBinding {
+                ctrl: true,
+                ..KeyBinding::new(KeyName::Letter('L'))
+            },
+            reveal: KeyBinding {
+                ctrl: true,
+                ..KeyBinding::new(KeyName::Letter('R'))
+            },
+            copy_otp: KeyBinding {
+                ctrl: true,
+                ..KeyBinding::new(KeyName::Letter('T'))
+            },
+            new_entry: KeyBinding {
+                ctrl: true,
+                ..KeyBinding::new(KeyName::Letter('N'))
+            },
+            edit: KeyBinding {
+                ctrl: true,
+                ..KeyBinding::new(KeyName::Letter('E'))
+            },
+        }
+    }
+}
+
+/** A key plus modifiers, written in the config file as e.g. `"Ctrl+Shift+C"`.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyBinding {
+    pub key: KeyName,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl KeyBinding {
+    pub fn new(key: KeyName) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let mut parts: Vec<&str> = value.split('+').map(str::trim).collect();
+        let key = parts
+            .pop()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| format!("Keybinding '{}' has no key", value))?;
+
+        let mut binding = KeyBinding::new(KeyName::try_from(key)?);
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => binding.ctrl = true,
+                "alt" => binding.alt = true,
+                "shift" => binding.shift = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown modifier '{}' in keybinding '{}'",
+                        modifier, value
+                    ))
+                }
+            }
+        }
+        Ok(binding)
+    }
+}
+
+impl Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+/** Keys which may be bound to actions.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyName {
+    Letter(char),
+    Enter,
+    Escape,
+    Tab,
+    Space,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+impl TryFrom<&str> for KeyName {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut chars = value.chars();
+        if let (Some(letter), None) = (chars.next(), chars.next()) {
+            if letter.is_ascii_alphabetic() {
+                return Ok(KeyName::Letter(letter.to_ascii_uppercase()));
+            }
+        }
+
+        match value.to_lowercase().as_str() {
+            "enter" | "return" => Ok(KeyName::Enter),
+            "escape" | "esc" => Ok(KeyName::Escape),
+            "tab" => Ok(KeyName::Tab),
+            "space" => Ok(KeyName::Space),
+            "backspace" => Ok(KeyName::Backspace),
+            "delete" | "del" => Ok(KeyName::Delete),
+            "up" => Ok(KeyName::Up),
+            "down" => Ok(KeyName::Down),
+            "left" => Ok(KeyName::Left),
+            "right" => Ok(KeyName::Right),
+            "home" => Ok(KeyName::Home),
+            "end" => Ok(KeyName::End),
+            "pageup" => Ok(KeyName::PageUp),
+            "pagedown" => Ok(KeyName::PageDown),
+            _ => Err(format!("Unknown key '{}'", value)),
+        }
+    }
+}
+
+impl Display for KeyName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyName::Letter(letter) => write!(f, "{}", letter),
+            key => write!(f, "{:?}", key),
+        }
+    }
+}
+
+impl Settings {
+    /** Load settings from the default config location, then apply environment overrides.
+     *
+     * A missing config file is not an error, defaults are used instead.
+     */
+    pub fn load() -> Result<Self, Error> {
+        let vars = |name: &str| std::env::var(name).ok();
+        let config_path = config_path(vars);
+
+        let mut settings = if config_path.is_file() {
+            info!("Loading config from {}", config_path.to_string_lossy());
+            Self::from_file(&config_path)?
+        } else {
+            debug!(
+                "No config at {}, using defaults",
+                config_path.to_string_lossy()
+            );
+            Self::default()
+        };
+
+        settings.apply_env_overrides(vars)?;
+        Ok(settings)
+    }
+
+    /** Parse settings from a TOML config file.
+     */
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|err| Error::InvalidConfig {
+            path: path.to_path_buf(),
+            err: err.to_string(),
+        })
+    }
+
+    /** Override settings from environment variables, looked up via `vars`.
+     *
+     * `PASSWORD_STORE_DIR` and `PASSWORD_STORE_CLIP_TIME` are honoured for compatibility with
+     * `pass`, the `PASSRS_*` equivalents take precedence over them. Key file lists are
+     * colon-separated, like `PATH`.
+     */
+    pub fn apply_env_overrides<F>(&mut self, vars: F) -> Result<(), Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let var = |names: &[&str]| names.iter().find_map(|name| vars(name));
+
+        if let Some(store_path) = var(&["PASSRS_STORE_DIR", "PASSWORD_STORE_DIR"]) {
+            self.store_path = PathBuf::from(store_path);
+        }
+        if let Some(secret_keys) = var(&["PASSRS_SECRET_KEYS"]) {
+            self.secret_keys = std::env::split_paths(&secret_keys).collect();
+        }
+        if let Some(public_keys) = var(&["PASSRS_PUBLIC_KEYS"]) {
+            self.public_keys = std::env::split_paths(&public_keys).collect();
+        }
+        if let Some(timeout) = var(&["PASSRS_CLIP_TIME", "PASSWORD_STORE_CLIP_TIME"]) {
+            self.clipboard_timeout = timeout.parse().map_err(|_| Error::InvalidConfig {
+                path: "environment".into(),
+                err: format!("clipboard timeout '{}' is not a number of seconds", timeout),
+            })?;
+        }
+        if let Some(timeout) = var(&["PASSRS_KEY_TIMEOUT"]) {
+            self.key_session_timeout = timeout.parse().map_err(|_| Error::InvalidConfig {
+                path: "environment".into(),
+                err: format!("key timeout '{}' is not a number of seconds", timeout),
+            })?;
+        }
+        if let Some(length) = var(&["PASSRS_GENERATED_LENGTH", "PASSWORD_STORE_GENERATED_LENGTH"]) {
+            self.generated_length = length.parse().map_err(|_| Error::InvalidConfig {
+                path: "environment".into(),
+                err: format!("generated length '{}' is not a number", length),
+            })?;
+        }
+        if let Some(wordlist) = var(&["PASSRS_WORDLIST"]) {
+            self.wordlist = Some(PathBuf::from(wordlist));
+        }
+        if let Some(theme) = var(&["PASSRS_THEME"]) {
+            self.theme = match theme.to_lowercase().as_str() {
+                "light" => ThemeName::Light,
+                "dark" => ThemeName::Dark,
+                _ => {
+                    return Err(Error::InvalidConfig {
+                        path: "environment".into(),
+                        err: format!("unknown theme '{}'", theme),
+                    })
+                }
+            };
+        }
+        Ok(())
+    }
+}
+
+/** Resolve the config file path; `PASSRS_CONFIG` wins, otherwise `$XDG_CONFIG_HOME/passrs`.
+*/
+fn config_path<F>(vars: F) -> PathBuf
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if let Some(path) = vars("PASSRS_CONFIG") {
+        return PathBuf::from(path);
+    }
+
+    let mut config_dir = match vars("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home::home_dir().unwrap_or_default().join(".config"),
+    };
+    config_dir.push("passrs");
+    config_dir.push("config.toml");
+    config_dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TmpTree;
+    use anyhow::Result;
+    use std::collections::HashMap;
+
+    fn env(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_parse_config() -> Result<()> {
+        let tmp_tree = TmpTree::new();
+        let config = tmp_tree.base_path.join("config.toml");
+        std::fs::write(
+            &config,
+            concat!(
+                "store_path = \"/srv/store\"\n",
+                "secret_keys = [\"/keys/me.asc\"]\n",
+                "clipboard_timeout = 10\n",
+                "key_session_timeout = 60\n",
+                "reveal_timeout = 3\n",
+                "generated_length = 32\n",
+                "wordlist = \"/usr/share/dict/words\"\n",
+                "follow_symlinks = true\n",
+                "theme = \"light\"\n",
+                "[keybindings]\n",
+                "select = \"Ctrl+O\"\n",
+            ),
+        )?;
+
+        let settings = Settings::from_file(&config)?;
+        assert_eq!(settings.store_path, PathBuf::from("/srv/store"));
+        assert_eq!(settings.secret_keys, vec![PathBuf::from("/keys/me.asc")]);
+        assert!(settings.public_keys.is_empty());
+        assert_eq!(settings.clipboard_timeout, 10);
+        assert_eq!(settings.key_session_timeout, 60);
+        assert_eq!(settings.reveal_timeout, 3);
+        assert_eq!(settings.theme, ThemeName::Light);
+        assert_eq!(settings.generated_length, 32);
+        assert_eq!(
+            settings.wordlist,
+            Some(PathBuf::from("/usr/share/dict/words"))
+        );
+        assert!(settings.follow_symlinks);
+        assert_eq!(
+            settings.keybindings.select,
+            KeyBinding {
+                ctrl: true,
+                ..KeyBinding::new(KeyName::Letter('O'))
+            }
+        );
+        // Unset bindings keep their defaults.
+        assert_eq!(settings.keybindings.up, KeyBinding::new(KeyName::Up));
+        // Bindings print in the same form the config file accepts.
+        assert_eq!(settings.keybindings.select.to_string(), "Ctrl+O");
+        assert_eq!(settings.keybindings.up.to_string(), "Up");
+        let lock = settings.keybindings.lock;
+        assert_eq!(KeyBinding::try_from(lock.to_string()), Ok(lock));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_config() -> Result<()> {
+        let tmp_tree = TmpTree::new();
+        let config = tmp_tree.base_path.join("config.toml");
+
+        for contents in [
+            "store_path = ",
+            "unknown_field = true",
+            "clipboard_timeout = \"soon\"",
+            "[keybindings]\nselect = \"Hyper+X\"",
+        ] {
+            std::fs::write(&config, contents)?;
+            let err = Settings::from_file(&config).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidConfig { .. }),
+                "expected InvalidConfig for {:?}, got {:?}",
+                contents,
+                err
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_overrides() -> Result<()> {
+        let mut settings = Settings::default();
+        settings.apply_env_overrides(env(&[
+            ("PASSWORD_STORE_DIR", "/pass/store"),
+            ("PASSWORD_STORE_CLIP_TIME", "20"),
+            ("PASSRS_CLIP_TIME", "5"),
+            ("PASSRS_SECRET_KEYS", "/a.asc:/b.asc"),
+            ("PASSRS_THEME", "Light"),
+            ("PASSWORD_STORE_GENERATED_LENGTH", "40"),
+            ("PASSRS_WORDLIST", "/words.txt"),
+        ]))?;
+
+        assert_eq!(settings.store_path, PathBuf::from("/pass/store"));
+        assert_eq!(settings.clipboard_timeout, 5);
+        assert_eq!(
+            settings.secret_keys,
+            vec![PathBuf::from("/a.asc"), PathBuf::from("/b.asc")]
+        );
+        assert_eq!(settings.theme, ThemeName::Light);
+        assert_eq!(settings.generated_length, 40);
+        assert_eq!(settings.wordlist, Some(PathBuf::from("/words.txt")));
+
+        let err = settings
+            .apply_env_overrides(env(&[("PASSWORD_STORE_CLIP_TIME", "soon")]))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_path() {
+        assert_eq!(
+            config_path(env(&[("XDG_CONFIG_HOME", "/xdg")])),
+            PathBuf::from("/xdg/passrs/config.toml")
+        );
+        assert_eq!(
+            config_path(env(&[
+                ("XDG_CONFIG_HOME", "/xdg"),
+                ("PASSRS_CONFIG", "/etc/passrs.toml")
+            ])),
+            PathBuf::from("/etc/passrs.toml")
+        );
+    }
+}
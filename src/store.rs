@@ -0,0 +1,376 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use pgp::types::KeyTrait;
+
+use super::errors::Error;
+use super::pass_scanner::{self, ScanOptions, Scanner, StoreEntry};
+use super::pgp::decrypt::{self, DecryptedMessage};
+use super::pgp::encrypt;
+use super::pgp::recipients::{self, GpgIdRecipients};
+use super::pgp::reencrypt::{self, Reencryption};
+use super::pgp::session::{KeySession, SessionKeys};
+use super::settings::Settings;
+
+/** A password store on disk, together with the secret keys that read it.
+ *
+ * Entries are named the way `pass` names them: their path below the store without the `.gpg`
+ * extension, such as `web/github`.
+*/
+pub struct Store {
+    path: PathBuf,
+    settings: Settings,
+    session: KeySession,
+}
+
+/// An entry matching a search, higher scores being better matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub entry: StoreEntry,
+    pub score: i64,
+    /// Positions of the characters of the entry's name the query matched.
+    pub indices: Vec<usize>,
+}
+
+impl Store {
+    /** Open the store configured in `settings`, loading its secret and public keys. The secret
+     * keys start out locked, see `unlock`.
+     */
+    pub fn open(settings: Settings) -> Result<Self, Error> {
+        let session = KeySession::new(
+            load_secret_keys(&settings)?,
+            Duration::from_secs(settings.key_session_timeout),
+        )
+        .with_public_keys(load_public_keys(&settings)?);
+        Ok(Self {
+            path: settings.store_path.clone(),
+            settings,
+            session,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn session(&self) -> &KeySession {
+        &self.session
+    }
+
+    pub fn session_mut(&mut self) -> &mut KeySession {
+        &mut self.session
+    }
+
+    /// Unlock one of the store's secret keys, identified by its hex key ID.
+    pub fn unlock(&mut self, key_id: &str, passphrase: &str) -> Result<(), Error> {
+        self.session.unlock(key_id, passphrase, Instant::now())
+    }
+
+    /// The file of an entry, rejecting names that would lead outside the store.
+    pub fn entry_path(&self, name: &str) -> Result<PathBuf, Error> {
+        let mut path = self.folder_path(name)?;
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".gpg");
+        path.set_file_name(file_name);
+        self.inside(name, path)
+    }
+
+    /** A folder of the store by name. Only plain folder names are accepted, so empty names, `.`,
+     * `..` and absolute paths are rejected rather than resolving to the store or beyond it.
+     */
+    pub fn folder_path(&self, name: &str) -> Result<PathBuf, Error> {
+        let relative = Path::new(name);
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return Err(Error::OutsideStore {
+                name: name.to_string(),
+            });
+        }
+        self.inside(name, self.path.join(relative))
+    }
+
+    /// Check a path resolved from a name lies strictly below the store.
+    fn inside(&self, name: &str, path: PathBuf) -> Result<PathBuf, Error> {
+        if !path.starts_with(&self.path) || path == self.path {
+            return Err(Error::OutsideStore {
+                name: name.to_string(),
+            });
+        }
+        Ok(path)
+    }
+
+    /// The name of the entry stored in a file.
+    pub fn entry_name(&self, entry_path: &Path) -> Option<String> {
+        pass_scanner::entry_name(entry_path, &self.path)
+    }
+
+    /// Every entry in the store, in name order.
+    pub fn entries(&self) -> Result<Vec<StoreEntry>, Error> {
+        self.entries_in(&self.path)
+    }
+
+    /// The entries at or below a folder of the store, in name order.
+    pub fn entries_in(&self, folder: &Path) -> Result<Vec<StoreEntry>, Error> {
+        self.scanner()?.collect_store_entries(folder)
+    }
+
+    /// A scanner for the store, as configured in its settings.
+    pub fn scanner(&self) -> Result<Scanner, Error> {
+        Scanner::new(&self.path, ScanOptions::from(&self.settings))
+    }
+
+    /** Fuzzy search entry names, best match first. An empty query matches every entry equally.
+     */
+    pub fn search(&self, query: &str) -> Result<Vec<SearchMatch>, Error> {
+        let entries = self.entries()?;
+        let matches = pass_scanner::filter_pass_entries(&entries, query).unwrap_or_default();
+        Ok(matches
+            .into_iter()
+            .map(|found| SearchMatch {
+                entry: entries[found.id].clone(),
+                score: found.score,
+                indices: found.indices,
+            })
+            .collect())
+    }
+
+    /// Decrypt an entry by name, see `decrypt_file`.
+    pub fn decrypt(&mut self, name: &str) -> Result<DecryptOutcome, Error> {
+        let entry_path = self.entry_path(name)?;
+        if !entry_path.is_file() {
+            return Err(Error::NotInStore {
+                name: name.to_string(),
+            });
+        }
+        self.decrypt_file(&entry_path)
+    }
+
+    /** Decrypt an entry's file with the unlocked keys, or report which key needs unlocking
+     * first.
+     */
+    pub fn decrypt_file(&mut self, entry_path: &Path) -> Result<DecryptOutcome, Error> {
+        decrypt_pass_entry(entry_path, &mut self.session)
+    }
+
+    /// Encrypt and write an entry by name, see `encrypt_file`.
+    pub fn encrypt(&self, name: &str, plaintext: &str) -> Result<(), Error> {
+        self.encrypt_file(&self.entry_path(name)?, plaintext)
+    }
+
+    /** Encrypt an entry's file to the recipients of the `.gpg-id` applying to it, creating its
+     * folders as needed.
+     */
+    pub fn encrypt_file(&self, entry_path: &Path, plaintext: &str) -> Result<(), Error> {
+        write_pass_entry(entry_path, plaintext, &self.path, &self.session)
+    }
+
+    /** The key that must be unlocked before an entry's file can be decrypted, as its hex key ID
+     * and user, or `None` when one is already unlocked.
+     */
+    pub fn locked_key(&mut self, entry_path: &Path) -> Result<Option<(String, String)>, Error> {
+        let message = decrypt::deserialise_message(entry_path)?;
+        Ok(match self.session.keys_for(&message, Instant::now()) {
+            SessionKeys::Locked(key) => Some((hex::encode(key.key_id()), key_user(key))),
+            SessionKeys::Ready(_) => None,
+        })
+    }
+
+    /// The hex key IDs an entry's file is encrypted to.
+    pub fn message_recipients(&self, entry_path: &Path) -> Result<Vec<String>, Error> {
+        Ok(decrypt::deserialise_message(entry_path)?
+            .get_recipients()
+            .into_iter()
+            .map(hex::encode)
+            .collect())
+    }
+
+    /** Whether an entry's file is encrypted to other recipients than the `.gpg-id` applying to
+     * it names, as after moving it to another folder.
+     */
+    pub fn needs_reencrypt(&self, entry_path: &Path) -> Result<bool, Error> {
+        let wanted = recipients::entry_recipients(
+            entry_path,
+            &self.path,
+            self.session.keys(),
+            self.session.public_keys(),
+        )?;
+        let message = decrypt::deserialise_message(entry_path)?;
+        Ok(!reencrypt::encrypted_to(&message, &wanted))
+    }
+
+    /// The recipients of the `.gpg-id` applying to an entry, for display.
+    pub fn describe_recipients(&self, entry_path: &Path) -> Result<GpgIdRecipients, Error> {
+        recipients::describe_recipients(
+            entry_path,
+            &self.path,
+            self.session.keys(),
+            self.session.public_keys(),
+        )
+    }
+
+    /** Prepare to re-encrypt a folder of the store to new recipients, see `Reencryption`.
+     */
+    pub fn reencrypt(
+        &self,
+        folder: &Path,
+        recipient_ids: &[String],
+    ) -> Result<Reencryption, Error> {
+        Reencryption::start(
+            folder,
+            &self.path,
+            recipient_ids,
+            &self.session,
+            self.session.public_keys().to_vec(),
+        )
+    }
+}
+
+/** Outcome of trying to decrypt an entry with the currently unlocked keys.
+*/
+#[derive(Debug)]
+pub enum DecryptOutcome {
+    Decrypted(DecryptedMessage),
+    /// A recipient key needs its passphrase before the entry can be decrypted.
+    Locked {
+        key_id: String,
+        key_user: String,
+    },
+}
+
+fn load_secret_keys(settings: &Settings) -> Result<Vec<pgp::SignedSecretKey>, Error> {
+    settings
+        .secret_keys
+        .iter()
+        .map(|key_path| decrypt::load_signing_key(key_path))
+        .collect()
+}
+
+fn load_public_keys(settings: &Settings) -> Result<Vec<pgp::SignedPublicKey>, Error> {
+    let mut public_keys = vec![];
+    for key_path in &settings.public_keys {
+        public_keys.extend(decrypt::load_public_keys(key_path)?);
+    }
+    Ok(public_keys)
+}
+
+fn key_user(key: &pgp::SignedSecretKey) -> String {
+    key.details
+        .users
+        .first()
+        .map(|user| user.id.id().to_string())
+        .unwrap_or_else(|| "unknown user".to_string())
+}
+
+fn decrypt_pass_entry(
+    entry_path: &Path,
+    key_session: &mut KeySession,
+) -> Result<DecryptOutcome, Error> {
+    let message = decrypt::deserialise_message(entry_path)?;
+
+    key_session.touch(Instant::now());
+    let key_session = &*key_session;
+    let key_pass_pairs = match key_session.ready_keys(&message) {
+        SessionKeys::Ready(pairs) => pairs,
+        SessionKeys::Locked(key) => {
+            return Ok(DecryptOutcome::Locked {
+                key_id: hex::encode(key.key_id()),
+                key_user: key_user(key),
+            })
+        }
+    };
+
+    let decrypted_message =
+        decrypt::decrypt_message(message, &key_pass_pairs, key_session.public_keys())?;
+
+    Ok(DecryptOutcome::Decrypted(decrypted_message))
+}
+
+/** Encrypt an entry to the recipients of the `.gpg-id` applying to it, and write it to the store.
+*/
+fn write_pass_entry(
+    entry_path: &Path,
+    plaintext: &str,
+    store_path: &Path,
+    key_session: &KeySession,
+) -> Result<(), Error> {
+    let recipients = recipients::entry_recipients(
+        entry_path,
+        store_path,
+        key_session.keys(),
+        key_session.public_keys(),
+    )?;
+    let data = encrypt::encrypt_message(plaintext, &recipients)?;
+    encrypt::write_entry(entry_path, &data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgp::recipients::GPG_ID_FILE;
+    use crate::test_util::{init_logs, TmpTree, SAMPLE_CONTENT};
+    use anyhow::Result;
+
+    const SAMPLE_KEY_ID: &str = "f711232219df6593";
+
+    #[test]
+    fn test_store() -> Result<()> {
+        init_logs();
+        let tmp_tree = TmpTree::new();
+        std::fs::write(tmp_tree.base_path.join(GPG_ID_FILE), SAMPLE_KEY_ID)?;
+        let mut store = Store::open(Settings {
+            store_path: tmp_tree.base_path.clone(),
+            secret_keys: vec!["./src/pgp/sample_key.asc".into()],
+            public_keys: vec!["./src/pgp/sample_key.pub.asc".into()],
+            ..Settings::default()
+        })?;
+        // Public keys are loaded with the store, not on every decryption.
+        assert_eq!(store.session().public_keys().len(), 1);
+
+        store.encrypt("web/github", SAMPLE_CONTENT)?;
+        let names: Vec<String> = store
+            .entries()?
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["a/target", "b/target", "web/github"]);
+        assert_eq!(
+            store
+                .entry_name(&tmp_tree.base_path.join("web/github.gpg"))
+                .as_deref(),
+            Some("web/github")
+        );
+        assert!(store.entry_path("../outside").is_err());
+        assert!(store.entry_path(".").is_err());
+        assert!(store.entry_path("").is_err());
+        assert!(store.folder_path(".").is_err());
+        assert_eq!(store.folder_path("web")?, tmp_tree.base_path.join("web"));
+
+        let found = store.search("gthb")?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].entry.name, "web/github");
+        assert_eq!(store.search("")?.len(), 3);
+
+        assert!(matches!(
+            store.decrypt("web/github")?,
+            DecryptOutcome::Locked { key_id, .. } if key_id == SAMPLE_KEY_ID
+        ));
+        store.unlock(SAMPLE_KEY_ID, "sample")?;
+        match store.decrypt("web/github")? {
+            DecryptOutcome::Decrypted(message) => assert_eq!(message.plaintext, SAMPLE_CONTENT),
+            DecryptOutcome::Locked { .. } => panic!("the key was unlocked"),
+        }
+
+        assert!(matches!(
+            store.decrypt("web/gitlab"),
+            Err(Error::NotInStore { .. })
+        ));
+        Ok(())
+    }
+}
@@ -2,6 +2,14 @@ use log::{debug, LevelFilter};
 use std::{env, path::PathBuf};
 use uuid::Uuid;
 
+/// Plaintext of the sample entries under `src/pgp`.
+pub const SAMPLE_CONTENT: &str = concat!(
+    "4gG2y&9?-]AAE(wUnD]v22zs\"nx}ad\n",
+    "---\n",
+    "username: sample@example.com\n",
+    "some_key: foobar\n",
+);
+
 pub fn init_logs() {
     let _ = env_logger::Builder::from_default_env()
         .filter(Some("passrs"), LevelFilter::Debug)
@@ -25,11 +33,11 @@ impl TmpTree {
 
         let mut expected = vec![];
         for dir in tlds {
-            let target = base_dir.join(&dir);
+            let target = base_dir.join(dir);
             std::fs::create_dir_all(&target).unwrap();
 
             for file_ in per_dir_files {
-                let f_path = target.join(&file_);
+                let f_path = target.join(file_);
                 std::fs::write(&f_path, file_).unwrap();
                 expected.push(f_path.to_owned());
             }
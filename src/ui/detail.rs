@@ -0,0 +1,227 @@
+use std::path::PathBuf;
+
+use iced::widget::{button, column, row, scrollable, text, Column};
+use iced::{Alignment, Element, Length};
+use log::warn;
+
+use super::generator::GeneratorTarget;
+use super::Action;
+use passrs::entry::PassEntry;
+use passrs::otp::{self, OtpAuth, OtpKind};
+use passrs::SignatureStatus;
+
+/// Placeholder shown in place of hidden values.
+const MASK: &str = "••••••••";
+
+/** A decrypted entry, shown field by field with its secrets masked until revealed.
+*/
+pub struct EntryDetail {
+    pub entry_name: String,
+    /// Encrypted entry on disk, rewritten when a HOTP counter advances.
+    pub path: PathBuf,
+    pub entry: PassEntry,
+    pub signature: SignatureStatus,
+    /// Who the entry's folder encrypts to, from its `.gpg-id`.
+    pub recipients: String,
+    pub revealed: bool,
+    /// Generators for the entry's otpauth URIs, in order, skipping any which failed to parse.
+    pub otp: Vec<OtpAuth>,
+    /// Unix time the TOTP codes are shown for, updated every tick.
+    pub now: u64,
+    /// How the password breaks its folder's `.passrs-policy`, if it does.
+    pub policy_warning: Option<String>,
+}
+
+impl EntryDetail {
+    pub fn new(
+        entry_name: String,
+        path: PathBuf,
+        entry: PassEntry,
+        signature: SignatureStatus,
+        recipients: String,
+    ) -> Self {
+        let otp = entry
+            .otpauth
+            .iter()
+            .filter_map(|uri| match OtpAuth::parse(uri) {
+                Ok(otp) => Some(otp),
+                Err(err) => {
+                    warn!("Skipping otpauth URI in {}: {}", entry_name, err);
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            entry_name,
+            path,
+            entry,
+            signature,
+            recipients,
+            revealed: false,
+            otp,
+            now: otp::unix_time(),
+            policy_warning: None,
+        }
+    }
+
+    /// Whether any code shown changes over time, and so needs ticking.
+    pub fn has_totp(&self) -> bool {
+        self.otp
+            .iter()
+            .any(|otp| matches!(otp.kind, OtpKind::Totp { .. }))
+    }
+
+    /** The label and value of a copyable field; index 0 is the password, the rest follow the
+     * entry's fields in order.
+     */
+    pub fn copyable(&self, index: usize) -> Option<(&str, &str)> {
+        if index == 0 {
+            return Some(("password", &self.entry.password));
+        }
+        self.entry
+            .fields
+            .get(index - 1)
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn view<'a>(&'a self, notice: Option<&'a str>) -> Element<'a, Action> {
+        let header = row![
+            button("Back").on_press(Action::CloseDetail).padding(2),
+            text(&self.entry_name).size(24),
+            button("Edit").on_press(Action::EditEntry).padding(2),
+            button("New password")
+                .on_press(Action::OpenGenerator(GeneratorTarget::Entry))
+                .padding(2),
+        ]
+        .spacing(8)
+        .align_items(Alignment::Center);
+
+        let reveal_label = if self.revealed { "Hide" } else { "Reveal" };
+        let password = if self.revealed {
+            self.entry.password.as_str()
+        } else {
+            MASK
+        };
+
+        let mut fields = Column::new().spacing(4).push(field_row(
+            0,
+            "password",
+            password,
+            Some(
+                button(reveal_label)
+                    .on_press(Action::ToggleReveal)
+                    .padding(2),
+            ),
+        ));
+
+        for (idx, (key, value)) in self.entry.fields.iter().enumerate() {
+            // otpauth URIs carry the OTP seed, so are as sensitive as the password.
+            let value = if !self.revealed && self.entry.otpauth.contains(value) {
+                MASK
+            } else {
+                value.as_str()
+            };
+            fields = fields.push(field_row(idx + 1, key, value, None));
+        }
+
+        for (idx, otp) in self.otp.iter().enumerate() {
+            fields = fields.push(otp_row(idx, otp, self.now));
+        }
+
+        // URIs which didn't parse are still listed, so they aren't silently hidden.
+        for uri in &self.entry.otpauth {
+            let parsed = self.otp.iter().any(|otp| otp.uri() == uri);
+            if !parsed && !self.entry.fields.iter().any(|(_, value)| value == uri) {
+                let uri = if self.revealed { uri.as_str() } else { MASK };
+                fields = fields.push(row![text("otpauth").width(Length::Fixed(120.0)), text(uri)]);
+            }
+        }
+
+        if !self.entry.notes.is_empty() {
+            fields = fields.push(text(&self.entry.notes));
+        }
+
+        column![
+            header,
+            text(self.signature.to_string()),
+            text(format!("Encrypts to: {}", self.recipients)),
+            text(self.policy_warning.as_deref().unwrap_or_default()),
+            scrollable(fields).height(Length::Fill),
+            text(notice.unwrap_or_default()),
+        ]
+        .spacing(8)
+        .padding(8)
+        .width(Length::Fill)
+        .into()
+    }
+}
+
+/** A one-time code, with a countdown for TOTP, or a button to advance a HOTP counter.
+*/
+fn otp_row(index: usize, otp: &OtpAuth, now: u64) -> Element<'_, Action> {
+    let label = match &otp.issuer {
+        Some(issuer) => format!("{} ({})", issuer, otp.account),
+        None => otp.account.clone(),
+    };
+    let shortcut = if index == 0 { " (Ctrl+T)" } else { "" };
+
+    let (value, action) = match otp.kind {
+        OtpKind::Totp { .. } => (
+            format!(
+                "{}  ({}s)",
+                otp.code(now),
+                otp.remaining(now).unwrap_or_default()
+            ),
+            format!("Copy code{}", shortcut),
+        ),
+        OtpKind::Hotp { counter } => (
+            format!("counter {}", counter),
+            format!("Next code{}", shortcut),
+        ),
+    };
+
+    row![
+        text("otp").width(Length::Fixed(120.0)),
+        text(label).width(Length::Fill),
+        text(value),
+        button(text(action))
+            .on_press(Action::CopyOtp(index))
+            .padding(2),
+    ]
+    .spacing(8)
+    .align_items(Alignment::Center)
+    .into()
+}
+
+/** One labelled field, with its copy button and Ctrl+<number> shortcut hint.
+*/
+fn field_row<'a>(
+    index: usize,
+    label: &'a str,
+    value: &'a str,
+    extra: Option<iced::widget::Button<'a, Action>>,
+) -> Element<'a, Action> {
+    let shortcut = if index < 9 {
+        format!("Copy (Ctrl+{})", index + 1)
+    } else {
+        "Copy".to_string()
+    };
+
+    let mut line = row![
+        text(label).width(Length::Fixed(120.0)),
+        text(value).width(Length::Fill),
+    ]
+    .spacing(8)
+    .align_items(Alignment::Center);
+
+    if let Some(extra) = extra {
+        line = line.push(extra);
+    }
+    line.push(
+        button(text(shortcut))
+            .on_press(Action::CopyField(index))
+            .padding(2),
+    )
+    .into()
+}
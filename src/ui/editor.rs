@@ -0,0 +1,181 @@
+use std::path::{Component, Path, PathBuf};
+
+use iced::widget::{button, column, row, scrollable, text, text_input, Column};
+use iced::{Alignment, Element, Length};
+
+use zeroize::Zeroizing;
+
+use super::generator::GeneratorTarget;
+use super::Action;
+use passrs::entry::PassEntry;
+
+/** State for the form creating a new entry, or editing an existing one.
+ *
+ * iced has no multi-line text input, so everything after the password is edited as a list of
+ * single lines.
+*/
+pub struct EntryEditor {
+    /// Name of the entry being edited, or None when inserting a new one.
+    pub editing: Option<String>,
+    pub name: String,
+    pub password: Zeroizing<String>,
+    /// Lines after the password: fields, otpauth URIs and notes.
+    pub lines: Vec<String>,
+    /// Who the entry's folder encrypts to, from its `.gpg-id`.
+    pub recipients: String,
+    pub error: Option<String>,
+}
+
+impl EntryEditor {
+    pub fn insert() -> Self {
+        Self {
+            editing: None,
+            name: String::new(),
+            password: Zeroizing::new(String::new()),
+            lines: vec![],
+            recipients: String::new(),
+            error: None,
+        }
+    }
+
+    pub fn edit(entry_name: &str, entry: &PassEntry, recipients: String) -> Self {
+        let text = entry.to_string();
+        Self {
+            editing: Some(entry_name.to_string()),
+            name: entry_name.to_string(),
+            password: Zeroizing::new(entry.password.clone()),
+            lines: text.lines().skip(1).map(str::to_string).collect(),
+            recipients,
+            error: None,
+        }
+    }
+
+    /** The entry's new plaintext, in the same shape `pass insert` writes: the password first, one
+     * line per line, with a trailing newline.
+     */
+    pub fn plaintext(&self) -> Zeroizing<String> {
+        let mut lines: Vec<&str> = vec![&self.password];
+        lines.extend(self.lines.iter().map(String::as_str));
+        while lines.len() > 1 && lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+        Zeroizing::new(format!("{}\n", lines.join("\n")))
+    }
+
+    /** Where the entry is written, checking the name stays inside the store.
+     */
+    pub fn entry_path(&self, store_path: &Path) -> Result<PathBuf, String> {
+        let name = self.name.trim().trim_end_matches(".gpg");
+        if name.is_empty() {
+            return Err("Entry name is empty".to_string());
+        }
+        let relative = Path::new(name);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(format!("'{}' is not a valid entry name", name));
+        }
+        Ok(store_path.join(format!("{}.gpg", name)))
+    }
+
+    pub fn view(&self) -> Element<'_, Action> {
+        let heading = match &self.editing {
+            Some(name) => text(format!("Editing '{}'", name)),
+            None => text("New entry"),
+        }
+        .size(24);
+
+        // Renaming isn't supported here, so the name is fixed when editing.
+        let mut name = text_input("Name, e.g. email/example.com", &self.name).padding(2);
+        if self.editing.is_none() {
+            name = name.on_input(Action::EditorName);
+        }
+        let password = text_input("Password", self.password.as_str())
+            .on_input(Action::EditorPassword)
+            .password()
+            .padding(2);
+
+        let password = row![
+            password,
+            button("Generate")
+                .on_press(Action::OpenGenerator(GeneratorTarget::Editor))
+                .padding(2),
+        ]
+        .spacing(4)
+        .align_items(Alignment::Center);
+
+        let mut lines = Column::new().spacing(2);
+        for (idx, line) in self.lines.iter().enumerate() {
+            lines = lines.push(
+                row![
+                    text_input("key: value, or notes", line)
+                        .on_input(move |line| Action::EditorLine(idx, line))
+                        .padding(2),
+                    button("Remove")
+                        .on_press(Action::EditorRemoveLine(idx))
+                        .padding(2),
+                ]
+                .spacing(4)
+                .align_items(Alignment::Center),
+            );
+        }
+
+        let buttons = row![
+            button("Add line").on_press(Action::EditorAddLine),
+            button("Save").on_press(Action::EditorSave),
+            button("Cancel").on_press(Action::EditorCancel),
+        ]
+        .spacing(4);
+
+        column![
+            heading,
+            name,
+            password,
+            scrollable(lines).height(Length::Fill),
+            text(format!("Encrypts to: {}", self.recipients)),
+            text(self.error.as_deref().unwrap_or_default()),
+            buttons,
+        ]
+        .spacing(4)
+        .padding(8)
+        .width(Length::Fill)
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::SAMPLE_CONTENT;
+
+    #[test]
+    fn test_edit_round_trips_entry() {
+        let entry = PassEntry::parse(SAMPLE_CONTENT);
+        let editor = EntryEditor::edit("a/target", &entry, String::new());
+
+        assert_eq!(editor.password.as_str(), entry.password);
+        assert_eq!(editor.lines.len(), 3);
+        assert_eq!(editor.plaintext().as_str(), SAMPLE_CONTENT);
+    }
+
+    #[test]
+    fn test_insert_plaintext_and_path() {
+        let mut editor = EntryEditor::insert();
+        *editor.password = "hunter2".to_string();
+        editor.lines = vec!["login: sam".to_string(), "".to_string()];
+        assert_eq!(editor.plaintext().as_str(), "hunter2\nlogin: sam\n");
+
+        let store = Path::new("/store");
+        editor.name = "email/example.com".to_string();
+        assert_eq!(
+            editor.entry_path(store),
+            Ok(PathBuf::from("/store/email/example.com.gpg"))
+        );
+
+        for invalid in ["", "  ", "../outside", "/etc/passwd", "./a"] {
+            editor.name = invalid.to_string();
+            assert!(editor.entry_path(store).is_err(), "{:?}", invalid);
+        }
+    }
+}
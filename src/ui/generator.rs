@@ -0,0 +1,315 @@
+use std::path::Path;
+
+use iced::widget::{button, checkbox, column, row, slider, text, text_input, Column};
+use iced::{Element, Length};
+
+use zeroize::Zeroizing;
+
+use super::Action;
+use passrs::generator::folder_policy::FolderPolicy;
+use passrs::generator::passphrase::{self, PassphrasePolicy, Wordlist};
+use passrs::generator::{self, CharClass, PasswordPolicy};
+
+/// Longest length the slider offers, longer passwords can still be typed into the editor.
+const MAX_LENGTH: u16 = 128;
+const MAX_WORDS: u16 = 16;
+
+/** Where a password accepted from the generator goes.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorTarget {
+    /// The password field of the open entry form.
+    Editor,
+    /// The first line of the entry shown in the detail pane, rewritten in place.
+    Entry,
+}
+
+/** State for the password generator, regenerating its preview whenever the policy changes.
+*/
+pub struct GeneratorDialog {
+    pub policy: PasswordPolicy,
+    /// Set when generating a passphrase from words rather than random characters.
+    pub passphrase: Option<PassphrasePolicy>,
+    pub wordlist: Result<Wordlist, String>,
+    pub target: GeneratorTarget,
+    /// The password that's used if accepted, or why the policy can't produce one.
+    pub preview: Result<Zeroizing<String>, String>,
+    /// Bits of entropy in the preview.
+    pub entropy: f64,
+    /// The `.passrs-policy` for the entry's folder and its path, or why it couldn't be read.
+    pub folder: Result<Option<(String, FolderPolicy)>, String>,
+    /// How the preview breaks the folder's policy, if it does.
+    pub violations: Vec<String>,
+}
+
+impl GeneratorDialog {
+    /** Open the generator, starting from the folder's policy if it has one, and loading the
+     * user's wordlist for passphrases.
+     */
+    pub fn new(
+        length: usize,
+        wordlist: Option<&Path>,
+        folder: Result<Option<(String, FolderPolicy)>, String>,
+        target: GeneratorTarget,
+    ) -> Self {
+        let wordlist = match wordlist {
+            Some(path) => Wordlist::from_file(path).map_err(|err| err.to_string()),
+            None => Err(
+                "No wordlist configured. Set `wordlist` in config.toml, for instance to EFF's large \
+                 wordlist."
+                    .to_string(),
+            ),
+        };
+        let policy = match &folder {
+            Ok(Some((_, folder_policy))) => folder_policy.password_policy(length),
+            _ => PasswordPolicy {
+                length,
+                ..PasswordPolicy::default()
+            },
+        };
+        let mut dialog = Self {
+            policy,
+            passphrase: None,
+            wordlist,
+            target,
+            preview: Err(String::new()),
+            entropy: 0.0,
+            folder,
+            violations: vec![],
+        };
+        dialog.regenerate();
+        dialog
+    }
+
+    pub fn regenerate(&mut self) {
+        if let Some(policy) = &self.passphrase {
+            let generated = match &self.wordlist {
+                Ok(wordlist) => {
+                    passphrase::generate(policy, wordlist).map_err(|err| err.to_string())
+                }
+                Err(err) => Err(err.clone()),
+            };
+            self.preview = generated.map(|generated| {
+                self.entropy = generated.entropy;
+                generated.phrase
+            });
+        } else {
+            self.entropy = self.policy.entropy();
+            self.preview = generator::generate(&self.policy).map_err(|err| err.to_string());
+        }
+
+        self.violations = match (&self.folder, &self.preview) {
+            (Ok(Some((_, folder_policy))), Ok(password)) => folder_policy.violations(password),
+            _ => vec![],
+        };
+    }
+
+    pub fn set_words(&mut self, words: bool) {
+        self.passphrase = words.then(PassphrasePolicy::default);
+        self.regenerate();
+    }
+
+    /** Change the passphrase settings, when generating from words.
+     */
+    pub fn update_passphrase(&mut self, update: impl FnOnce(&mut PassphrasePolicy)) {
+        if let Some(policy) = &mut self.passphrase {
+            update(policy);
+            self.regenerate();
+        }
+    }
+
+    pub fn set_length(&mut self, length: u16) {
+        self.policy.length = length.into();
+        self.regenerate();
+    }
+
+    pub fn set_class(&mut self, class: CharClass, enabled: bool) {
+        self.policy.set_class(class, enabled);
+        self.regenerate();
+    }
+
+    /** Switch to letters and digits only, like `pass generate --no-symbols`.
+     */
+    pub fn no_symbols(&mut self) {
+        self.policy = PasswordPolicy::no_symbols(self.policy.length);
+        self.regenerate();
+    }
+
+    pub fn set_exclude_ambiguous(&mut self, exclude: bool) {
+        self.policy.exclude_ambiguous = exclude;
+        self.regenerate();
+    }
+
+    pub fn view(&self) -> Element<'_, Action> {
+        let heading = match self.target {
+            GeneratorTarget::Editor => "Generate password",
+            GeneratorTarget::Entry => "Replace password",
+        };
+
+        let mode = checkbox(
+            "Passphrase from words",
+            self.passphrase.is_some(),
+            Action::GeneratorWords,
+        );
+        let mut buttons = row![button("Regenerate").on_press(Action::GeneratorRegenerate)];
+
+        let options = if let Some(policy) = &self.passphrase {
+            let words = u16::try_from(policy.words).unwrap_or(MAX_WORDS);
+            column![
+                row![
+                    text(format!("Words: {}", words)).width(Length::Fixed(120.0)),
+                    slider(1..=MAX_WORDS, words, Action::GeneratorWordCount),
+                ]
+                .spacing(4),
+                text_input("Separator", &policy.separator)
+                    .on_input(Action::GeneratorSeparator)
+                    .padding(2),
+                checkbox(
+                    "Capitalise words",
+                    policy.capitalize,
+                    Action::GeneratorCapitalize
+                ),
+                checkbox("Add a digit", policy.digit, Action::GeneratorDigit),
+            ]
+        } else {
+            let length = u16::try_from(self.policy.length).unwrap_or(MAX_LENGTH);
+            let mut classes = Column::new().spacing(2);
+            for class in CharClass::ALL {
+                classes = classes.push(checkbox(
+                    class.to_string(),
+                    self.policy.includes(class),
+                    move |enabled| Action::GeneratorClass(class, enabled),
+                ));
+            }
+            buttons = buttons.push(button("No symbols").on_press(Action::GeneratorNoSymbols));
+
+            column![
+                row![
+                    text(format!("Length: {}", length)).width(Length::Fixed(120.0)),
+                    slider(1..=MAX_LENGTH, length, Action::GeneratorLength),
+                ]
+                .spacing(4),
+                classes,
+                checkbox(
+                    "Exclude ambiguous characters (0Oo1lI|)",
+                    self.policy.exclude_ambiguous,
+                    Action::GeneratorAmbiguous,
+                ),
+            ]
+        }
+        .spacing(4);
+
+        let mut accept = button("Use");
+        let preview = match &self.preview {
+            Ok(password) => {
+                accept = accept.on_press(Action::GeneratorUse);
+                column![
+                    text(password.as_str()),
+                    text(format!("About {:.0} bits of entropy", self.entropy)),
+                ]
+            }
+            Err(err) => column![text(err)],
+        };
+        let buttons = buttons
+            .push(accept)
+            .push(button("Cancel").on_press(Action::GeneratorCancel))
+            .spacing(4);
+
+        let folder = match &self.folder {
+            Ok(Some((path, _))) if self.violations.is_empty() => format!("Follows {}", path),
+            Ok(Some((path, _))) => format!("Breaks {}: {}", path, self.violations.join(", ")),
+            Ok(None) => String::new(),
+            Err(err) => err.clone(),
+        };
+
+        column![
+            text(heading).size(24),
+            mode,
+            options,
+            preview,
+            text(folder),
+            buttons
+        ]
+        .spacing(4)
+        .padding(8)
+        .width(Length::Fill)
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TmpTree;
+
+    #[test]
+    fn test_preview_follows_policy() {
+        let mut dialog = GeneratorDialog::new(20, None, Ok(None), GeneratorTarget::Editor);
+        assert_eq!(dialog.preview.as_ref().map(|p| p.len()), Ok(20));
+
+        dialog.no_symbols();
+        dialog.set_length(40);
+        let password = dialog.preview.clone().unwrap();
+        assert_eq!(password.len(), 40);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+
+        for class in CharClass::ALL {
+            dialog.set_class(class, false);
+        }
+        assert!(dialog.preview.is_err());
+    }
+
+    #[test]
+    fn test_passphrase_preview() -> std::io::Result<()> {
+        let tmp_tree = TmpTree::new();
+        let words = tmp_tree.base_path.join("words.txt");
+        let list: Vec<String> = (0..7776).map(|idx| format!("word{}", idx)).collect();
+        std::fs::write(&words, list.join("\n"))?;
+
+        let mut dialog = GeneratorDialog::new(20, Some(&words), Ok(None), GeneratorTarget::Editor);
+        dialog.set_words(true);
+        dialog.update_passphrase(|policy| {
+            policy.words = 5;
+            policy.separator = ".".to_string();
+        });
+
+        let phrase = dialog.preview.clone().unwrap();
+        assert_eq!(phrase.split('.').count(), 5);
+        assert!(dialog.entropy > 60.0);
+
+        let missing = Path::new("/nonexistent/words.txt");
+        let mut dialog = GeneratorDialog::new(20, Some(missing), Ok(None), GeneratorTarget::Editor);
+        assert!(dialog.preview.is_ok());
+        dialog.set_words(true);
+        assert!(dialog.preview.is_err());
+
+        // Without a configured wordlist only passphrases are unavailable.
+        let mut dialog = GeneratorDialog::new(20, None, Ok(None), GeneratorTarget::Editor);
+        assert!(dialog.preview.is_ok());
+        dialog.set_words(true);
+        assert!(dialog
+            .preview
+            .unwrap_err()
+            .contains("No wordlist configured"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_folder_policy() -> Result<(), toml::de::Error> {
+        let folder_policy: FolderPolicy = toml::from_str("max_length = 16\nsymbols = false")?;
+        let folder = Ok(Some((".passrs-policy".to_string(), folder_policy)));
+        let mut dialog = GeneratorDialog::new(25, None, folder, GeneratorTarget::Entry);
+
+        assert_eq!(dialog.policy.length, 16);
+        assert!(!dialog.policy.includes(CharClass::Symbols));
+        assert!(dialog.violations.is_empty());
+
+        dialog.set_class(CharClass::Symbols, true);
+        dialog.set_length(20);
+        assert_eq!(
+            dialog.violations,
+            vec!["longer than 16 characters", "contains symbols"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use iced::keyboard::{KeyCode, Modifiers};
+
+use passrs::settings::{KeyBinding, KeyName};
+
+/** Whether a key press, with its modifiers, matches a configured keybinding.
+*/
+pub fn binding_matches(binding: &KeyBinding, key_code: KeyCode, modifiers: Modifiers) -> bool {
+    key_name(key_code) == Some(binding.key)
+        && modifiers.control() == binding.ctrl
+        && modifiers.alt() == binding.alt
+        && modifiers.shift() == binding.shift
+}
+
+/** The field index copied by a Ctrl+<digit> shortcut in the detail view; Ctrl+1 is the first.
+*/
+pub fn copy_shortcut(key_code: KeyCode, modifiers: Modifiers) -> Option<usize> {
+    if !modifiers.control() || modifiers.alt() || modifiers.shift() {
+        return None;
+    }
+    let digit = match key_code {
+        KeyCode::Key1 | KeyCode::Numpad1 => 1,
+        KeyCode::Key2 | KeyCode::Numpad2 => 2,
+        KeyCode::Key3 | KeyCode::Numpad3 => 3,
+        KeyCode::Key4 | KeyCode::Numpad4 => 4,
+        KeyCode::Key5 | KeyCode::Numpad5 => 5,
+        KeyCode::Key6 | KeyCode::Numpad6 => 6,
+        KeyCode::Key7 | KeyCode::Numpad7 => 7,
+        KeyCode::Key8 | KeyCode::Numpad8 => 8,
+        KeyCode::Key9 | KeyCode::Numpad9 => 9,
+        _ => return None,
+    };
+    Some(digit - 1)
+}
+
+/** Map an iced key code onto the subset of keys which can be bound in the config.
+*/
+fn key_name(key_code: KeyCode) -> Option<KeyName> {
+    let name = match key_code {
+        KeyCode::Enter | KeyCode::NumpadEnter => KeyName::Enter,
+        KeyCode::Escape => KeyName::Escape,
+        KeyCode::Tab => KeyName::Tab,
+        KeyCode::Space => KeyName::Space,
+        KeyCode::Backspace => KeyName::Backspace,
+        KeyCode::Delete => KeyName::Delete,
+        KeyCode::Up => KeyName::Up,
+        KeyCode::Down => KeyName::Down,
+        KeyCode::Left => KeyName::Left,
+        KeyCode::Right => KeyName::Right,
+        KeyCode::Home => KeyName::Home,
+        KeyCode::End => KeyName::End,
+        KeyCode::PageUp => KeyName::PageUp,
+        KeyCode::PageDown => KeyName::PageDown,
+        KeyCode::A => KeyName::Letter('A'),
+        KeyCode::B => KeyName::Letter('B'),
+        KeyCode::C => KeyName::Letter('C'),
+        KeyCode::D => KeyName::Letter('D'),
+        KeyCode::E => KeyName::Letter('E'),
+        KeyCode::F => KeyName::Letter('F'),
+        KeyCode::G => KeyName::Letter('G'),
+        KeyCode::H => KeyName::Letter('H'),
+        KeyCode::I => KeyName::Letter('I'),
+        KeyCode::J => KeyName::Letter('J'),
+        KeyCode::K => KeyName::Letter('K'),
+        KeyCode::L => KeyName::Letter('L'),
+        KeyCode::M => KeyName::Letter('M'),
+        KeyCode::N => KeyName::Letter('N'),
+        KeyCode::O => KeyName::Letter('O'),
+        KeyCode::P => KeyName::Letter('P'),
+        KeyCode::Q => KeyName::Letter('Q'),
+        KeyCode::R => KeyName::Letter('R'),
+        KeyCode::S => KeyName::Letter('S'),
+        KeyCode::T => KeyName::Letter('T'),
+        KeyCode::U => KeyName::Letter('U'),
+        KeyCode::V => KeyName::Letter('V'),
+        KeyCode::W => KeyName::Letter('W'),
+        KeyCode::X => KeyName::Letter('X'),
+        KeyCode::Y => KeyName::Letter('Y'),
+        KeyCode::Z => KeyName::Letter('Z'),
+        _ => return None,
+    };
+    Some(name)
+}
@@ -1,21 +1,75 @@
+mod detail;
+mod editor;
+mod generator;
+mod keys;
+mod passphrase;
+mod reencrypt;
+
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use iced::widget::{column, container, scrollable, text, text_input, Text};
+use iced::keyboard::{KeyCode, Modifiers};
+use iced::widget::{
+    button, column, container, progress_bar, row, scrollable, text, text_input, Row,
+};
 use iced::{
-    executor, subscription, Application, Command, Element, Event, Length, Subscription, Theme,
+    executor, subscription, theme, Application, Color, Command, Element, Length, Subscription,
+    Theme,
 };
 
-use super::pass_scanner;
+use detail::EntryDetail;
+use editor::EntryEditor;
+use generator::{GeneratorDialog, GeneratorTarget};
 use log::{debug, error, info, warn};
+use passphrase::PassphrasePrompt;
+use passrs::clipboard::{self, Clipboard, CopiedSecret};
+use passrs::entry::PassEntry;
+use passrs::errors::Error;
+use passrs::generator::folder_policy::{self, FolderPolicy, POLICY_FILE};
+use passrs::generator::CharClass;
+use passrs::index;
+use passrs::otp;
+use passrs::pass_scanner::{
+    self, EntryMatch, ScanBatch, ScanOptions, ScanProgress, Scanner, StoreEntry,
+};
+use passrs::settings::ThemeName;
+use passrs::watcher::{self, StoreChange, StoreWatcher};
+use passrs::{DecryptOutcome, SignatureStatus, Store, GPG_ID_FILE};
+use reencrypt::ReencryptDialog;
+use zeroize::{Zeroize, Zeroizing};
+
+/// How long the store has to be quiet before changes to it are shown, so a checkout touching
+/// many entries updates the list once.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
 
 pub struct PassRS {
-    entries: Vec<PathBuf>,
-    entry_names: Vec<String>,
-    store_path: PathBuf,
+    store: Store,
+    entries: Vec<StoreEntry>,
+    /// Entries matching the search, best match first.
+    visible: Vec<EntryMatch>,
     search: String,
     selected: Option<usize>,
     last_search: String,
+    /// Entry shown in the detail pane, in place of the search list.
+    detail: Option<EntryDetail>,
+    reveal_count: u64,
+    /// Form for inserting or editing an entry, shown over everything but the passphrase prompt.
+    editor: Option<EntryEditor>,
+    /// Password generator, shown over the editor or the detail pane it fills in.
+    generator: Option<GeneratorDialog>,
+    reencrypt: Option<ReencryptDialog>,
+    prompt: Option<PassphrasePrompt>,
+    /// Opened on first use, as there may be no display server to copy to.
+    clipboard: Option<Box<dyn Clipboard>>,
+    /// Secret currently on the clipboard, tagged with the copy it came from. Restoring it is left
+    /// to a `clip-restore` process, this only carries the saved contents over to a later copy.
+    copied: Option<(u64, CopiedSecret)>,
+    copy_count: u64,
+    notice: Option<String>,
+    /// Progress of the scan filling in the list, while there's no index to start from.
+    scan: Option<ScanProgress>,
 }
 
 #[derive(Debug, Clone)]
@@ -24,56 +78,104 @@ pub enum Action {
     SelectDown,
     SelectUp,
     SelectEntry,
-    EventOccurred(Event),
+    KeyReleased(KeyCode, Modifiers),
+    PassphraseInput(Zeroizing<String>),
+    PassphraseSubmit,
+    PassphraseCancel,
+    Lock,
+    Tick(Instant),
+    /// Clipboard timeout for the given copy has elapsed.
+    ClipboardRestored(u64),
+    CloseDetail,
+    ToggleReveal,
+    /// Reveal timeout for the given reveal has elapsed.
+    Remask(u64),
+    CopyField(usize),
+    /// Copy the code of the given OTP generator, advancing it first if it's HOTP.
+    CopyOtp(usize),
+    NewEntry,
+    EditEntry,
+    EditorName(String),
+    EditorPassword(String),
+    EditorLine(usize, String),
+    EditorAddLine,
+    EditorRemoveLine(usize),
+    EditorSave,
+    EditorCancel,
+    OpenGenerator(GeneratorTarget),
+    GeneratorLength(u16),
+    GeneratorClass(CharClass, bool),
+    GeneratorAmbiguous(bool),
+    GeneratorNoSymbols,
+    GeneratorWords(bool),
+    GeneratorWordCount(u16),
+    GeneratorSeparator(String),
+    GeneratorCapitalize(bool),
+    GeneratorDigit(bool),
+    GeneratorRegenerate,
+    GeneratorUse,
+    GeneratorCancel,
+    OpenReencrypt,
+    ReencryptFolder(String),
+    ReencryptRecipients(String),
+    ReencryptStart,
+    /// Process the next entry of the running re-encryption.
+    ReencryptStep,
+    ReencryptClose,
+    /// Entries were added, removed or renamed by another program.
+    StoreChanged(Vec<StoreChange>),
+    /// Entries found by the scan filling in the list.
+    ScanBatch(ScanBatch),
+    ScanFinished(Result<ScanProgress, String>),
+    CancelScan,
 }
 
 impl Application for PassRS {
     type Executor = executor::Default;
     type Message = Action;
     type Theme = Theme;
-    type Flags = ();
-
-    fn new(_flags: Self::Flags) -> (PassRS, Command<Action>) {
-        let mut store_path = home::home_dir().unwrap();
-        store_path.push(".password-store");
-        let pass_entries = pass_scanner::collect_pass_files(&store_path);
+    type Flags = Store;
 
-        match pass_entries {
-            Ok(entries) => {
+    fn new(store: Self::Flags) -> (PassRS, Command<Action>) {
+        // Without a cached index to start from, the list fills in as the store is scanned.
+        let (entries, scan) = match store
+            .scanner()
+            .map(|scanner| index::cached_entries(&scanner))
+        {
+            Ok(Some(entries)) => {
                 info!("Found {} password entries.", entries.len());
-                let entry_names = entries
-                    .iter()
-                    .filter_map(|i| entry_to_ui_format(&i, &store_path))
-                    .collect();
-
-                (
-                    Self {
-                        entries,
-                        entry_names,
-                        store_path,
-                        search: "".to_string(),
-                        selected: None,
-                        last_search: "".to_string(),
-                    },
-                    Command::none(),
-                )
+                (entries, None)
             }
+            Ok(None) => (vec![], Some(ScanProgress::default())),
             Err(err) => {
                 warn!("Failed to retrieve store path entries: '{}'", err);
-
-                (
-                    Self {
-                        entries: vec![],
-                        entry_names: vec![],
-                        store_path,
-                        search: "".to_string(),
-                        selected: None,
-                        last_search: "".to_string(),
-                    },
-                    Command::none(),
-                )
+                (vec![], None)
             }
-        }
+        };
+        let visible = pass_scanner::filter_pass_entries(&entries, "").unwrap_or_default();
+
+        (
+            Self {
+                store,
+                entries,
+                visible,
+                search: "".to_string(),
+                selected: None,
+                last_search: "".to_string(),
+                detail: None,
+                reveal_count: 0,
+                editor: None,
+                generator: None,
+                reencrypt: None,
+                prompt: None,
+                clipboard: None,
+                copied: None,
+                copy_count: 0,
+                notice: None,
+                scan,
+            },
+            Command::none(),
+        )
     }
 
     fn title(&self) -> String {
@@ -82,6 +184,70 @@ impl Application for PassRS {
 
     fn update(&mut self, message: Action) -> Command<Action> {
         match message {
+            Action::KeyReleased(key_code, modifiers) => {
+                let bindings = &self.store.settings().keybindings;
+                if self.prompt.is_some() {
+                    // Only cancelling applies while the passphrase prompt has focus.
+                    if keys::binding_matches(&bindings.cancel, key_code, modifiers) {
+                        return self.update(Action::PassphraseCancel);
+                    }
+                    return Command::none();
+                }
+
+                if self.generator.is_some() {
+                    if keys::binding_matches(&bindings.cancel, key_code, modifiers) {
+                        return self.update(Action::GeneratorCancel);
+                    }
+                    return Command::none();
+                }
+                if self.editor.is_some() {
+                    if keys::binding_matches(&bindings.cancel, key_code, modifiers) {
+                        return self.update(Action::EditorCancel);
+                    }
+                    return Command::none();
+                }
+                if self.reencrypt.is_some() {
+                    if keys::binding_matches(&bindings.cancel, key_code, modifiers) {
+                        return self.update(Action::ReencryptClose);
+                    }
+                    return Command::none();
+                }
+
+                if self.detail.is_some() {
+                    if let Some(index) = keys::copy_shortcut(key_code, modifiers) {
+                        return self.update(Action::CopyField(index));
+                    }
+
+                    let bound = [
+                        (&bindings.cancel, Action::CloseDetail),
+                        (&bindings.reveal, Action::ToggleReveal),
+                        (&bindings.copy_otp, Action::CopyOtp(0)),
+                        (&bindings.edit, Action::EditEntry),
+                        (&bindings.lock, Action::Lock),
+                    ]
+                    .into_iter()
+                    .find(|(binding, _)| keys::binding_matches(binding, key_code, modifiers));
+
+                    if let Some((_, action)) = bound {
+                        return self.update(action);
+                    }
+                    return Command::none();
+                }
+
+                let bound = [
+                    (&bindings.select, Action::SelectEntry),
+                    (&bindings.up, Action::SelectUp),
+                    (&bindings.down, Action::SelectDown),
+                    (&bindings.lock, Action::Lock),
+                    (&bindings.new_entry, Action::NewEntry),
+                ]
+                .into_iter()
+                .find(|(binding, _)| keys::binding_matches(binding, key_code, modifiers));
+
+                if let Some((_, action)) = bound {
+                    return self.update(action);
+                }
+            }
             Action::SearchInput(input) => {
                 info!("SearchInput triggered: '{}'.", input);
                 self.last_search = self.search.clone();
@@ -93,29 +259,22 @@ impl Application for PassRS {
                     self.search, self.last_search
                 );
                 if self.search != self.last_search {
-                    let entry_strs = self
-                        .entries
-                        .iter()
-                        .filter_map(|i| entry_to_ui_format(&i, &self.store_path))
-                        .collect();
-                    self.entry_names = pass_scanner::filter_pass_entries(&entry_strs, &self.search)
-                        .unwrap_or(vec![]);
+                    self.filter_entries();
                 }
             }
-            Action::SelectUp => match self.selected {
-                Some(idx) => {
+            Action::SelectUp => {
+                if let Some(idx) = self.selected {
                     self.selected = if idx > 0 {
                         Some(idx - 1)
                     } else {
-                        Some(self.entries.len())
+                        Some(self.visible.len().saturating_sub(1))
                     }
                 }
-                None => {}
-            },
+            }
             Action::SelectDown => {
                 match self.selected {
                     Some(idx) => {
-                        self.selected = if idx + 1 == self.entries.len() {
+                        self.selected = if idx + 1 >= self.visible.len() {
                             // Roll from bottom to top of list
                             Some(0)
                         } else {
@@ -128,36 +287,372 @@ impl Application for PassRS {
                 }
             }
             Action::SelectEntry => {
-                let id = match self.selected {
-                    Some(idx) => idx,
-                    None => 0,
+                let id = self.selected.unwrap_or_default();
+
+                let Some(entry) = self.visible.get(id).map(|found| &self.entries[found.id]) else {
+                    return Command::none();
                 };
 
-                let entry_name = &self.entry_names[id];
-                let entry = match entry_from_ui_format(&entry_name, &self.store_path) {
-                    Some(ent) => ent,
-                    None => {
-                        warn!("Failed to resolve selected path entry: {}", entry_name);
+                return self.open_entry(entry.path.clone(), entry.name.clone());
+            }
+            Action::PassphraseInput(input) => {
+                if let Some(prompt) = &mut self.prompt {
+                    prompt.input = input;
+                }
+            }
+            Action::PassphraseSubmit => {
+                let Some(prompt) = &mut self.prompt else {
+                    return Command::none();
+                };
+
+                let unlocked = self.store.unlock(&prompt.key_id, &prompt.input);
+                prompt.input.zeroize();
+                match unlocked {
+                    Ok(()) => {
+                        let prompt = self.prompt.take().expect("prompt checked above");
+                        return self.open_entry(prompt.entry, prompt.entry_name);
+                    }
+                    Err(Error::BadPassphrase { .. }) => {
+                        prompt.attempts += 1;
+                        warn!(
+                            "Incorrect passphrase for key {} ({} attempts)",
+                            prompt.key_id, prompt.attempts
+                        );
+                        if prompt.exhausted() {
+                            error!("Too many incorrect passphrases, giving up");
+                            self.prompt = None;
+                        }
+                    }
+                    Err(err) => {
+                        error!("Failed to unlock key: {}", err);
+                        self.prompt = None;
+                    }
+                }
+            }
+            Action::PassphraseCancel => {
+                self.prompt = None;
+            }
+            Action::Lock => {
+                self.store.session_mut().lock();
+            }
+            Action::Tick(now) => {
+                if self.store.session_mut().expire(now) {
+                    info!("Key session idle, locked keys");
+                }
+                if let Some(detail) = &mut self.detail {
+                    detail.now = otp::unix_time();
+                }
+            }
+            Action::ClipboardRestored(copy) => {
+                // A later copy restarts the timeout, so only its own timer may clear the notice.
+                if matches!(&self.copied, Some((latest, _)) if *latest == copy) {
+                    self.copied = None;
+                    self.notice = None;
+                }
+            }
+            Action::CloseDetail => {
+                self.detail = None;
+                self.notice = None;
+            }
+            Action::ToggleReveal => {
+                let Some(detail) = &mut self.detail else {
+                    return Command::none();
+                };
+                detail.revealed = !detail.revealed;
+
+                if detail.revealed {
+                    self.reveal_count += 1;
+                    let reveal = self.reveal_count;
+                    return Command::perform(
+                        tokio::time::sleep(Duration::from_secs(
+                            self.store.settings().reveal_timeout,
+                        )),
+                        move |_| Action::Remask(reveal),
+                    );
+                }
+            }
+            Action::Remask(reveal) => {
+                // Toggling again restarts the timeout, only the latest reveal's timer applies.
+                if reveal == self.reveal_count {
+                    if let Some(detail) = &mut self.detail {
+                        detail.revealed = false;
+                    }
+                }
+            }
+            Action::CopyField(index) => {
+                let Some((label, value)) = self
+                    .detail
+                    .as_ref()
+                    .and_then(|detail| detail.copyable(index))
+                    .map(|(label, value)| (label.to_string(), value.to_string()))
+                else {
+                    return Command::none();
+                };
+                return self.copy_to_clipboard(&label, &value);
+            }
+            Action::CopyOtp(index) => {
+                let Some(detail) = &mut self.detail else {
+                    return Command::none();
+                };
+                let Some(mut generator) = detail.otp.get(index).cloned() else {
+                    return Command::none();
+                };
+
+                // HOTP codes are single use, so the advanced counter must be saved first.
+                if generator.increment() {
+                    let old_uri = detail.otp[index].uri();
+                    let updated = detail.entry.replace_otpauth(old_uri, generator.uri());
+                    if let Err(err) = self.store.encrypt_file(&detail.path, &updated.to_string()) {
+                        error!("Failed to save HOTP counter: {}", err);
+                        self.notice = Some(format!("Failed to save HOTP counter: {}", err));
                         return Command::none();
                     }
+                    detail.entry = updated;
+                    detail.otp[index] = generator.clone();
+                }
+
+                let code = generator.code(otp::unix_time());
+                return self.copy_to_clipboard("one-time code", &code);
+            }
+            Action::NewEntry => {
+                self.editor = Some(EntryEditor::insert());
+            }
+            Action::EditEntry => {
+                if let Some(detail) = &self.detail {
+                    self.editor = Some(EntryEditor::edit(
+                        &detail.entry_name,
+                        &detail.entry,
+                        detail.recipients.clone(),
+                    ));
+                }
+            }
+            Action::EditorName(name) => {
+                let Some(editor) = &mut self.editor else {
+                    return Command::none();
+                };
+                editor.name = name;
+
+                let entry_path = editor.entry_path(self.store.path()).ok();
+                let recipients = entry_path
+                    .map(|entry_path| self.folder_recipients(&entry_path))
+                    .unwrap_or_default();
+                if let Some(editor) = &mut self.editor {
+                    editor.recipients = recipients;
+                }
+            }
+            Action::EditorPassword(password) => {
+                if let Some(editor) = &mut self.editor {
+                    *editor.password = password;
+                }
+            }
+            Action::EditorLine(index, line) => {
+                if let Some(existing) = self
+                    .editor
+                    .as_mut()
+                    .and_then(|editor| editor.lines.get_mut(index))
+                {
+                    *existing = line;
+                }
+            }
+            Action::EditorAddLine => {
+                if let Some(editor) = &mut self.editor {
+                    editor.lines.push(String::new());
+                }
+            }
+            Action::EditorRemoveLine(index) => {
+                if let Some(editor) = &mut self.editor {
+                    if index < editor.lines.len() {
+                        editor.lines.remove(index);
+                    }
+                }
+            }
+            Action::EditorSave => {
+                self.save_editor();
+            }
+            Action::EditorCancel => {
+                self.editor = None;
+            }
+            Action::OpenGenerator(target) => {
+                // A new entry without a name yet gets the store root's policy.
+                let entry_path = match target {
+                    GeneratorTarget::Editor => self
+                        .editor
+                        .as_ref()
+                        .and_then(|editor| editor.entry_path(self.store.path()).ok()),
+                    GeneratorTarget::Entry => {
+                        self.detail.as_ref().map(|detail| detail.path.clone())
+                    }
+                };
+                let entry_path = entry_path.unwrap_or_else(|| self.store.path().join(POLICY_FILE));
+
+                self.generator = Some(GeneratorDialog::new(
+                    self.store.settings().generated_length,
+                    self.store.settings().wordlist.as_deref(),
+                    self.folder_policy(&entry_path),
+                    target,
+                ));
+            }
+            Action::GeneratorLength(length) => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.set_length(length);
+                }
+            }
+            Action::GeneratorClass(class, enabled) => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.set_class(class, enabled);
+                }
+            }
+            Action::GeneratorAmbiguous(exclude) => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.set_exclude_ambiguous(exclude);
+                }
+            }
+            Action::GeneratorNoSymbols => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.no_symbols();
+                }
+            }
+            Action::GeneratorWords(words) => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.set_words(words);
+                }
+            }
+            Action::GeneratorWordCount(words) => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.update_passphrase(|policy| policy.words = words.into());
+                }
+            }
+            Action::GeneratorSeparator(separator) => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.update_passphrase(|policy| policy.separator = separator);
+                }
+            }
+            Action::GeneratorCapitalize(capitalize) => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.update_passphrase(|policy| policy.capitalize = capitalize);
+                }
+            }
+            Action::GeneratorDigit(digit) => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.update_passphrase(|policy| policy.digit = digit);
+                }
+            }
+            Action::GeneratorRegenerate => {
+                if let Some(dialog) = &mut self.generator {
+                    dialog.regenerate();
+                }
+            }
+            Action::GeneratorUse => {
+                self.use_generated();
+            }
+            Action::GeneratorCancel => {
+                self.generator = None;
+            }
+            Action::OpenReencrypt => {
+                let current = self.folder_recipients(&self.store.path().join(GPG_ID_FILE));
+                self.reencrypt = Some(ReencryptDialog::new(current));
+            }
+            Action::ReencryptFolder(folder) => {
+                let Some(dialog) = &mut self.reencrypt else {
+                    return Command::none();
                 };
+                dialog.folder = folder;
 
-                let decrypted_message = decrypt_pass_entry(&entry);
-                match decrypted_message {
-                    Ok(data) => info!("Decrypted data:\n{}", data),
-                    Err(err) => error!("Decrypting failed: {}", err),
+                let current = match dialog.folder_path(self.store.path()) {
+                    Ok(folder) => self.folder_recipients(&folder.join(GPG_ID_FILE)),
+                    Err(err) => err,
+                };
+                if let Some(dialog) = &mut self.reencrypt {
+                    dialog.current = current;
+                }
+            }
+            Action::ReencryptRecipients(recipients) => {
+                if let Some(dialog) = &mut self.reencrypt {
+                    dialog.recipients = recipients;
+                }
+            }
+            Action::ReencryptStart => {
+                return self.start_reencrypt();
+            }
+            Action::ReencryptStep => {
+                return self.step_reencrypt();
+            }
+            Action::ReencryptClose => {
+                if let Some(dialog) = self.reencrypt.take() {
+                    if dialog.running.is_some() {
+                        info!("Re-encryption cancelled, no entries were changed");
+                    }
+                }
+            }
+            Action::StoreChanged(changes) => self.apply_store_changes(changes),
+            Action::ScanBatch(batch) => {
+                if let Some(progress) = self.scan.as_mut() {
+                    // Workers hand over batches in whatever order they finish them, so progress
+                    // reported by one can be behind what another already reported.
+                    progress.folders_found =
+                        progress.folders_found.max(batch.progress.folders_found);
+                    progress.folders_scanned =
+                        progress.folders_scanned.max(batch.progress.folders_scanned);
+                    progress.entries = progress.entries.max(batch.progress.entries);
+                    self.add_entries(batch.entries);
+                }
+            }
+            Action::ScanFinished(result) => {
+                if self.scan.is_some() {
+                    self.scan = None;
+                    match result {
+                        Ok(progress) => {
+                            info!(
+                                "Found {} password entries in {} folders.",
+                                progress.entries, progress.folders_scanned
+                            );
+                            self.build_index();
+                        }
+                        Err(err) => {
+                            warn!("Failed to scan the store: {}", err);
+                            self.notice = Some(format!("Failed to scan the store: {}", err));
+                        }
+                    }
+                }
+            }
+            Action::CancelScan => {
+                if self.scan.take().is_some() {
+                    self.notice = Some(format!(
+                        "Scan cancelled, showing the {} entries found so far.",
+                        self.entries.len()
+                    ));
                 }
             }
-            Action::EventOccurred(_event) => {}
         }
         Command::none()
     }
 
-    fn view(&self) -> Element<Action> {
-        let mut dark_row = iced::widget::container::Appearance::default();
-        dark_row.background = Some(iced::color!(10, 10, 10).into());
+    fn view(&self) -> Element<'_, Action> {
+        if let Some(prompt) = &self.prompt {
+            return prompt.view();
+        }
+        if let Some(dialog) = &self.generator {
+            return dialog.view();
+        }
+        if let Some(editor) = &self.editor {
+            return editor.view();
+        }
+        if let Some(dialog) = &self.reencrypt {
+            return dialog.view();
+        }
+        if let Some(detail) = &self.detail {
+            return detail.view(self.notice.as_deref());
+        }
 
-        let entry_names = render_pass_entries(&self.entry_names, &self.store_path);
+        let entry_names = render_pass_entries(
+            self.visible.iter().map(|found| {
+                (
+                    self.entries[found.id].name.as_str(),
+                    found.indices.as_slice(),
+                )
+            }),
+            self.theme().palette().primary,
+        );
         let entry_names: Vec<iced::widget::Container<Action, iced::Renderer>> = entry_names
             .into_iter()
             .enumerate()
@@ -170,7 +665,7 @@ impl Application for PassRS {
                     }
                 }
                 // Make a normally-themed container.
-                return container(entry);
+                container(entry)
             })
             .collect();
 
@@ -180,98 +675,660 @@ impl Application for PassRS {
             .on_input(Action::SearchInput)
             .padding(2);
 
-        column![search_box, scroll_box]
+        let lock_status = if self.store.session().is_unlocked() {
+            row![
+                text("Keys unlocked"),
+                button("Lock").on_press(Action::Lock).padding(2),
+            ]
+        } else {
+            row![text("Keys locked")]
+        }
+        .push(button("New entry").on_press(Action::NewEntry).padding(2))
+        .push(
+            button("Re-encrypt folder")
+                .on_press(Action::OpenReencrypt)
+                .padding(2),
+        )
+        .spacing(4);
+
+        let notice = text(self.notice.as_deref().unwrap_or_default());
+
+        let mut content = column![search_box, lock_status, notice];
+        if let Some(progress) = self.scan {
+            content = content.push(
+                row![
+                    progress_bar(
+                        0.0..=progress.folders_found.max(1) as f32,
+                        progress.folders_scanned as f32
+                    )
+                    .height(Length::Fixed(12.0))
+                    .width(Length::FillPortion(1)),
+                    text(format!(
+                        "Scanning: {} entries in {} of {} folders",
+                        progress.entries, progress.folders_scanned, progress.folders_found
+                    )),
+                    button("Cancel").on_press(Action::CancelScan).padding(2),
+                ]
+                .spacing(4),
+            );
+        }
+
+        content
+            .push(scroll_box)
             .spacing(2)
             .width(Length::Fill)
             .into()
     }
 
+    fn theme(&self) -> Theme {
+        match self.store.settings().theme {
+            ThemeName::Light => Theme::Light,
+            ThemeName::Dark => Theme::Dark,
+        }
+    }
+
     fn subscription(&self) -> Subscription<Action> {
-        // TODO: Filter to keyboard enter etc...
-        subscription::events_with(|event, status| {
+        // Key presses are resolved against the configured keybindings in `update`.
+        let events = subscription::events_with(|event, _status| {
             match event {
-                iced::Event::Keyboard(key) => {
-                    //debug!("received Keyboard event: {:?}", key);
-                    match key {
-                        //iced::keyboard::Event::KeyPressed {
-                        //    key_code,
-                        //    modifiers,
-                        //} => {
-                        //    debug!("received Key Press: {:?}|{:?}", key_code, modifiers);
-                        //    None
-                        //}
-                        iced::keyboard::Event::KeyReleased {
-                            key_code,
-                            modifiers: _,
-                        } => match key_code {
-                            iced::keyboard::KeyCode::Enter => Some(Action::SelectEntry),
-                            iced::keyboard::KeyCode::Down => Some(Action::SelectDown),
-                            iced::keyboard::KeyCode::Up => Some(Action::SelectUp),
-                            _ => None,
-                        },
-                        //iced::keyboard::Event::ModifiersChanged(modifiers) => {
-                        //    debug!("Modifiers changed: {:?}", modifiers);
-                        //    None
-                        //}
-                        _ => None,
-                    }
-                }
+                iced::Event::Keyboard(iced::keyboard::Event::KeyReleased {
+                    key_code,
+                    modifiers,
+                }) => Some(Action::KeyReleased(key_code, modifiers)),
                 iced::Event::Window(win) => {
                     debug!("received Window event: {:?}", win);
                     None
                 }
                 _ => None, // Ignore mouse events for now.
             }
-        })
+        });
+
+        let watch = watch_store(
+            self.store.path().to_path_buf(),
+            ScanOptions::from(self.store.settings()),
+        );
+        let mut events = Subscription::batch([events, watch]);
+        // Cancelling drops the subscription, which stops the scan.
+        if self.scan.is_some() {
+            let scan = scan_store(
+                self.store.path().to_path_buf(),
+                ScanOptions::from(self.store.settings()),
+            );
+            events = Subscription::batch([events, scan]);
+        }
+
+        // Only tick while there's an unlocked session to expire, or a TOTP code counting down.
+        let totp_shown = self.detail.as_ref().is_some_and(|detail| detail.has_totp());
+        if self.store.session().is_unlocked() || totp_shown {
+            Subscription::batch([
+                events,
+                iced::time::every(Duration::from_secs(1)).map(Action::Tick),
+            ])
+        } else {
+            events
+        }
     }
 }
 
-fn entry_to_ui_format(entry: &PathBuf, base_path: &Path) -> Option<String> {
-    let rel_path = entry.strip_prefix(&base_path);
+impl PassRS {
+    /** Decrypt an entry, or prompt for a passphrase if its recipient key is still locked.
+     */
+    fn open_entry(&mut self, entry: PathBuf, entry_name: String) -> Command<Action> {
+        match self.store.decrypt_file(&entry) {
+            Ok(DecryptOutcome::Decrypted(data)) => {
+                debug!("Decrypted entry {}", entry_name);
+                let pass_entry = PassEntry::parse(&data.plaintext);
+                let command = self.copy_to_clipboard("password", &pass_entry.password);
+                let recipients = self.folder_recipients(&entry);
+                self.detail = Some(EntryDetail::new(
+                    entry_name,
+                    entry,
+                    pass_entry,
+                    data.signature,
+                    recipients,
+                ));
+                self.check_policy();
+                return command;
+            }
+            Ok(DecryptOutcome::Locked { key_id, key_user }) => {
+                info!("Key {} is locked, prompting for passphrase", key_id);
+                self.prompt = Some(PassphrasePrompt::new(entry, entry_name, key_id, key_user));
+            }
+            Err(err) => {
+                error!("Decrypting failed: {}", err);
+                self.notice = Some(format!("Failed to decrypt {}: {}", entry_name, err));
+            }
+        }
+        Command::none()
+    }
+
+    /** Encrypt and write the entry in the editor, to the recipients of its `.gpg-id`.
+     *
+     * On failure the editor stays open with the error, so nothing typed is lost.
+     */
+    fn save_editor(&mut self) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+
+        let entry_path = match editor.entry_path(self.store.path()) {
+            Ok(entry_path) => entry_path,
+            Err(err) => {
+                editor.error = Some(err);
+                return;
+            }
+        };
+        if editor.editing.is_none() && entry_path.exists() {
+            editor.error = Some(format!("'{}' already exists", editor.name.trim()));
+            return;
+        }
+
+        let plaintext = editor.plaintext();
+        if let Err(err) = self.store.encrypt_file(&entry_path, &plaintext) {
+            error!("Failed to save entry: {}", err);
+            editor.error = Some(err.to_string());
+            return;
+        }
 
-    match rel_path {
-        Ok(p) => {
-            let entry_name = p.to_string_lossy().replace(".gpg", "");
-            Some(entry_name)
+        let entry_name = self.store.entry_name(&entry_path).unwrap_or_default();
+        info!("Saved entry {}", entry_name);
+        self.notice = Some(format!("Saved '{}'.", entry_name));
+
+        // An edited entry goes back to its detail view, showing the new contents.
+        if self
+            .editor
+            .take()
+            .and_then(|editor| editor.editing)
+            .is_some()
+        {
+            let recipients = self.folder_recipients(&entry_path);
+            self.detail = Some(EntryDetail::new(
+                entry_name,
+                entry_path,
+                PassEntry::parse(&plaintext),
+                SignatureStatus::Unsigned,
+                recipients,
+            ));
+            self.check_policy();
+        }
+        self.refresh_entries();
+    }
+
+    /** Put the generator's password where it was asked for: into the entry form, or as the new
+     * first line of the entry in the detail pane, leaving the rest of it untouched.
+     */
+    fn use_generated(&mut self) {
+        let Some(dialog) = self.generator.take() else {
+            return;
+        };
+        let Ok(password) = dialog.preview else {
+            return;
+        };
+
+        match dialog.target {
+            GeneratorTarget::Editor => {
+                if let Some(editor) = &mut self.editor {
+                    editor.password = password;
+                }
+            }
+            GeneratorTarget::Entry => {
+                let Some(detail) = &mut self.detail else {
+                    return;
+                };
+                let updated = detail.entry.with_password(&password);
+                if let Err(err) = self.store.encrypt_file(&detail.path, &updated.to_string()) {
+                    error!("Failed to save generated password: {}", err);
+                    self.notice = Some(format!("Failed to save generated password: {}", err));
+                    return;
+                }
+                info!("Generated a new password for {}", detail.entry_name);
+                self.notice = Some(format!(
+                    "Generated a new password for '{}'.",
+                    detail.entry_name
+                ));
+                detail.entry = updated;
+                detail.signature = SignatureStatus::Unsigned;
+                self.check_policy();
+            }
+        }
+    }
+
+    /** Begin re-encrypting the dialog's folder, stepping through entries in later updates so
+     * progress is shown as it goes.
+     */
+    fn start_reencrypt(&mut self) -> Command<Action> {
+        let Some(dialog) = &mut self.reencrypt else {
+            return Command::none();
+        };
+
+        let started = dialog.folder_path(self.store.path()).and_then(|folder| {
+            self.store
+                .reencrypt(&folder, &dialog.recipient_ids())
+                .map_err(|err| err.to_string())
+        });
+        match started {
+            Ok(reencryption) => {
+                dialog.running = Some(reencryption);
+                dialog.status = Some("Re-encrypting...".to_string());
+                Command::perform(async {}, |_| Action::ReencryptStep)
+            }
+            Err(err) => {
+                dialog.status = Some(err);
+                Command::none()
+            }
+        }
+    }
+
+    fn step_reencrypt(&mut self) -> Command<Action> {
+        let Some(dialog) = &mut self.reencrypt else {
+            return Command::none();
+        };
+        let Some(reencryption) = &mut dialog.running else {
+            return Command::none();
+        };
+
+        match reencryption.step(self.store.session_mut()) {
+            Ok(Some(entry)) => {
+                let (done, total) = reencryption.progress();
+                let entry_name = self.store.entry_name(&entry).unwrap_or_default();
+                dialog.status = Some(format!("{} of {}: {}", done, total, entry_name));
+                return Command::perform(async {}, |_| Action::ReencryptStep);
+            }
+            Ok(None) => {
+                let reencryption = dialog.running.take().expect("running checked above");
+                dialog.status = Some(match reencryption.commit() {
+                    Ok(report) => format!(
+                        "Re-encrypted {} entries, {} already up to date.",
+                        report.reencrypted.len(),
+                        report.skipped.len()
+                    ),
+                    Err(err) => format!("Failed, no entries were changed: {}", err),
+                });
+            }
+            Err(err) => {
+                error!("Re-encryption failed: {}", err);
+                dialog.running = None;
+                dialog.status = Some(format!("Failed, no entries were changed: {}", err));
+            }
+        }
+        self.refresh_entries();
+        Command::none()
+    }
+
+    /** Describe the recipients of the `.gpg-id` applying to an entry, or why they're unknown.
+     */
+    fn folder_recipients(&self, entry_path: &Path) -> String {
+        match self.store.describe_recipients(entry_path) {
+            Ok(described) => described.to_string(),
+            Err(err) => err.to_string(),
         }
-        Err(_) => None,
     }
-}
 
-fn entry_from_ui_format(entry_name: &str, base_path: &Path) -> Option<PathBuf> {
-    let mut rel_path = base_path.to_owned();
+    /** The `.passrs-policy` governing an entry, with its path relative to the store for display.
+     */
+    fn folder_policy(&self, entry_path: &Path) -> Result<Option<(String, FolderPolicy)>, String> {
+        match folder_policy::entry_policy(entry_path, self.store.path()) {
+            Ok(Some((path, policy))) => {
+                let path = path.strip_prefix(self.store.path()).unwrap_or(&path);
+                Ok(Some((path.to_string_lossy().to_string(), policy)))
+            }
+            Ok(None) => Ok(None),
+            Err(err) => {
+                warn!("Failed to load password policy: {}", err);
+                Err(err.to_string())
+            }
+        }
+    }
+
+    /** Flag the detail pane's password if it breaks its folder's policy.
+     */
+    fn check_policy(&mut self) {
+        let Some(detail) = &self.detail else {
+            return;
+        };
+        let warning = match self.folder_policy(&detail.path) {
+            Ok(Some((path, policy))) => {
+                let violations = policy.violations(&detail.entry.password);
+                (!violations.is_empty())
+                    .then(|| format!("Password breaks {}: {}", path, violations.join(", ")))
+            }
+            Ok(None) => None,
+            Err(err) => Some(err),
+        };
+        if let Some(detail) = &mut self.detail {
+            detail.policy_warning = warning;
+        }
+    }
+
+    /** Rescan the store, e.g. after adding an entry, keeping the current search applied.
+     */
+    fn refresh_entries(&mut self) {
+        // The rescan lists everything, so a scan still running has nothing left to add.
+        self.scan = None;
+        self.entries = match self
+            .store
+            .scanner()
+            .and_then(|scanner| index::load_entries(&scanner))
+        {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("Failed to retrieve store path entries: '{}'", err);
+                vec![]
+            }
+        };
+        self.filter_entries();
+    }
+
+    /** Apply changes made to the store by other programs, keeping the selected entry selected
+     * wherever it moves to in the list.
+     */
+    fn apply_store_changes(&mut self, changes: Vec<StoreChange>) {
+        let selected = self.selected_path();
+
+        let applied = self
+            .store
+            .scanner()
+            .and_then(|scanner| watcher::apply_changes(&mut self.entries, &scanner, &changes));
+        if let Err(err) = applied {
+            warn!("Failed to update entries, rescanning the store: '{}'", err);
+            self.refresh_entries();
+        } else {
+            self.filter_entries();
+        }
+        debug!(
+            "Applied {} store changes, {} entries",
+            changes.len(),
+            self.entries.len()
+        );
+
+        self.reselect(selected);
+    }
+
+    /** Add entries found by a scan, skipping any the watcher added in the meantime.
+     */
+    fn add_entries(&mut self, entries: Vec<StoreEntry>) {
+        let selected = self.selected_path();
+        let known: HashSet<PathBuf> = self
+            .entries
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect();
+        self.entries.extend(
+            entries
+                .into_iter()
+                .filter(|entry| !known.contains(&entry.path)),
+        );
+        self.entries.sort_by(|a, b| a.name.cmp(&b.name));
+        self.filter_entries();
+        self.reselect(selected);
+    }
+
+    /** Build the index the next launch starts from, off the UI thread. The store was just
+     * scanned, so its folders are still cached by the system.
+     */
+    fn build_index(&self) {
+        let store_path = self.store.path().to_path_buf();
+        let options = ScanOptions::from(self.store.settings());
+        std::thread::spawn(move || {
+            let indexed = Scanner::new(&store_path, options)
+                .and_then(|scanner| index::load_entries(&scanner));
+            if let Err(err) = indexed {
+                warn!("Failed to index the store: {}", err);
+            }
+        });
+    }
+
+    fn selected_path(&self) -> Option<PathBuf> {
+        self.selected
+            .and_then(|idx| self.visible.get(idx))
+            .map(|found| self.entries[found.id].path.clone())
+    }
+
+    /// Select an entry again after the list changed, wherever it moved to.
+    fn reselect(&mut self, selected: Option<PathBuf>) {
+        if let Some(selected) = selected {
+            let position = self
+                .visible
+                .iter()
+                .position(|found| self.entries[found.id].path == selected);
+            self.selected = match position {
+                Some(idx) => Some(idx),
+                None if self.visible.is_empty() => None,
+                None => self.selected.map(|idx| idx.min(self.visible.len() - 1)),
+            };
+        }
+    }
+
+    /** Show the entries matching the search, best match first.
+     */
+    fn filter_entries(&mut self) {
+        self.visible =
+            pass_scanner::filter_pass_entries(&self.entries, &self.search).unwrap_or_default();
+    }
+
+    /** Copy a secret to the clipboard, restoring the previous contents after the timeout.
+     *
+     * The restore is handed to a `clip-restore` process, as the command line does, so the
+     * clipboard is still cleared if passrs is closed first.
+     */
+    fn copy_to_clipboard(&mut self, label: &str, secret: &str) -> Command<Action> {
+        if self.clipboard.is_none() {
+            self.clipboard = clipboard::display_clipboard();
+        }
+        let Some(clipboard) = &mut self.clipboard else {
+            error!("{}", Error::NoDisplay);
+            self.notice = Some(Error::NoDisplay.to_string());
+            return Command::none();
+        };
+
+        let pending = self.copied.take().map(|(_, copied)| copied);
+        let timeout = self.store.settings().clipboard_timeout;
+        let copied = CopiedSecret::copy(clipboard.as_mut(), secret, pending).and_then(|copied| {
+            clipboard::restore_in_background(&copied, timeout)?;
+            Ok(copied)
+        });
+        match copied {
+            Ok(copied) => {
+                self.copy_count += 1;
+                let copy = self.copy_count;
+                self.copied = Some((copy, copied));
 
-    // Entry name may have a fuzzy match prefix on it...
-    let mut entry = entry_name;
-    entry = match entry.split_once(": ") {
-        Some((_, ent)) => ent,
-        None => entry,
-    };
+                self.notice = Some(format!(
+                    "Copied {} to clipboard, clearing in {} seconds.",
+                    label, timeout
+                ));
+                Command::perform(
+                    tokio::time::sleep(Duration::from_secs(timeout)),
+                    move |_| Action::ClipboardRestored(copy),
+                )
+            }
+            Err(err) => {
+                error!("Failed to copy to clipboard: {}", err);
+                self.notice = Some(err.to_string());
+                Command::none()
+            }
+        }
+    }
+}
 
-    rel_path.push(entry);
-    rel_path.set_extension("gpg");
+/** Report changes to the store made outside of passrs, as by `git pull`. The watcher blocks, so
+ * it runs on its own thread, passing batches of changes back over a channel.
+*/
+fn watch_store(store_path: PathBuf, options: ScanOptions) -> Subscription<Action> {
+    use iced::futures::channel::mpsc;
+    use iced::futures::StreamExt;
 
-    if rel_path.exists() && rel_path.is_file() {
-        return Some(rel_path);
+    enum State {
+        Starting(PathBuf, ScanOptions),
+        Watching(mpsc::UnboundedReceiver<Vec<StoreChange>>),
+        Stopped,
     }
-    None
+
+    subscription::unfold(
+        (store_path.clone(), options.clone()),
+        State::Starting(store_path, options),
+        |mut state| async move {
+            loop {
+                state = match state {
+                    State::Starting(store_path, options) => {
+                        match Scanner::new(&store_path, options).and_then(StoreWatcher::new) {
+                            Ok(mut store_watcher) => {
+                                let (sender, receiver) = mpsc::unbounded();
+                                std::thread::spawn(move || loop {
+                                    match store_watcher.wait(WATCH_DEBOUNCE) {
+                                        Ok(changes) if changes.is_empty() => {}
+                                        Ok(changes) => {
+                                            if sender.unbounded_send(changes).is_err() {
+                                                break;
+                                            }
+                                        }
+                                        Err(err) => {
+                                            warn!("Stopped watching the store: {}", err);
+                                            break;
+                                        }
+                                    }
+                                });
+                                State::Watching(receiver)
+                            }
+                            Err(err) => {
+                                warn!("Failed to watch the store for changes: {}", err);
+                                State::Stopped
+                            }
+                        }
+                    }
+                    State::Watching(mut receiver) => match receiver.next().await {
+                        Some(changes) => {
+                            return (Action::StoreChanged(changes), State::Watching(receiver))
+                        }
+                        None => State::Stopped,
+                    },
+                    State::Stopped => iced::futures::future::pending().await,
+                }
+            }
+        },
+    )
 }
 
-fn render_pass_entries<'a>(entries: &Vec<String>, base_path: &Path) -> Vec<Text<'a>> {
-    let matches = entries.iter().map(|i| text(i.to_owned())).collect();
+/** Scan the store on worker threads, passing entries back as they're found. Once the
+ * subscription is dropped, the next batch finds no one to take it and the scan stops.
+*/
+fn scan_store(store_path: PathBuf, options: ScanOptions) -> Subscription<Action> {
+    use iced::futures::channel::mpsc;
+    use iced::futures::StreamExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Stops the scan once the subscription is dropped, as when it's cancelled.
+    struct CancelOnDrop(Arc<AtomicBool>);
 
+    impl Drop for CancelOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    enum State {
+        Starting(PathBuf, ScanOptions),
+        Scanning(mpsc::UnboundedReceiver<Action>, CancelOnDrop),
+        Finished,
+    }
+
+    subscription::unfold(
+        ("scan", store_path.clone(), options.clone()),
+        State::Starting(store_path, options),
+        |mut state| async move {
+            loop {
+                state = match state {
+                    State::Starting(store_path, options) => {
+                        let (sender, receiver) = mpsc::unbounded();
+                        let cancel = Arc::new(AtomicBool::new(false));
+                        let guard = CancelOnDrop(cancel.clone());
+                        std::thread::spawn(move || {
+                            let threads =
+                                std::thread::available_parallelism().map_or(1, |n| n.get());
+                            let scanned = Scanner::new(&store_path, options).and_then(|scanner| {
+                                scanner.scan_parallel(&store_path, threads, &cancel, |batch| {
+                                    sender.unbounded_send(Action::ScanBatch(batch)).is_ok()
+                                })
+                            });
+                            let finished =
+                                Action::ScanFinished(scanned.map_err(|err| err.to_string()));
+                            let _ = sender.unbounded_send(finished);
+                        });
+                        State::Scanning(receiver, guard)
+                    }
+                    State::Scanning(mut receiver, guard) => match receiver.next().await {
+                        Some(action) => return (action, State::Scanning(receiver, guard)),
+                        None => State::Finished,
+                    },
+                    State::Finished => iced::futures::future::pending().await,
+                }
+            }
+        },
+    )
+}
+
+/** Render entry names with the characters the search matched in the highlight colour. Text
+ * can't be styled in parts, so every run of matched or unmatched characters is a text of its own.
+*/
+fn render_pass_entries<'a, 'b>(
+    matches: impl Iterator<Item = (&'b str, &'b [usize])>,
+    highlight: Color,
+) -> Vec<Row<'a, Action>> {
     matches
+        .map(|(name, indices)| {
+            let runs = highlight_runs(name, indices)
+                .into_iter()
+                .map(|(matched, run)| {
+                    let run = text(run);
+                    if matched {
+                        run.style(theme::Text::Color(highlight)).into()
+                    } else {
+                        run.into()
+                    }
+                });
+            Row::with_children(runs.collect())
+        })
+        .collect()
 }
 
-fn decrypt_pass_entry(entry_path: &Path) -> Result<String> {
-    use super::pgp::decrypt;
-    // TODO - load signing key at-start, based off settings for a key directory...
-    let key_path = PathBuf::from("/home/sam/code/personal/passrs/src/pgp/sample_key.asc");
-    let signing_key = decrypt::load_signing_key(&key_path)?;
-    let key_pass_pair = decrypt::KeyAndPassphrasePair::new("sample", &signing_key);
+/// Split a name into runs of characters which are all matched, or all not, by the indices.
+fn highlight_runs(name: &str, indices: &[usize]) -> Vec<(bool, String)> {
+    let mut runs: Vec<(bool, String)> = vec![];
+    for (idx, ch) in name.chars().enumerate() {
+        let matched = indices.binary_search(&idx).is_ok();
+        match runs.last_mut() {
+            Some((run_matched, run)) if *run_matched == matched => run.push(ch),
+            _ => runs.push((matched, ch.to_string())),
+        }
+    }
+    runs
+}
 
-    let message = decrypt::deserialise_message(entry_path)?;
-    let decrypted_message = decrypt::decrypt_message(message, &[key_pass_pair])?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(decrypted_message)
+    #[test]
+    fn test_highlight_runs() {
+        assert_eq!(
+            highlight_runs("w/welp", &[0, 2, 3]),
+            [
+                (true, "w".to_string()),
+                (false, "/".to_string()),
+                (true, "we".to_string()),
+                (false, "lp".to_string()),
+            ]
+        );
+        assert_eq!(
+            highlight_runs("ünï", &[1]),
+            [
+                (false, "ü".to_string()),
+                (true, "n".to_string()),
+                (false, "ï".to_string()),
+            ]
+        );
+        assert_eq!(highlight_runs("foo", &[]), [(false, "foo".to_string())]);
+        assert!(highlight_runs("", &[]).is_empty());
+    }
 }
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use iced::widget::{button, column, row, text, text_input};
+use iced::{Element, Length};
+
+use zeroize::Zeroizing;
+
+use super::Action;
+
+/// Wrong passphrases allowed before the prompt gives up.
+pub const MAX_PASSPHRASE_ATTEMPTS: u32 = 3;
+
+/** State for the masked passphrase input, shown when a recipient key of the entry is locked.
+*/
+pub struct PassphrasePrompt {
+    /// Entry to decrypt once the key is unlocked.
+    pub entry: PathBuf,
+    pub entry_name: String,
+    /// Hex key ID of the locked key.
+    pub key_id: String,
+    /// User ID of the locked key, for display.
+    pub key_user: String,
+    pub input: Zeroizing<String>,
+    pub attempts: u32,
+}
+
+impl PassphrasePrompt {
+    pub fn new(entry: PathBuf, entry_name: String, key_id: String, key_user: String) -> Self {
+        Self {
+            entry,
+            entry_name,
+            key_id,
+            key_user,
+            input: Zeroizing::new(String::new()),
+            attempts: 0,
+        }
+    }
+
+    /// Whether the last permitted attempt has been used up.
+    pub fn exhausted(&self) -> bool {
+        self.attempts >= MAX_PASSPHRASE_ATTEMPTS
+    }
+
+    pub fn view(&self) -> Element<'_, Action> {
+        let heading = text(format!(
+            "Passphrase needed to decrypt '{}'",
+            self.entry_name
+        ));
+        let key = text(format!("Key {} ({})", self.key_user, self.key_id));
+
+        let input = text_input("Passphrase...", self.input.as_str())
+            .on_input(|input| Action::PassphraseInput(Zeroizing::new(input)))
+            .on_submit(Action::PassphraseSubmit)
+            .password()
+            .padding(2);
+
+        let attempts = if self.attempts > 0 {
+            text(format!(
+                "Incorrect passphrase, attempt {} of {}",
+                self.attempts, MAX_PASSPHRASE_ATTEMPTS
+            ))
+        } else {
+            text("")
+        };
+
+        let buttons = row![
+            button("Unlock").on_press(Action::PassphraseSubmit),
+            button("Cancel").on_press(Action::PassphraseCancel),
+        ]
+        .spacing(4);
+
+        column![heading, key, input, attempts, buttons]
+            .spacing(4)
+            .padding(8)
+            .width(Length::Fill)
+            .into()
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::{Component, Path, PathBuf};
+
+use iced::widget::{button, column, row, text, text_input};
+use iced::{Element, Length};
+
+use super::Action;
+use passrs::Reencryption;
+
+/** State for re-encrypting a folder to a new set of recipients.
+*/
+pub struct ReencryptDialog {
+    /// Folder relative to the store root; empty for the whole store.
+    pub folder: String,
+    /// New recipients, separated by commas or whitespace.
+    pub recipients: String,
+    /// Who the folder currently encrypts to.
+    pub current: String,
+    pub running: Option<Reencryption>,
+    pub status: Option<String>,
+}
+
+impl ReencryptDialog {
+    pub fn new(current: String) -> Self {
+        Self {
+            folder: String::new(),
+            recipients: String::new(),
+            current,
+            running: None,
+            status: None,
+        }
+    }
+
+    pub fn recipient_ids(&self) -> Vec<String> {
+        self.recipients
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /** The folder to re-encrypt, checking it stays inside the store.
+     */
+    pub fn folder_path(&self, store_path: &Path) -> Result<PathBuf, String> {
+        let folder = self.folder.trim().trim_matches('/');
+        if !Path::new(folder)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(format!("'{}' is not a folder in the store", folder));
+        }
+        Ok(store_path.join(folder))
+    }
+
+    pub fn view(&self) -> Element<'_, Action> {
+        let mut folder = text_input("Folder, empty for the whole store", &self.folder).padding(2);
+        let mut recipients = text_input(
+            "New recipients: key IDs, fingerprints or emails",
+            &self.recipients,
+        )
+        .padding(2);
+        let mut start = button("Re-encrypt");
+        if self.running.is_none() {
+            folder = folder.on_input(Action::ReencryptFolder);
+            recipients = recipients
+                .on_input(Action::ReencryptRecipients)
+                .on_submit(Action::ReencryptStart);
+            start = start.on_press(Action::ReencryptStart);
+        }
+
+        // Cancelling part way is safe, nothing is written until every entry is ready.
+        let close = if self.running.is_some() {
+            "Cancel"
+        } else {
+            "Close"
+        };
+        let buttons = row![start, button(close).on_press(Action::ReencryptClose)].spacing(4);
+
+        column![
+            text("Re-encrypt folder").size(24),
+            folder,
+            text(format!("Currently encrypts to: {}", self.current)),
+            recipients,
+            text(self.status.as_deref().unwrap_or_default()),
+            buttons,
+        ]
+        .spacing(4)
+        .padding(8)
+        .width(Length::Fill)
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recipients_and_folder() {
+        let mut dialog = ReencryptDialog::new(String::new());
+        dialog.recipients = "alice@example.com, 0xF711232219DF6593\nbob@example.com".to_string();
+        assert_eq!(
+            dialog.recipient_ids(),
+            vec!["alice@example.com", "0xF711232219DF6593", "bob@example.com"]
+        );
+
+        let store = Path::new("/store");
+        assert_eq!(dialog.folder_path(store), Ok(PathBuf::from("/store/")));
+        dialog.folder = "team/shared/".to_string();
+        assert_eq!(
+            dialog.folder_path(store),
+            Ok(PathBuf::from("/store/team/shared"))
+        );
+        dialog.folder = "../elsewhere".to_string();
+        assert!(dialog.folder_path(store).is_err());
+    }
+}
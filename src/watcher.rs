@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+
+use crate::errors::Error;
+use crate::pass_scanner::{Scanner, StoreEntry, IGNORE_FILE};
+use crate::pgp::recipients::GPG_ID_FILE;
+
+/** A change to the store, as seen by a `StoreWatcher`.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreChange {
+    /// An entry, folder or `.gpg-id` was created, moved in, or rewritten.
+    Added(PathBuf),
+    /// An entry, folder or `.gpg-id` was deleted or moved away.
+    Removed(PathBuf),
+    /// Events were lost, or `.passrsignore` changed, so the whole store has to be scanned again.
+    Rescan,
+}
+
+/** Watches every folder of a store with inotify, reporting changes to entries. Folders the
+ * scanner skips aren't watched.
+*/
+pub struct StoreWatcher {
+    scanner: Scanner,
+    inotify: Inotify,
+    watches: HashMap<WatchDescriptor, PathBuf>,
+}
+
+impl StoreWatcher {
+    pub fn new(scanner: Scanner) -> Result<Self, Error> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+        let store_path = scanner.store_path().to_path_buf();
+        let mut watcher = Self {
+            scanner,
+            inotify,
+            watches: HashMap::new(),
+        };
+        watcher.watch_tree(&store_path)?;
+        debug!(
+            "Watching {} folders of {}",
+            watcher.watches.len(),
+            store_path.display()
+        );
+        Ok(watcher)
+    }
+
+    /** Wait for changes, then keep collecting them until none arrive for `debounce`, so a burst
+     * such as a checkout comes back as one batch. Repeated changes to a path are merged.
+     */
+    pub fn wait(&mut self, debounce: Duration) -> Result<Vec<StoreChange>, Error> {
+        let mut changes = vec![];
+        self.poll(None)?;
+        loop {
+            self.read_changes(&mut changes)?;
+            if !self.poll(Some(debounce))? {
+                break;
+            }
+        }
+        Ok(changes)
+    }
+
+    /** Watch a folder and every folder below it.
+     */
+    fn watch_tree(&mut self, folder: &Path) -> Result<(), Error> {
+        let flags = AddWatchFlags::IN_CREATE
+            | AddWatchFlags::IN_DELETE
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_ONLYDIR;
+
+        for folder in self.scanner.collect_folders(folder)? {
+            let watch = self.inotify.add_watch(&folder, flags)?;
+            self.watches.insert(watch, folder);
+        }
+        Ok(())
+    }
+
+    /** Stop watching a folder that went away, and the folders below it.
+     */
+    fn unwatch_tree(&mut self, folder: &Path) {
+        let gone: Vec<WatchDescriptor> = self
+            .watches
+            .iter()
+            .filter(|(_, path)| path.starts_with(folder))
+            .map(|(watch, _)| *watch)
+            .collect();
+        for watch in gone {
+            self.watches.remove(&watch);
+            // The kernel drops the watch itself once the folder is deleted.
+            let _ = self.inotify.rm_watch(watch);
+        }
+    }
+
+    /// Wait for events to read, returning false if the timeout passed first.
+    fn poll(&self, timeout: Option<Duration>) -> Result<bool, Error> {
+        let timeout = timeout.map_or(-1, |timeout| timeout.as_millis() as i32);
+        let mut fds = [PollFd::new(self.inotify.as_raw_fd(), PollFlags::POLLIN)];
+        loop {
+            match poll(&mut fds, timeout) {
+                Ok(ready) => return Ok(ready > 0),
+                Err(Errno::EINTR) => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    fn read_changes(&mut self, changes: &mut Vec<StoreChange>) -> Result<(), Error> {
+        loop {
+            let events = match self.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+            for event in events {
+                if let Some(change) = self.change_for(event)? {
+                    merge_change(changes, change);
+                }
+            }
+        }
+    }
+
+    fn change_for(&mut self, event: InotifyEvent) -> Result<Option<StoreChange>, Error> {
+        if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+            warn!("Missed changes to the store, it will be rescanned");
+            return self.rescan();
+        }
+        if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+            self.watches.remove(&event.wd);
+            return Ok(None);
+        }
+
+        let (Some(folder), Some(name)) = (self.watches.get(&event.wd), event.name) else {
+            return Ok(None);
+        };
+        let path = folder.join(name);
+        let is_dir = event.mask.contains(AddWatchFlags::IN_ISDIR);
+        if path == self.scanner.store_path().join(IGNORE_FILE) {
+            info!("{} changed, rescanning the store", IGNORE_FILE);
+            return self.rescan();
+        }
+        if self.scanner.is_ignored(&path) || !(is_dir || is_store_file(&path)) {
+            return Ok(None);
+        }
+
+        let added = AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO;
+        let removed = AddWatchFlags::IN_DELETE | AddWatchFlags::IN_MOVED_FROM;
+        if event.mask.intersects(added) {
+            if is_dir {
+                // The folder may already be gone again, which its removal event will report.
+                if let Err(err) = self.watch_tree(&path) {
+                    debug!("Not watching {}: {}", path.display(), err);
+                }
+            }
+            Ok(Some(StoreChange::Added(path)))
+        } else if event.mask.intersects(removed) {
+            if is_dir {
+                self.unwatch_tree(&path);
+            }
+            Ok(Some(StoreChange::Removed(path)))
+        } else if event.mask.contains(AddWatchFlags::IN_CLOSE_WRITE) {
+            Ok(Some(StoreChange::Added(path)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl StoreWatcher {
+    /** Start over with the store's current ignore rules, watching any folder that was missed.
+     */
+    fn rescan(&mut self) -> Result<Option<StoreChange>, Error> {
+        let store_path = self.scanner.store_path().to_path_buf();
+        self.scanner = Scanner::new(&store_path, self.scanner.options().clone())?;
+        let stale: Vec<WatchDescriptor> = self
+            .watches
+            .iter()
+            .filter(|(_, folder)| self.scanner.is_ignored(folder))
+            .map(|(watch, _)| *watch)
+            .collect();
+        for watch in stale {
+            self.watches.remove(&watch);
+            let _ = self.inotify.rm_watch(watch);
+        }
+        self.watch_tree(&store_path)?;
+        Ok(Some(StoreChange::Rescan))
+    }
+}
+
+impl Drop for StoreWatcher {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.inotify.as_raw_fd());
+    }
+}
+
+/// Whether a file can change the entry list: an entry itself, the `.gpg-id` naming its
+/// recipients, or the ignore file.
+fn is_store_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "gpg")
+        || path
+            .file_name()
+            .is_some_and(|name| name == GPG_ID_FILE || name == IGNORE_FILE)
+}
+
+/** Add a change to a batch, replacing an earlier change to the same path. A rescan makes any
+ * other change redundant.
+*/
+fn merge_change(changes: &mut Vec<StoreChange>, change: StoreChange) {
+    if changes.contains(&StoreChange::Rescan) {
+        return;
+    }
+    if change == StoreChange::Rescan {
+        changes.clear();
+    } else {
+        changes.retain(|earlier| match (earlier, &change) {
+            (
+                StoreChange::Added(earlier) | StoreChange::Removed(earlier),
+                StoreChange::Added(path) | StoreChange::Removed(path),
+            ) => earlier != path,
+            _ => true,
+        });
+    }
+    changes.push(change);
+}
+
+/** Bring entries, kept in name order, up to date with a batch of changes to the store.
+*/
+pub fn apply_changes(
+    entries: &mut Vec<StoreEntry>,
+    scanner: &Scanner,
+    changes: &[StoreChange],
+) -> Result<(), Error> {
+    let store_path = scanner.store_path();
+    for change in changes {
+        match change {
+            StoreChange::Rescan => {
+                *entries = scanner.collect_store_entries(store_path)?;
+            }
+            StoreChange::Added(path) | StoreChange::Removed(path)
+                if path.file_name().is_some_and(|name| name == GPG_ID_FILE) =>
+            {
+                // Recipients changed for everything below the folder.
+                if let Some(folder) = path.parent() {
+                    rescan_folder(entries, scanner, folder)?;
+                }
+            }
+            StoreChange::Added(path) if scanner.is_ignored(path) => {}
+            StoreChange::Added(path) if path.is_dir() => {
+                rescan_folder(entries, scanner, path)?;
+            }
+            StoreChange::Added(path) => {
+                // A file already gone again is left to its removal.
+                let Ok(entry) = StoreEntry::new(path, store_path) else {
+                    continue;
+                };
+                match entries.iter_mut().find(|known| known.path == entry.path) {
+                    Some(known) => *known = entry,
+                    None => entries.push(entry),
+                }
+            }
+            StoreChange::Removed(path) => {
+                entries.retain(|entry| !entry.path.starts_with(path));
+            }
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(())
+}
+
+fn rescan_folder(
+    entries: &mut Vec<StoreEntry>,
+    scanner: &Scanner,
+    folder: &Path,
+) -> Result<(), Error> {
+    entries.retain(|entry| !entry.path.starts_with(folder));
+    if folder.is_dir() && !scanner.is_ignored(folder) {
+        entries.extend(scanner.collect_store_entries(folder)?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass_scanner::ScanOptions;
+    use crate::test_util::{init_logs, TmpTree};
+    use anyhow::Result;
+    use std::fs;
+
+    const DEBOUNCE: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn test_watch_store() -> Result<()> {
+        init_logs();
+        let tmp_tree = TmpTree::new();
+        let base = &tmp_tree.base_path;
+        let scanner = Scanner::new(base, ScanOptions::default())?;
+        let mut entries = scanner.collect_store_entries(base)?;
+        let mut watcher = StoreWatcher::new(scanner.clone())?;
+
+        fs::create_dir_all(base.join("web/mail"))?;
+        fs::write(base.join("web/mail/work.gpg"), "entry")?;
+        fs::rename(base.join("b/target.gpg"), base.join("b/renamed.gpg"))?;
+        fs::remove_file(base.join("a/target.gpg"))?;
+        fs::write(base.join("a/.target.gpg.tmp"), "scratch")?;
+        fs::create_dir_all(base.join(".git/objects"))?;
+        fs::write(base.join(".git/objects/ignored.gpg"), "object")?;
+
+        let changes = watcher.wait(DEBOUNCE)?;
+        assert!(changes.contains(&StoreChange::Removed(base.join("a/target.gpg"))));
+        assert!(changes.contains(&StoreChange::Removed(base.join("b/target.gpg"))));
+        assert!(changes.contains(&StoreChange::Added(base.join("b/renamed.gpg"))));
+        assert!(changes.iter().all(|change| match change {
+            StoreChange::Added(path) | StoreChange::Removed(path) =>
+                !path.to_string_lossy().contains(".tmp") && !path.starts_with(base.join(".git")),
+            StoreChange::Rescan => false,
+        }));
+
+        // Entries created under a new folder are picked up, whether or not they were seen.
+        apply_changes(&mut entries, &scanner, &changes)?;
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["b/renamed", "web/mail/work"]);
+
+        // New folders are watched too.
+        fs::write(base.join("web/mail/home.gpg"), "entry")?;
+        fs::write(base.join("web").join(GPG_ID_FILE), "f711232219df6593\n")?;
+        let changes = watcher.wait(DEBOUNCE)?;
+        apply_changes(&mut entries, &scanner, &changes)?;
+        assert_eq!(entries, scanner.collect_store_entries(base)?);
+        assert_eq!(entries[1].recipients_hint, ["f711232219df6593"]);
+
+        fs::remove_dir_all(base.join("web"))?;
+        let changes = watcher.wait(DEBOUNCE)?;
+        apply_changes(&mut entries, &scanner, &changes)?;
+        assert_eq!(entries, scanner.collect_store_entries(base)?);
+
+        // A new ignore file asks for a rescan, and stops the folders it ignores being watched.
+        fs::write(base.join(IGNORE_FILE), "b/\n")?;
+        assert_eq!(watcher.wait(DEBOUNCE)?, [StoreChange::Rescan]);
+        let scanner = Scanner::new(base, ScanOptions::default())?;
+        apply_changes(&mut entries, &scanner, &[StoreChange::Rescan])?;
+        assert!(entries.is_empty());
+        fs::write(base.join("b/ignored.gpg"), "entry")?;
+        fs::write(base.join("a/seen.gpg"), "entry")?;
+        assert_eq!(
+            watcher.wait(DEBOUNCE)?,
+            [StoreChange::Added(base.join("a/seen.gpg"))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_changes() {
+        let mut changes = vec![];
+        merge_change(&mut changes, StoreChange::Added("a.gpg".into()));
+        merge_change(&mut changes, StoreChange::Added("b.gpg".into()));
+        merge_change(&mut changes, StoreChange::Removed("a.gpg".into()));
+        assert_eq!(
+            changes,
+            [
+                StoreChange::Added("b.gpg".into()),
+                StoreChange::Removed("a.gpg".into())
+            ]
+        );
+
+        merge_change(&mut changes, StoreChange::Rescan);
+        merge_change(&mut changes, StoreChange::Added("c.gpg".into()));
+        assert_eq!(changes, [StoreChange::Rescan]);
+    }
+}